@@ -0,0 +1,125 @@
+use std::io;
+
+/// The code points of the bytes `0x80` to `0xBF` in Windows-1251. The bytes `0xC0` to `0xFF` are `А` (U+0410) to `я` (U+044F) in order, and the undefined byte `0x98` is mapped to U+0098.
+const WINDOWS_1251_HIGH: [u16; 64] = [
+    0x0402, 0x0403, 0x201A, 0x0453, 0x201E, 0x2026, 0x2020, 0x2021, 0x20AC, 0x2030, 0x0409, 0x2039,
+    0x040A, 0x040C, 0x040B, 0x040F, 0x0452, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x0098, 0x2122, 0x0459, 0x203A, 0x045A, 0x045C, 0x045B, 0x045F, 0x00A0, 0x040E, 0x045E, 0x0408,
+    0x00A4, 0x0490, 0x00A6, 0x00A7, 0x0401, 0x00A9, 0x0404, 0x00AB, 0x00AC, 0x00AD, 0x00AE, 0x0407,
+    0x00B0, 0x00B1, 0x0406, 0x0456, 0x0491, 0x00B5, 0x00B6, 0x00B7, 0x0451, 0x2116, 0x0454, 0x00BB,
+    0x0458, 0x0405, 0x0455, 0x0457,
+];
+
+/// The character encoding of the queries sent to a WHOIS server and of its responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Charset {
+    /// UTF-8. This is the default.
+    Utf8,
+    /// ISO-8859-1 (Latin-1). Every byte is mapped to the Unicode code point of the same value.
+    Iso8859_1,
+    /// Windows-1251, the Cyrillic Windows codepage.
+    Windows1251,
+    /// UTF-16 in little-endian byte order. A leading byte order mark is removed when decoding.
+    Utf16Le,
+    /// UTF-16 in big-endian byte order. A leading byte order mark is removed when decoding.
+    Utf16Be,
+}
+
+impl Charset {
+    /// Get a charset by its label, such as `"utf-8"`, `"iso-8859-1"`, `"latin1"`, `"windows-1251"` or `"utf-16le"`. Labels are matched case-insensitively. `"utf-16"` means UTF-16LE.
+    pub fn from_label<S: AsRef<str>>(label: S) -> Option<Charset> {
+        match label.as_ref().trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Charset::Utf8),
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "latin-1" | "l1" => {
+                Some(Charset::Iso8859_1)
+            },
+            "windows-1251" | "cp1251" | "x-cp1251" => Some(Charset::Windows1251),
+            "utf-16le" | "utf-16" => Some(Charset::Utf16Le),
+            "utf-16be" => Some(Charset::Utf16Be),
+            _ => None,
+        }
+    }
+
+    /// Encode a string. A character which cannot be represented in this charset causes an error.
+    pub(crate) fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Charset::Utf8 => Ok(text.as_bytes().to_vec()),
+            Charset::Iso8859_1 => text
+                .chars()
+                .map(|c| u8::try_from(u32::from(c)).map_err(|_| unencodable(c, "ISO-8859-1")))
+                .collect(),
+            Charset::Windows1251 => text
+                .chars()
+                .map(|c| {
+                    if c.is_ascii() {
+                        return Ok(c as u8);
+                    }
+
+                    let code_point = u32::from(c);
+
+                    if (0x0410..=0x044F).contains(&code_point) {
+                        return Ok((code_point - 0x0410) as u8 + 0xC0);
+                    }
+
+                    WINDOWS_1251_HIGH
+                        .iter()
+                        .position(|&high| u32::from(high) == code_point)
+                        .map(|index| index as u8 + 0x80)
+                        .ok_or_else(|| unencodable(c, "Windows-1251"))
+                })
+                .collect(),
+            Charset::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Charset::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        }
+    }
+
+    /// Decode bytes into a string.
+    pub(crate) fn decode(self, data: Vec<u8>) -> io::Result<String> {
+        match self {
+            Charset::Utf8 => String::from_utf8(data)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+            Charset::Iso8859_1 => Ok(data.into_iter().map(char::from).collect()),
+            Charset::Windows1251 => Ok(data
+                .into_iter()
+                .map(|b| match b {
+                    0x00..=0x7F => char::from(b),
+                    0x80..=0xBF => {
+                        char::from_u32(u32::from(WINDOWS_1251_HIGH[usize::from(b - 0x80)]))
+                            .expect("the table has only valid code points")
+                    },
+                    0xC0..=0xFF => char::from_u32(0x0410 + u32::from(b - 0xC0))
+                        .expect("Cyrillic letters are valid code points"),
+                })
+                .collect()),
+            Charset::Utf16Le => decode_utf16(&data, u16::from_le_bytes),
+            Charset::Utf16Be => decode_utf16(&data, u16::from_be_bytes),
+        }
+    }
+}
+
+#[inline]
+fn unencodable(c: char, charset: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("The character {:?} cannot be encoded in {}.", c, charset),
+    )
+}
+
+/// Decode UTF-16 data whose code units are read by `from_bytes`, removing a leading byte order mark.
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> io::Result<String> {
+    if data.len() % 2 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The UTF-16 data has an odd number of bytes.",
+        ));
+    }
+
+    let mut units: Vec<u16> =
+        data.chunks_exact(2).map(|unit| from_bytes([unit[0], unit[1]])).collect();
+
+    if units.first() == Some(&0xFEFF) {
+        units.remove(0);
+    }
+
+    String::from_utf16(&units).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
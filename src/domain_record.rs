@@ -0,0 +1,91 @@
+use crate::parse_key_values;
+
+const DOMAIN_KEYS: [&str; 2] = ["Domain Name", "domain"];
+const REGISTRAR_KEYS: [&str; 1] = ["Registrar"];
+const CREATION_DATE_KEYS: [&str; 3] = ["Creation Date", "created", "Registered on"];
+const EXPIRATION_DATE_KEYS: [&str; 5] = [
+    "Registry Expiry Date",
+    "Registrar Registration Expiration Date",
+    "Expiry Date",
+    "expires",
+    "paid-till",
+];
+const UPDATED_DATE_KEYS: [&str; 3] = ["Updated Date", "last-update", "changed"];
+const NAME_SERVER_KEYS: [&str; 2] = ["Name Server", "nserver"];
+const STATUS_KEYS: [&str; 3] = ["Domain Status", "status", "state"];
+
+/// The common fields of a domain WHOIS response (gTLD registries and registrars, and ccTLD registries such as AFNIC).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainRecord {
+    /// The domain, e.g. `EXAMPLE.COM` (`Domain Name` or `domain`).
+    pub domain:          Option<String>,
+    /// The name of the registrar (`Registrar`).
+    pub registrar:       Option<String>,
+    /// The creation date (`Creation Date`, `created` or `Registered on`).
+    pub creation_date:   Option<String>,
+    /// The expiration date (`Registry Expiry Date`, `Registrar Registration Expiration Date`, `Expiry Date`, `expires` or `paid-till`).
+    pub expiration_date: Option<String>,
+    /// The date of the last update (`Updated Date`, `last-update` or `changed`).
+    pub updated_date:    Option<String>,
+    /// The name servers, in the order that they are listed (`Name Server` or `nserver`).
+    pub name_servers:    Vec<String>,
+    /// The statuses, e.g. `clientTransferProhibited https://icann.org/epp#clientTransferProhibited` (`Domain Status`, `status` or `state`).
+    pub statuses:        Vec<String>,
+    /// The raw response body.
+    pub body:            String,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DomainRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut record = serializer.serialize_struct("DomainRecord", 8)?;
+
+        record.serialize_field("domain", &self.domain)?;
+        record.serialize_field("registrar", &self.registrar)?;
+        record.serialize_field("creation_date", &self.creation_date)?;
+        record.serialize_field("expiration_date", &self.expiration_date)?;
+        record.serialize_field("updated_date", &self.updated_date)?;
+        record.serialize_field("name_servers", &self.name_servers)?;
+        record.serialize_field("statuses", &self.statuses)?;
+        record.serialize_field("body", &self.body)?;
+
+        record.end()
+    }
+}
+
+/// Extract the common fields of a domain WHOIS response body. Keys are matched case-insensitively, the first non-empty value of the most preferred key is used for a single-valued field, and a missing field is **None** (or empty).
+pub fn parse_domain_record<S: Into<String>>(body: S) -> DomainRecord {
+    let body = body.into();
+
+    let pairs = parse_key_values(&body);
+
+    let find = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            pairs
+                .iter()
+                .find(|(k, v)| k.eq_ignore_ascii_case(key) && !v.is_empty())
+                .map(|(_, v)| v.clone())
+        })
+    };
+
+    let find_all = |keys: &[&str]| {
+        pairs
+            .iter()
+            .filter(|(k, v)| keys.iter().any(|key| k.eq_ignore_ascii_case(key)) && !v.is_empty())
+            .map(|(_, v)| v.clone())
+            .collect()
+    };
+
+    DomainRecord {
+        domain: find(&DOMAIN_KEYS),
+        registrar: find(&REGISTRAR_KEYS),
+        creation_date: find(&CREATION_DATE_KEYS),
+        expiration_date: find(&EXPIRATION_DATE_KEYS),
+        updated_date: find(&UPDATED_DATE_KEYS),
+        name_servers: find_all(&NAME_SERVER_KEYS),
+        statuses: find_all(&STATUS_KEYS),
+        body,
+    }
+}
@@ -0,0 +1,428 @@
+use std::io;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] =
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which the code lengths of the code length alphabet are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// The maximum size of a decompressed response. A larger one is rejected as a decompression bomb.
+const MAX_OUTPUT: usize = 16 * 1024 * 1024;
+
+const GZIP_FHCRC: u8 = 0x02;
+const GZIP_FEXTRA: u8 = 0x04;
+const GZIP_FNAME: u8 = 0x08;
+const GZIP_FCOMMENT: u8 = 0x10;
+const ZLIB_FDICT: u8 = 0x20;
+
+#[inline]
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the bits of a DEFLATE stream, starting from the least significant bit of each byte.
+struct BitReader<'a> {
+    data:     &'a [u8],
+    position: usize,
+    buffer:   u64,
+    count:    u8,
+}
+
+impl<'a> BitReader<'a> {
+    #[inline]
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Read `n` (at most 16) bits.
+    fn bits(&mut self, n: u8) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| invalid("The compressed data is truncated."))?;
+
+            self.position += 1;
+            self.buffer |= u64::from(byte) << self.count;
+            self.count += 8;
+        }
+
+        let value = (self.buffer & ((1 << n) - 1)) as u32;
+
+        self.buffer >>= n;
+        self.count -= n;
+
+        Ok(value)
+    }
+
+    /// Skip the rest of the current byte.
+    #[inline]
+    fn align(&mut self) {
+        let skip = self.count % 8;
+
+        self.buffer >>= skip;
+        self.count -= skip;
+    }
+
+    /// Get the data after the bytes which have been read.
+    #[inline]
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position - usize::from(self.count / 8)..]
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols ordered by their codes.
+struct Huffman {
+    counts:  [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Build the code from the code length of each symbol. A length of 0 means that the symbol is unused.
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+
+        counts[0] = 0;
+
+        let mut left = 1i32;
+
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+
+            if left < 0 {
+                return Err(invalid("The compressed data has an over-subscribed Huffman code."));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+
+        Ok(Huffman {
+            counts,
+            symbols,
+        })
+    }
+
+    /// Read a symbol, one bit at a time, because Huffman codes are stored starting from their most significant bit.
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+
+            let count = i32::from(count);
+
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("The compressed data has an invalid Huffman code."))
+    }
+}
+
+fn fixed_codes() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid("The compressed data has too many Huffman codes."));
+    }
+
+    let mut code_lengths = [0u8; 19];
+
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let total = literal_count + distance_count;
+    let mut lengths = vec![0u8; total];
+    let mut index = 0;
+
+    while index < total {
+        let symbol = code_length_code.decode(reader)?;
+
+        let (length, repeat) = match symbol {
+            0..=15 => {
+                lengths[index] = symbol as u8;
+                index += 1;
+
+                continue;
+            },
+            16 if index > 0 => (lengths[index - 1], 3 + reader.bits(2)? as usize),
+            17 => (0, 3 + reader.bits(3)? as usize),
+            18 => (0, 11 + reader.bits(7)? as usize),
+            _ => return Err(invalid("The compressed data has invalid code lengths.")),
+        };
+
+        if index + repeat > total {
+            return Err(invalid("The compressed data has too many code lengths."));
+        }
+
+        lengths[index..index + repeat].fill(length);
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(invalid("The compressed data has no end-of-block code."));
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+fn inflate_codes(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)?;
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = usize::from(symbol - 257);
+                let length =
+                    usize::from(LENGTH_BASE[index]) + reader.bits(LENGTH_EXTRA[index])? as usize;
+
+                let index = usize::from(distances.decode(reader)?);
+
+                if index >= DISTANCE_BASE.len() {
+                    return Err(invalid("The compressed data has an invalid distance code."));
+                }
+
+                let distance = usize::from(DISTANCE_BASE[index])
+                    + reader.bits(DISTANCE_EXTRA[index])? as usize;
+
+                if distance > out.len() {
+                    return Err(invalid("The compressed data refers to data before its start."));
+                }
+
+                // the copied bytes may overlap the ones being written, so they are copied one by one
+                let start = out.len() - distance;
+
+                for i in start..start + length {
+                    out.push(out[i]);
+                }
+            },
+            _ => return Err(invalid("The compressed data has an invalid length code.")),
+        }
+
+        if out.len() > MAX_OUTPUT {
+            return Err(invalid("The decompressed data is too large."));
+        }
+    }
+}
+
+/// Decompress a raw DEFLATE (RFC 1951) stream. The reader is left after the last block.
+fn inflate(reader: &mut BitReader) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+
+                let length = reader.bits(16)?;
+
+                if length != !reader.bits(16)? & 0xFFFF {
+                    return Err(invalid("The compressed data has a corrupt stored block."));
+                }
+
+                for _ in 0..length {
+                    out.push(reader.bits(8)? as u8);
+                }
+
+                if out.len() > MAX_OUTPUT {
+                    return Err(invalid("The decompressed data is too large."));
+                }
+            },
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+
+                inflate_codes(reader, &mut out, &literals, &distances)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+
+                inflate_codes(reader, &mut out, &literals, &distances)?;
+            },
+            _ => return Err(invalid("The compressed data has an invalid block type.")),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= u32::from(byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    b << 16 | a
+}
+
+/// Skip a zero-terminated field of a gzip header.
+fn skip_zero_terminated(data: &[u8], offset: usize) -> io::Result<usize> {
+    data.get(offset..)
+        .and_then(|rest| rest.iter().position(|&b| b == 0))
+        .map(|end| offset + end + 1)
+        .ok_or_else(|| invalid("The gzip header is truncated."))
+}
+
+fn gunzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 18 || data[2] != 8 {
+        return Err(invalid("The gzip data is truncated or uses an unknown compression method."));
+    }
+
+    let flags = data[3];
+    let mut offset = 10;
+
+    if flags & GZIP_FEXTRA != 0 {
+        let length = data
+            .get(offset..offset + 2)
+            .map(|length| usize::from(u16::from_le_bytes([length[0], length[1]])))
+            .ok_or_else(|| invalid("The gzip header is truncated."))?;
+
+        offset += 2 + length;
+    }
+
+    if flags & GZIP_FNAME != 0 {
+        offset = skip_zero_terminated(data, offset)?;
+    }
+
+    if flags & GZIP_FCOMMENT != 0 {
+        offset = skip_zero_terminated(data, offset)?;
+    }
+
+    if flags & GZIP_FHCRC != 0 {
+        offset += 2;
+    }
+
+    let mut reader =
+        BitReader::new(data.get(offset..).ok_or_else(|| invalid("The gzip header is truncated."))?);
+
+    let out = inflate(&mut reader)?;
+
+    let trailer = reader.rest();
+
+    if trailer.len() < 8 {
+        return Err(invalid("The gzip trailer is truncated."));
+    }
+
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+    if crc != crc32(&out) || size != out.len() as u32 {
+        return Err(invalid("The gzip checksum does not match."));
+    }
+
+    Ok(out)
+}
+
+fn unzlib(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data[1] & ZLIB_FDICT != 0 {
+        return Err(invalid("The zlib data needs a preset dictionary."));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+
+    let out = inflate(&mut reader)?;
+
+    let trailer = reader.rest();
+
+    if trailer.len() < 4
+        || u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != adler32(&out)
+    {
+        return Err(invalid("The zlib checksum does not match."));
+    }
+
+    Ok(out)
+}
+
+/// Decompress gzip (RFC 1952) or zlib (RFC 1950) data. Only the first member of a multi-member gzip file is decompressed.
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    match data {
+        [0x1F, 0x8B, ..] => gunzip(data),
+        [_, _, ..] => unzlib(data),
+        _ => Err(invalid("The compressed data is truncated.")),
+    }
+}
@@ -0,0 +1,53 @@
+use std::net::IpAddr;
+
+/// A network in CIDR notation, such as `193.0.0.0/8` or `2001:200::/23`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct IpCidr {
+    addr:   IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// Parse a CIDR string. A bare IP is treated as a single-address network.
+    pub(crate) fn parse(cidr: &str) -> Option<IpCidr> {
+        let (addr, prefix) = match cidr.trim().split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+            None => (cidr.trim().parse::<IpAddr>().ok()?, None),
+        };
+
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = prefix.unwrap_or(max_prefix);
+
+        if prefix > max_prefix {
+            return None;
+        }
+
+        Some(IpCidr {
+            addr,
+            prefix,
+        })
+    }
+
+    #[inline]
+    pub(crate) const fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Check whether the network contains the IP. An IPv4 network never contains an IPv6 address and vice versa.
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+
+                u32::from(network) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+
+                u128::from(network) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
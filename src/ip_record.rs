@@ -0,0 +1,98 @@
+use std::net::Ipv4Addr;
+
+use crate::parse_key_values;
+
+const RANGE_KEYS: [&str; 3] = ["NetRange", "inetnum", "inet6num"];
+const CIDR_KEYS: [&str; 2] = ["CIDR", "route"];
+const ORG_NAME_KEYS: [&str; 3] = ["OrgName", "org-name", "Organization"];
+const COUNTRY_KEYS: [&str; 1] = ["Country"];
+const ABUSE_EMAIL_KEYS: [&str; 2] = ["OrgAbuseEmail", "abuse-mailbox"];
+
+/// The common fields of an IP WHOIS response (ARIN, RIPE, APNIC, etc.).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpRecord {
+    /// The network range, e.g. `8.8.8.0 - 8.8.8.255` (`NetRange`, `inetnum` or `inet6num`).
+    pub range:       Option<String>,
+    /// The network in CIDR notation, e.g. `8.8.8.0/24` (`CIDR` or `route`). If it is absent, it is derived from the range when the range is already in CIDR notation (e.g. an `inet6num`) or is exactly one IPv4 prefix.
+    pub cidr:        Option<String>,
+    /// The name of the organization (`OrgName`, `org-name` or `Organization`).
+    pub org_name:    Option<String>,
+    /// The country code (`Country` or `country`).
+    pub country:     Option<String>,
+    /// The abuse contact email (`OrgAbuseEmail` or `abuse-mailbox`).
+    pub abuse_email: Option<String>,
+    /// The raw response body.
+    pub body:        String,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for IpRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut record = serializer.serialize_struct("IpRecord", 6)?;
+
+        record.serialize_field("range", &self.range)?;
+        record.serialize_field("cidr", &self.cidr)?;
+        record.serialize_field("org_name", &self.org_name)?;
+        record.serialize_field("country", &self.country)?;
+        record.serialize_field("abuse_email", &self.abuse_email)?;
+        record.serialize_field("body", &self.body)?;
+
+        record.end()
+    }
+}
+
+/// Convert an IPv4 range such as `193.0.0.0 - 193.0.7.255` to CIDR notation if it is exactly one prefix.
+fn ipv4_range_to_cidr(range: &str) -> Option<String> {
+    let (start, end) = range.split_once('-')?;
+
+    let start = u32::from(start.trim().parse::<Ipv4Addr>().ok()?);
+    let end = u32::from(end.trim().parse::<Ipv4Addr>().ok()?);
+
+    // the size of the range minus one must be a mask of trailing ones which the start is aligned to
+    let host_mask = end.checked_sub(start)?;
+
+    if host_mask & host_mask.wrapping_add(1) != 0 || start & host_mask != 0 {
+        return None;
+    }
+
+    Some(format!("{}/{}", Ipv4Addr::from(start), host_mask.leading_zeros()))
+}
+
+/// Extract the common fields of an IP WHOIS response body. Keys are matched case-insensitively, the first non-empty value of the most preferred key is used, and a missing field is **None**.
+pub fn parse_ip_record<S: Into<String>>(body: S) -> IpRecord {
+    let body = body.into();
+
+    let pairs = parse_key_values(&body);
+
+    let find = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            pairs
+                .iter()
+                .find(|(k, v)| k.eq_ignore_ascii_case(key) && !v.is_empty())
+                .map(|(_, v)| v.clone())
+        })
+    };
+
+    let range = find(&RANGE_KEYS);
+
+    let cidr = find(&CIDR_KEYS).or_else(|| {
+        let range = range.as_ref()?;
+
+        if range.contains('/') {
+            Some(range.clone())
+        } else {
+            ipv4_range_to_cidr(range)
+        }
+    });
+
+    IpRecord {
+        range,
+        cidr,
+        org_name: find(&ORG_NAME_KEYS),
+        country: find(&COUNTRY_KEYS),
+        abuse_email: find(&ABUSE_EMAIL_KEYS),
+        body,
+    }
+}
@@ -22,6 +22,26 @@ This is a simple example of **servers.json**.
 }
 ```
 
+The labels of a domain are trimmed from the left until a key in the list is matched (`www.magiclen.org` → `magiclen.org` → `org`). If nothing is matched, the `""` server is used as the default domain server. A key set to `null` marks its TLD as unsupported intentionally, so a lookup for it fails with `WhoIsError::UnsupportedTld` instead of falling through to the `""` server. The `ip` server in the `_` object is used for IP lookups. Other keys of the `_` object (e.g. `asn`) are loaded as servers for other object types, which can be got by the `object_server` method. `ip` can be omitted or set to `null` (and a list can have no `_` at all), then IP lookups fail fast unless `options.server` is set. `WhoIs::without_ip_server` creates such an instance without a list.
+
+In a `query` template, `$addr` is replaced with the target. The token can be changed for all templates of a `WhoIs` instance with the `set_query_placeholder` method, e.g. to `{query}`.
+
+The `_` object may also have an `ip_ranges` object whose keys are CIDR ranges, so that IP lookups for known ranges go straight to the regional registry instead of being referred by the `ip` server. The most specific range which contains the IP is used.
+
+```json
+{
+    "_": {
+        "ip": "whois.arin.net",
+        "ip_ranges": {
+            "193.0.0.0/8": "whois.ripe.net",
+            "2001:200::/23": "whois.apnic.net"
+        }
+    }
+}
+```
+
+Keys for IDN TLDs should be in their ASCII (`xn--`) form, e.g. `xn--p1ai` for `рф`. Unicode keys are converted to that form when the list is loaded, and an explicit `xn--` key takes precedence if both appear.
+
 Then, use the `from_path` (or `from_string` if your JSON data is in-memory) associated function to create a `WhoIs` instance.
 
 ```rust,ignore
@@ -40,6 +60,42 @@ let whois = WhoIs::from_path("/path/to/servers.json").unwrap();
 let result: String = whois.lookup(WhoIsLookupOptions::from_string("magiclen.org").unwrap()).unwrap();
 ```
 
+For a one-off lookup with the default options, the `lookup_str` method parses the target for you.
+
+```rust,ignore
+use whois_rust::WhoIs;
+
+let whois = WhoIs::from_path("/path/to/servers.json").unwrap();
+
+let result: String = whois.lookup_str("magiclen.org").unwrap();
+```
+
+To get the common fields instead of the raw body, use the `lookup_domain_record` or `lookup_ip_record` method. The raw body is kept in the `body` field of the record.
+
+```rust,ignore
+use whois_rust::WhoIs;
+
+let whois = WhoIs::from_path("/path/to/servers.json").unwrap();
+
+let record = whois.lookup_domain_record("magiclen.org").unwrap();
+
+println!("{:?} {:?}", record.registrar, record.expiration_date);
+```
+
+## Classifying Responses
+
+Use the `lookup_classified` method to get the response body together with a `ResponseClass` (`Found`, `NotFound`, `RateLimited` or `Unknown`) and the pattern which matched. The patterns are stored in a `ResponseClassifier` which can be replaced by the `set_classifier` method.
+
+```rust,ignore
+use whois_rust::{ResponseClass, WhoIs, WhoIsLookupOptions};
+
+let whois = WhoIs::from_path("/path/to/servers.json").unwrap();
+
+let (result, class, _pattern) = whois.lookup_classified(WhoIsLookupOptions::from_string("magiclen.org").unwrap()).unwrap();
+
+assert_eq!(ResponseClass::Found, class);
+```
+
 ## Asynchronous APIs
 
 You may want to use async APIs with your async runtime. This crate supports `tokio`, currently.
@@ -52,6 +108,68 @@ features = ["tokio"]
 
 After enabling the async feature, the `from_path_async` function and the `lookup_async` function are available.
 
+The `lookup_lines_async` method returns a `WhoIsLines` stream, which yields the lines of the response body as they arrive. Referrals are followed first, so only the response of the last server is yielded, and it is only streamed as it arrives when no referral will be followed from it, e.g. when `follow` is 0.
+
+The `lookup_blocking` method runs `lookup_async` on an internal runtime, so that a synchronous part of an async codebase can share the same `WhoIs` instance.
+
+## Comments in the Server List
+
+Enable the `jsonc` feature to use the `from_jsonc_path` and `from_jsonc_string` associated functions, which accept the list of WHOIS servers as JSONC, i.e. JSON with `//` and `/* */` comments and trailing commas.
+
+```json
+{
+    // the registry of .org
+    "org": "whois.pir.org",
+}
+```
+
+## Compressed Responses
+
+Enable the `compression` feature to decompress gzip or zlib compressed responses, which some HTTP-to-WHOIS gateways send. Without it, such a response causes an error which says that it is compressed.
+
+```toml
+[dependencies.whois-rust]
+version = "*"
+features = ["compression"]
+```
+
+## System Fallback
+
+Enable the `system-whois` feature and set the `system_fallback` option to run the `whois` command of the operating system when no server in the list matches the domain. Only the blocking `lookup`, `lookup_str`, `lookup_target` and `lookup_classified` methods use the fallback; the async ones and the ones returning details of the servers (`lookup_detailed` and `lookup_all`) always query a WHOIS server.
+
+```toml
+[dependencies.whois-rust]
+version = "*"
+features = ["system-whois"]
+```
+
+This spawns a process resolved via `PATH` with the privileges of the current process, so only enable it where the environment can be trusted. The command is not run through a shell.
+
+## Serialization
+
+Enable the `serde` feature to implement `serde::Serialize` for `WhoIsLookupResult`, `IpRecord` and `DomainRecord`, so that they can be emitted as JSON directly. Fields are serialized with their Rust names.
+
+```toml
+[dependencies.whois-rust]
+version = "*"
+features = ["serde"]
+```
+
+## Connection Pool
+
+A `WhoIsPool` wraps a `WhoIs` instance and keeps idle connections for each server, so that a synchronous service which queries the same servers repeatedly doesn't need to connect every time. Most WHOIS servers close the connection after a response, so a connection is only reused if the server keeps it open and every response ends with the `response_end` text.
+
+```rust,ignore
+use whois_rust::{WhoIs, WhoIsLookupOptions, WhoIsPool, WhoIsPoolOptions};
+
+let pool = WhoIsPool::new(WhoIs::from_path("/path/to/servers.json").unwrap(), WhoIsPoolOptions {
+    response_end: Some(String::from("\n\n\n")),
+    ..WhoIsPoolOptions::default()
+});
+
+let result = pool.lookup(WhoIsLookupOptions::from_string("magiclen.org").unwrap()).unwrap();
+```
+
 ## Testing
 
 ```bash
@@ -71,16 +189,55 @@ cargo test
 #[cfg(feature = "tokio")]
 pub extern crate tokio;
 
+mod charset;
+mod domain_record;
+#[cfg(feature = "compression")]
+mod inflate;
+mod ip_cidr;
+mod ip_record;
+mod proxy;
+#[cfg(feature = "jsonc")]
+mod relaxed_json;
+mod response_classifier;
+mod response_processing;
+mod rwhois_policy;
+mod server_list;
 mod target;
+mod target_kind;
 mod who_is;
 mod who_is_error;
+mod who_is_event;
 mod who_is_host;
+#[cfg(feature = "tokio")]
+mod who_is_lines;
 mod who_is_lookup_options;
+mod who_is_lookup_result;
+mod who_is_pool;
+mod who_is_pool_options;
 mod who_is_server_value;
-
+mod who_is_server_value_builder;
+mod who_is_swap;
+mod who_is_transport;
+
+pub use charset::*;
+pub use domain_record::*;
+pub use ip_record::*;
+pub use response_classifier::*;
+pub use response_processing::*;
+pub use rwhois_policy::*;
 pub use target::*;
+pub use target_kind::*;
 pub use who_is::*;
 pub use who_is_error::*;
+pub use who_is_event::*;
 pub use who_is_host::*;
+#[cfg(feature = "tokio")]
+pub use who_is_lines::*;
 pub use who_is_lookup_options::*;
+pub use who_is_lookup_result::*;
+pub use who_is_pool::*;
+pub use who_is_pool_options::*;
 pub use who_is_server_value::*;
+pub use who_is_server_value_builder::*;
+pub use who_is_swap::*;
+pub use who_is_transport::*;
@@ -0,0 +1,303 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const DEFAULT_SOCKS_PORT: u16 = 1080;
+const DEFAULT_HTTP_PORT: u16 = 80;
+const MAX_HTTP_RESPONSE_HEAD_LENGTH: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProxyKind {
+    /// SOCKS5 without authentication. The WHOIS host is resolved by the proxy.
+    Socks5,
+    /// An HTTP proxy which supports the `CONNECT` method.
+    Http,
+}
+
+/// A proxy read from the `ALL_PROXY` environment variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Proxy {
+    pub(crate) kind: ProxyKind,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+#[inline]
+fn env_var(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| env::var(name).ok()).filter(|value| !value.trim().is_empty())
+}
+
+/// Check whether `host` matches a `NO_PROXY` list. An entry matches the host itself and its subdomains (a leading `.` is ignored), and `*` matches every host.
+fn is_bypassed(no_proxy: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+
+    no_proxy.split(',').map(str::trim).filter(|entry| !entry.is_empty()).any(|entry| {
+        if entry == "*" {
+            return true;
+        }
+
+        let entry = entry.trim_start_matches('.').to_ascii_lowercase();
+
+        host == entry || host.ends_with(&format!(".{}", entry))
+    })
+}
+
+impl Proxy {
+    /// Parse a proxy URL such as `socks5://127.0.0.1:1080` or `http://proxy.example.com:3128`.
+    pub(crate) fn parse(url: &str) -> io::Result<Proxy> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+        let (scheme, rest) = url.trim().split_once("://").unwrap_or(("http", url.trim()));
+
+        let (kind, default_port) = match scheme.to_ascii_lowercase().as_str() {
+            "socks5" | "socks5h" => (ProxyKind::Socks5, DEFAULT_SOCKS_PORT),
+            "http" => (ProxyKind::Http, DEFAULT_HTTP_PORT),
+            _ => return Err(invalid("The scheme of the proxy is not supported.")),
+        };
+
+        let authority = rest.split('/').next().unwrap_or_default();
+
+        if authority.contains('@') {
+            return Err(invalid("Proxy authentication is not supported."));
+        }
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| invalid("The port of the proxy is invalid."))?)
+            },
+            _ => (authority, default_port),
+        };
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        if host.is_empty() {
+            return Err(invalid("The host of the proxy is empty."));
+        }
+
+        Ok(Proxy {
+            kind,
+            host: String::from(host),
+            port,
+        })
+    }
+
+    /// Get the proxy which should be used to connect to `host`, according to the `ALL_PROXY` (or `all_proxy`) and `NO_PROXY` (or `no_proxy`) environment variables.
+    pub(crate) fn from_env(host: &str) -> io::Result<Option<Proxy>> {
+        let url = match env_var(&["ALL_PROXY", "all_proxy"]) {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+
+        if let Some(no_proxy) = env_var(&["NO_PROXY", "no_proxy"]) {
+            if is_bypassed(&no_proxy, host) {
+                return Ok(None);
+            }
+        }
+
+        Self::parse(&url).map(Some)
+    }
+
+    fn socks5_connect_request(host: &str, port: u16) -> io::Result<Vec<u8>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        let mut request = vec![5, 1, 0];
+
+        match host.parse::<std::net::IpAddr>() {
+            Ok(std::net::IpAddr::V4(ip)) => {
+                request.push(1);
+                request.extend_from_slice(&ip.octets());
+            },
+            Ok(std::net::IpAddr::V6(ip)) => {
+                request.push(4);
+                request.extend_from_slice(&ip.octets());
+            },
+            Err(_) => {
+                let length = u8::try_from(host.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "The host is too long for SOCKS5.")
+                })?;
+
+                request.push(3);
+                request.push(length);
+                request.extend_from_slice(host.as_bytes());
+            },
+        }
+
+        request.extend_from_slice(&port.to_be_bytes());
+
+        Ok(request)
+    }
+
+    #[inline]
+    fn socks5_error(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, format!("SOCKS5 proxy: {}", message))
+    }
+
+    /// Check the header (`VER`, `REP`, `RSV`, `ATYP`) of a SOCKS5 reply.
+    fn socks5_check_reply(header: [u8; 4]) -> io::Result<()> {
+        if header[0] != 5 {
+            return Err(Self::socks5_error("invalid reply"));
+        }
+
+        if header[1] != 0 {
+            return Err(Self::socks5_error(&format!("the connection failed (code {})", header[1])));
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn http_connect_request(host: &str, port: u16) -> String {
+        let authority = if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", authority)
+    }
+
+    fn http_check_response(head: &[u8]) -> io::Result<()> {
+        let head = String::from_utf8_lossy(head);
+
+        let status = head.lines().next().unwrap_or_default();
+
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::Other, format!("HTTP proxy: {}", status))),
+        }
+    }
+
+    /// Ask the proxy, which `stream` is connected to, to open a tunnel to `host:port`.
+    pub(crate) fn handshake<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        match self.kind {
+            ProxyKind::Socks5 => {
+                stream.write_all(&[5, 1, 0])?;
+
+                let mut method = [0; 2];
+                stream.read_exact(&mut method)?;
+
+                if method != [5, 0] {
+                    return Err(Self::socks5_error("the proxy requires authentication"));
+                }
+
+                stream.write_all(&Self::socks5_connect_request(host, port)?)?;
+
+                let mut header = [0; 4];
+                stream.read_exact(&mut header)?;
+
+                Self::socks5_check_reply(header)?;
+
+                let length = match header[3] {
+                    1 => 4,
+                    4 => 16,
+                    3 => {
+                        let mut length = [0; 1];
+                        stream.read_exact(&mut length)?;
+
+                        usize::from(length[0])
+                    },
+                    _ => return Err(Self::socks5_error("invalid address type")),
+                };
+
+                // skip the bound address and port
+                let mut bound = vec![0; length + 2];
+                stream.read_exact(&mut bound)?;
+
+                Ok(())
+            },
+            ProxyKind::Http => {
+                stream.write_all(Self::http_connect_request(host, port).as_bytes())?;
+                stream.flush()?;
+
+                // read byte by byte so that no data after the response head is consumed
+                let mut head = Vec::new();
+                let mut byte = [0; 1];
+
+                while !head.ends_with(b"\r\n\r\n") {
+                    if head.len() > MAX_HTTP_RESPONSE_HEAD_LENGTH {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "HTTP proxy: the response head is too long",
+                        ));
+                    }
+
+                    stream.read_exact(&mut byte)?;
+                    head.push(byte[0]);
+                }
+
+                Self::http_check_response(&head)
+            },
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Ask the proxy, which `stream` is connected to, to open a tunnel to `host:port`.
+    pub(crate) async fn handshake_async<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: &mut S,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        match self.kind {
+            ProxyKind::Socks5 => {
+                stream.write_all(&[5, 1, 0]).await?;
+
+                let mut method = [0; 2];
+                stream.read_exact(&mut method).await?;
+
+                if method != [5, 0] {
+                    return Err(Self::socks5_error("the proxy requires authentication"));
+                }
+
+                stream.write_all(&Self::socks5_connect_request(host, port)?).await?;
+
+                let mut header = [0; 4];
+                stream.read_exact(&mut header).await?;
+
+                Self::socks5_check_reply(header)?;
+
+                let length = match header[3] {
+                    1 => 4,
+                    4 => 16,
+                    3 => usize::from(stream.read_u8().await?),
+                    _ => return Err(Self::socks5_error("invalid address type")),
+                };
+
+                // skip the bound address and port
+                let mut bound = vec![0; length + 2];
+                stream.read_exact(&mut bound).await?;
+
+                Ok(())
+            },
+            ProxyKind::Http => {
+                stream.write_all(Self::http_connect_request(host, port).as_bytes()).await?;
+                stream.flush().await?;
+
+                // read byte by byte so that no data after the response head is consumed
+                let mut head = Vec::new();
+
+                while !head.ends_with(b"\r\n\r\n") {
+                    if head.len() > MAX_HTTP_RESPONSE_HEAD_LENGTH {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "HTTP proxy: the response head is too long",
+                        ));
+                    }
+
+                    head.push(stream.read_u8().await?);
+                }
+
+                Self::http_check_response(&head)
+            },
+        }
+    }
+}
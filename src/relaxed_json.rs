@@ -0,0 +1,108 @@
+/// Remove `//` line comments, `/* */` block comments and trailing commas from JSON text, so that it can be parsed by `serde_json`. Newlines inside comments are kept for the line numbers in error messages.
+pub(crate) fn strip_comments_and_trailing_commas(text: &str) -> String {
+    remove_trailing_commas(&remove_comments(text))
+}
+
+fn remove_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            result.push(c);
+
+            match c {
+                '\\' => {
+                    if let Some(c) = chars.next() {
+                        result.push(c);
+                    }
+                },
+                '"' => in_string = false,
+                _ => (),
+            }
+
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                result.push(c);
+            },
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        result.push(c);
+                        break;
+                    }
+                }
+            },
+            ('/', Some('*')) => {
+                chars.next();
+
+                let mut previous = '\0';
+
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+
+                    if c == '\n' {
+                        result.push(c);
+                    }
+
+                    previous = c;
+                }
+
+                result.push(' ');
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn remove_trailing_commas(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    let mut chars = text.char_indices();
+    let mut in_string = false;
+
+    while let Some((index, c)) = chars.next() {
+        if in_string {
+            result.push(c);
+
+            match c {
+                '\\' => {
+                    if let Some((_, c)) = chars.next() {
+                        result.push(c);
+                    }
+                },
+                '"' => in_string = false,
+                _ => (),
+            }
+
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                result.push(c);
+            },
+            ',' => {
+                let next = text[index + 1..].trim_start().chars().next();
+
+                if !matches!(next, Some('}') | Some(']')) {
+                    result.push(c);
+                }
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
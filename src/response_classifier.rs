@@ -0,0 +1,200 @@
+use serde_json::{Map, Value};
+
+use crate::WhoIsError;
+
+const DEFAULT_NOT_FOUND_PATTERNS: [&str; 11] = [
+    "No match for",
+    "NOT FOUND",
+    "No entries found",
+    "No Data Found",
+    "Domain not found",
+    "No matching record",
+    "The queried object does not exist",
+    "Object does not exist",
+    "Status: free",
+    "Status: AVAILABLE",
+    "is available for registration",
+];
+
+const DEFAULT_RATE_LIMITED_PATTERNS: [&str; 7] = [
+    "Query rate limit exceeded",
+    "WHOIS LIMIT EXCEEDED",
+    "Too many queries",
+    "Too many requests",
+    "exceeded the maximum allowable number",
+    "Number of allowed queries exceeded",
+    "%ERROR:201: access denied",
+];
+
+/// The classification of a WHOIS response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseClass {
+    /// The response looks like a real record.
+    Found,
+    /// The response says that the object does not exist.
+    NotFound,
+    /// The response says that the client has sent too many queries.
+    RateLimited,
+    /// The response is empty.
+    Unknown,
+}
+
+/// The `ResponseClassifier` structure stores the patterns used to classify WHOIS response bodies. The patterns are matched case-insensitively as substrings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseClassifier {
+    not_found_patterns:              Vec<String>,
+    rate_limited_patterns:           Vec<String>,
+    /// The patterns lowercased once here, so that classifying a body doesn't lowercase them again.
+    lowercase_not_found_patterns:    Vec<String>,
+    lowercase_rate_limited_patterns: Vec<String>,
+}
+
+impl Default for ResponseClassifier {
+    #[inline]
+    fn default() -> Self {
+        ResponseClassifier::new(
+            DEFAULT_NOT_FOUND_PATTERNS.iter().map(|s| String::from(*s)).collect(),
+            DEFAULT_RATE_LIMITED_PATTERNS.iter().map(|s| String::from(*s)).collect(),
+        )
+    }
+}
+
+impl ResponseClassifier {
+    /// Create a `ResponseClassifier` instance with patterns which indicate that the object does not exist and patterns which indicate that the server is throttling the client.
+    #[inline]
+    pub fn new(
+        not_found_patterns: Vec<String>,
+        rate_limited_patterns: Vec<String>,
+    ) -> ResponseClassifier {
+        ResponseClassifier {
+            lowercase_not_found_patterns: lowercase_all(&not_found_patterns),
+            lowercase_rate_limited_patterns: lowercase_all(&rate_limited_patterns),
+            not_found_patterns,
+            rate_limited_patterns,
+        }
+    }
+
+    /// Create a `ResponseClassifier` instance which doesn't have any pattern.
+    #[inline]
+    pub fn empty() -> ResponseClassifier {
+        ResponseClassifier::new(Vec::new(), Vec::new())
+    }
+
+    /// Read the patterns (JSON data) from a string to create a `ResponseClassifier` instance. The JSON data is an object which may have a `not_found` array and a `rate_limited` array. A missing array falls back to the default patterns.
+    #[inline]
+    pub fn from_string<S: AsRef<str>>(string: S) -> Result<ResponseClassifier, WhoIsError> {
+        let string = string.as_ref();
+
+        let value: Value = serde_json::from_str(string)?;
+
+        Self::from_value(&value)
+    }
+
+    /// Create a `ResponseClassifier` instance from a JSON value. See `from_string`.
+    pub fn from_value(value: &Value) -> Result<ResponseClassifier, WhoIsError> {
+        match value {
+            Value::Object(map) => {
+                let not_found_patterns = match Self::patterns_from_map(map, "not_found")? {
+                    Some(patterns) => patterns,
+                    None => DEFAULT_NOT_FOUND_PATTERNS.iter().map(|s| String::from(*s)).collect(),
+                };
+
+                let rate_limited_patterns = match Self::patterns_from_map(map, "rate_limited")? {
+                    Some(patterns) => patterns,
+                    None => {
+                        DEFAULT_RATE_LIMITED_PATTERNS.iter().map(|s| String::from(*s)).collect()
+                    },
+                };
+
+                Ok(ResponseClassifier::new(not_found_patterns, rate_limited_patterns))
+            },
+            _ => Err(WhoIsError::MapError("The classifier patterns are not an object.")),
+        }
+    }
+
+    fn patterns_from_map(
+        map: &Map<String, Value>,
+        key: &str,
+    ) -> Result<Option<Vec<String>>, WhoIsError> {
+        match map.get(key) {
+            Some(Value::Array(array)) => {
+                let mut patterns = Vec::with_capacity(array.len());
+
+                for pattern in array {
+                    if let Value::String(pattern) = pattern {
+                        patterns.push(String::from(pattern));
+                    } else {
+                        return Err(WhoIsError::MapError(
+                            "The classifier patterns contain a value which is not a string.",
+                        ));
+                    }
+                }
+
+                Ok(Some(patterns))
+            },
+            Some(_) => Err(WhoIsError::MapError("The classifier patterns are not an array.")),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ResponseClassifier {
+    /// Get the patterns which indicate that the object does not exist.
+    #[inline]
+    pub fn not_found_patterns(&self) -> &[String] {
+        &self.not_found_patterns
+    }
+
+    /// Get the patterns which indicate that the server is throttling the client.
+    #[inline]
+    pub fn rate_limited_patterns(&self) -> &[String] {
+        &self.rate_limited_patterns
+    }
+
+    /// Classify a WHOIS response body. Rate-limit patterns take precedence over not-found patterns.
+    #[inline]
+    pub fn classify<S: AsRef<str>>(&self, body: S) -> ResponseClass {
+        self.classify_with_pattern(body).0
+    }
+
+    /// Classify a WHOIS response body like `classify`, and get the pattern which matched, so that a pattern list can be tuned against real responses. The pattern is **None** for `Found` and `Unknown`.
+    pub fn classify_with_pattern<S: AsRef<str>>(&self, body: S) -> (ResponseClass, Option<&str>) {
+        let body = body.as_ref();
+
+        if body.trim().is_empty() {
+            return (ResponseClass::Unknown, None);
+        }
+
+        let body = body.to_lowercase();
+
+        if let Some(pattern) =
+            find_match(&self.rate_limited_patterns, &self.lowercase_rate_limited_patterns, &body)
+        {
+            (ResponseClass::RateLimited, Some(pattern))
+        } else if let Some(pattern) =
+            find_match(&self.not_found_patterns, &self.lowercase_not_found_patterns, &body)
+        {
+            (ResponseClass::NotFound, Some(pattern))
+        } else {
+            (ResponseClass::Found, None)
+        }
+    }
+}
+
+#[inline]
+fn lowercase_all(patterns: &[String]) -> Vec<String> {
+    patterns.iter().map(|pattern| pattern.to_lowercase()).collect()
+}
+
+/// Find the first pattern whose lowercased form (at the same index of `lowercase_patterns`) is in `lowercase_body`.
+#[inline]
+fn find_match<'a>(
+    patterns: &'a [String],
+    lowercase_patterns: &[String],
+    lowercase_body: &str,
+) -> Option<&'a str> {
+    lowercase_patterns
+        .iter()
+        .position(|pattern| lowercase_body.contains(pattern.as_str()))
+        .map(|index| patterns[index].as_str())
+}
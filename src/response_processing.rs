@@ -0,0 +1,120 @@
+const TRAILER_MARKERS: [&str; 8] = [
+    ">>>",
+    "NOTICE:",
+    "TERMS OF USE:",
+    "For more information on Whois status codes",
+    "URL of the ICANN Whois Inaccuracy Complaint Form",
+    "The data in this record is provided",
+    "The Service is provided so that you may look up",
+    "By submitting a WHOIS query, you agree",
+];
+
+#[inline]
+fn is_comment_line(line: &str) -> bool {
+    let line = line.trim_start();
+
+    line.starts_with('%') || line.starts_with('#') || line.starts_with(">>>")
+}
+
+#[inline]
+fn is_blank_line(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn is_trailer_line(line: &str) -> bool {
+    let line = line.trim_start();
+
+    TRAILER_MARKERS.iter().any(|marker| {
+        line.len() >= marker.len()
+            && line.is_char_boundary(marker.len())
+            && line[..marker.len()].eq_ignore_ascii_case(marker)
+    })
+}
+
+/// Remove leading comment lines (starting with `%`, `#` or `>>>`) and common trailing disclaimer sections (`>>> Last update of WHOIS database`, `NOTICE:`, `TERMS OF USE:`, etc.) from a WHOIS response body. The line endings of the remaining lines are preserved.
+pub fn strip_boilerplate<S: AsRef<str>>(body: S) -> String {
+    let lines: Vec<&str> = body.as_ref().split_inclusive('\n').collect();
+
+    let start = match lines.iter().position(|line| !is_blank_line(line) && !is_comment_line(line)) {
+        Some(start) => start,
+        None => return String::new(),
+    };
+
+    let mut end = lines[start..]
+        .iter()
+        .position(|line| is_trailer_line(line))
+        .map(|index| start + index)
+        .unwrap_or(lines.len());
+
+    while end > start && (is_blank_line(lines[end - 1]) || is_comment_line(lines[end - 1])) {
+        end -= 1;
+    }
+
+    lines[start..end].concat()
+}
+
+/// Check whether a `%` line looks like a data field (e.g. `%status: active`) rather than a comment (e.g. `% Note: this output has been filtered.`), i.e. the `%` is directly followed by a key and a `:`.
+fn is_percent_data_field(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, _)) => {
+            key.len() > 1
+                && key[1..].bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        },
+        None => false,
+    }
+}
+
+/// Check whether a line begins with `%` (after optional leading whitespace) and does not look like a data field. See `strip_percent_comments`.
+#[inline]
+pub(crate) fn is_percent_comment(line: &str) -> bool {
+    let line = line.trim_start();
+
+    line.starts_with('%') && !is_percent_data_field(line)
+}
+
+/// Remove the lines beginning with `%` (after optional leading whitespace), such as the metadata and disclaimers of RIPE-family responses. A line is kept if it looks like a data field, i.e. the `%` is directly followed by a key and a `:` (e.g. `%status: active`). The line endings of the remaining lines are preserved.
+pub fn strip_percent_comments<S: AsRef<str>>(body: S) -> String {
+    body.as_ref().split_inclusive('\n').filter(|line| !is_percent_comment(line)).collect()
+}
+
+/// Rewrite all `\r\n` and lone `\r` line endings in a WHOIS response body to `\n`.
+pub fn normalize_line_endings<S: AsRef<str>>(body: S) -> String {
+    let body = body.as_ref();
+
+    let mut result = String::with_capacity(body.len());
+
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\r' {
+            if chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+
+            result.push('\n');
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// Split each non-comment, non-blank line of a WHOIS response body on its first `:` into a trimmed key/value pair. The pairs keep the order of the lines, so a repeated key (e.g. `Name Server`) appears once per line. Lines without `:` are skipped.
+pub fn parse_key_values<S: AsRef<str>>(body: S) -> Vec<(String, String)> {
+    body.as_ref()
+        .lines()
+        .filter(|line| !is_blank_line(line) && !is_comment_line(line))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+
+            let key = key.trim();
+
+            if key.is_empty() {
+                None
+            } else {
+                Some((String::from(key), String::from(value.trim())))
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,9 @@
+/// How an `rwhois://` referral is handled. RWhois servers listen on port 4321 by default and speak a different protocol, although many of them answer a plain query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RWhoisPolicy {
+    /// Follow the referral with a plain query. If the referral has no explicit port, port 4321 is used instead of 43.
+    #[default]
+    Follow,
+    /// Don't follow the referral. Other referrals in the response may still be followed.
+    Skip,
+}
@@ -0,0 +1,75 @@
+use serde_json::{Map, Value};
+
+/// The top-level object of a server list, with the keys which appear more than once. `serde_json` keeps the last value of a duplicate key silently.
+pub(crate) struct ServerList {
+    pub(crate) map:            Map<String, Value>,
+    pub(crate) duplicate_keys: Vec<String>,
+}
+
+impl ServerList {
+    /// Parse the server list from JSON text.
+    pub(crate) fn from_slice(json: &[u8]) -> Result<ServerList, serde_json::Error> {
+        let map: Map<String, Value> = serde_json::from_slice(json)?;
+
+        let mut duplicate_keys: Vec<String> = Vec::new();
+
+        let keys = top_level_keys(json);
+
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].contains(key) && !duplicate_keys.contains(key) {
+                duplicate_keys.push(key.clone());
+            }
+        }
+
+        Ok(ServerList {
+            map,
+            duplicate_keys,
+        })
+    }
+}
+
+/// Get the keys of the top-level object of valid JSON text in order, including the repeated ones.
+fn top_level_keys(json: &[u8]) -> Vec<String> {
+    let mut keys = Vec::new();
+
+    let mut depth = 0usize;
+    let mut expecting_key = false;
+    let mut i = 0;
+
+    while i < json.len() {
+        match json[i] {
+            b'{' => {
+                depth += 1;
+                expecting_key = depth == 1;
+            },
+            b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            b',' if depth == 1 => expecting_key = true,
+            b'"' => {
+                let start = i;
+
+                i += 1;
+
+                while json[i] != b'"' {
+                    // skip the escaped character, which may be a quote
+                    if json[i] == b'\\' {
+                        i += 1;
+                    }
+
+                    i += 1;
+                }
+
+                if depth == 1 && expecting_key {
+                    keys.push(serde_json::from_slice(&json[start..=i]).unwrap_or_default());
+
+                    expecting_key = false;
+                }
+            },
+            _ => (),
+        }
+
+        i += 1;
+    }
+
+    keys
+}
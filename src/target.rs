@@ -1,14 +1,223 @@
-use validators::prelude::*;
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use validators::{errors::HostError, prelude::*};
 use validators_prelude::Host;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Validator)]
+use crate::TargetKind;
+
+/// The target (a domain or an IP) that you want to lookup. A Unicode domain is converted to its ASCII (punycode, `xn--`) form when it is parsed.
+///
+/// The input is kept as well if it has uppercase letters, for `WhoIsLookupOptions::preserve_case`. It is not compared or hashed.
+#[derive(Debug, Clone)]
+pub struct Target(pub(crate) Host, pub(crate) Option<String>);
+
+/// A host validated by `validators`, before the rules of `Target::parse_str` are applied.
+#[derive(Validator)]
 #[validator(host(port(Disallow)))]
-pub struct Target(pub(crate) Host);
+struct ValidatedHost(Host);
 
 impl Target {
     #[allow(clippy::missing_safety_doc)]
     #[inline]
     pub const unsafe fn from_host_unchecked(host: Host) -> Target {
-        Target(host)
+        Target(host, None)
+    }
+
+    /// Parse a domain or an IP. The surrounding whitespace is trimmed, and a string containing control characters (e.g. `\r\n`) is rejected. The zone ID of an IPv6 address (e.g. `%eth0` in `fe80::1%eth0`) is removed. A dotted string which is not a valid IPv4 address (e.g. `999.1.1.1` or `1.2.3`) is rejected too, because its all-numeric last label cannot be a TLD, while `1.2.3.4.example.com` is a domain. A Unicode domain is converted to its ASCII (punycode, `xn--`) form. A reverse-DNS domain of a whole address (e.g. `1.0.0.127.in-addr.arpa` or the 32 nibbles of an `ip6.arpa` domain) is converted to the IP, so it is routed to the IP server.
+    #[inline]
+    pub fn parse_str<S: AsRef<str>>(s: S) -> Result<Target, HostError> {
+        let s = s.as_ref().trim();
+
+        if s.chars().any(char::is_control) {
+            return Err(HostError::Invalid);
+        }
+
+        if let Some(ip) = parse_reverse_dns(s) {
+            return Ok(Target(ip, None));
+        }
+
+        Self::from_parsed_host(ValidatedHost::parse_str(strip_zone_id(s))?.0, cased_input(s))
+    }
+
+    /// Parse a domain or an IP from an owned string. See `parse_str` for the parsing rules.
+    #[inline]
+    pub fn parse_string<S: Into<String>>(s: S) -> Result<Target, HostError> {
+        let s = s.into();
+
+        if s.trim().len() != s.len() || s.contains('%') {
+            return Self::parse_str(s);
+        }
+
+        if s.chars().any(char::is_control) {
+            return Err(HostError::Invalid);
+        }
+
+        if let Some(ip) = parse_reverse_dns(&s) {
+            return Ok(Target(ip, None));
+        }
+
+        let input = cased_input(&s);
+
+        Self::from_parsed_host(ValidatedHost::parse_string(s)?.0, input)
+    }
+
+    /// Reject a domain whose last label is all-numeric, so that a malformed IP is not routed as a domain.
+    fn from_parsed_host(host: Host, input: Option<String>) -> Result<Target, HostError> {
+        if let Host::Domain(domain) = &host {
+            let tld = domain.trim_end_matches('.').rsplit('.').next().unwrap_or_default();
+
+            if !tld.is_empty() && tld.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(HostError::Invalid);
+            }
+        }
+
+        Ok(Target(host, input))
+    }
+
+    /// Get how this target has been classified, so that it can be verified before a lookup. An IP is routed to the IP server and a domain is routed by its TLD.
+    #[inline]
+    pub const fn kind(&self) -> TargetKind {
+        match &self.0 {
+            Host::Domain(_) => TargetKind::Domain,
+            Host::IPv4(_) => TargetKind::IPv4,
+            Host::IPv6(_) => TargetKind::IPv6,
+        }
+    }
+
+    /// Get the domain or the IP.
+    #[inline]
+    pub const fn host(&self) -> &Host {
+        &self.0
+    }
+}
+
+impl PartialEq for Target {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Target {}
+
+impl Hash for Target {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl ValidateString for Target {
+    type Error = HostError;
+
+    #[inline]
+    fn parse_string<S: Into<String>>(s: S) -> Result<Self, Self::Error> {
+        Target::parse_string(s)
+    }
+
+    #[inline]
+    fn parse_str<S: AsRef<str>>(s: S) -> Result<Self, Self::Error> {
+        Target::parse_str(s)
+    }
+
+    #[inline]
+    fn validate_str<S: AsRef<str>>(s: S) -> Result<(), Self::Error> {
+        Target::parse_str(s).map(|_| ())
+    }
+}
+
+impl ToUriAuthorityString for Target {
+    /// Format the target as a URI authority. An IPv6 address is enclosed in brackets.
+    #[inline]
+    fn to_uri_authority_string(&self) -> Cow<'_, str> {
+        match &self.0 {
+            Host::IPv4(ip) => Cow::Owned(ip.to_string()),
+            Host::IPv6(ip) => Cow::Owned(format!("[{}]", ip)),
+            Host::Domain(domain) => Cow::Borrowed(domain),
+        }
+    }
+}
+
+/// Keep the trimmed input if it has uppercase letters, which the parsed target has lost.
+#[inline]
+fn cased_input(s: &str) -> Option<String> {
+    if s.bytes().any(|b| b.is_ascii_uppercase()) {
+        Some(String::from(s))
+    } else {
+        None
+    }
+}
+
+/// Remove the zone ID of an IPv6 address, which is meaningless to WHOIS servers. The brackets are removed as well.
+fn strip_zone_id(s: &str) -> &str {
+    if let Some((addr, zone)) = s.split_once('%') {
+        let addr = match addr.strip_prefix('[') {
+            Some(addr) if zone.ends_with(']') => addr,
+            Some(_) => return s,
+            None => addr,
+        };
+
+        if addr.parse::<Ipv6Addr>().is_ok() {
+            return addr;
+        }
+    }
+
+    s
+}
+
+/// Convert a reverse-DNS domain of a whole address back to the IP, i.e. `d.c.b.a.in-addr.arpa` to `a.b.c.d`, or the 32 reversed nibbles of an `ip6.arpa` domain to the IPv6 address. A domain of a network (e.g. `0.127.in-addr.arpa`) is not converted.
+fn parse_reverse_dns(domain: &str) -> Option<Host> {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+
+    if let Some(labels) = domain.strip_suffix(".in-addr.arpa") {
+        let mut octets = [0u8; 4];
+        let mut count = 0;
+
+        for label in labels.rsplit('.') {
+            // a leading zero would be read as an octal number by some resolvers
+            if count == 4 || label.is_empty() || (label.len() > 1 && label.starts_with('0')) {
+                return None;
+            }
+
+            octets[count] = label.parse().ok()?;
+            count += 1;
+        }
+
+        return (count == 4).then(|| Host::IPv4(Ipv4Addr::from(octets)));
+    }
+
+    if let Some(labels) = domain.strip_suffix(".ip6.arpa") {
+        let mut addr = 0u128;
+        let mut count = 0;
+
+        for label in labels.rsplit('.') {
+            if count == 32 || label.len() != 1 {
+                return None;
+            }
+
+            addr = (addr << 4) | u128::from(label.chars().next()?.to_digit(16)?);
+            count += 1;
+        }
+
+        return (count == 32).then(|| Host::IPv6(Ipv6Addr::from(addr)));
+    }
+
+    None
+}
+
+impl Display for Target {
+    /// Format the target in the form which is sent to WHOIS servers. An IPv6 address is not enclosed in brackets.
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Host::IPv4(ip) => Display::fmt(ip, f),
+            Host::IPv6(ip) => Display::fmt(ip, f),
+            Host::Domain(domain) => f.write_str(domain),
+        }
     }
 }
@@ -0,0 +1,10 @@
+/// How a `Target` has been classified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TargetKind {
+    /// A domain, including a numeric-looking hostname such as `1.2.3.4.example.com`.
+    Domain,
+    /// An IPv4 address.
+    IPv4,
+    /// An IPv6 address.
+    IPv6,
+}
@@ -1,460 +1,2875 @@
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::{Read, Write},
-    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Debug, Formatter},
+    fs,
+    io::{self, Read, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
     path::Path,
     str::FromStr,
-    time::Duration,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::{Map, Value};
 #[cfg(feature = "tokio")]
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use trust_dns_client::{
     client::{Client, SyncClient},
     op::DnsResponse,
     rr::{DNSClass, Name, RData, Record, RecordType},
     udp::UdpClientConnection,
 };
-use validators::{models::Host, prelude::*};
+use validators::models::Host;
+
+use crate::{
+    ip_cidr::IpCidr, normalize_line_endings, parse_domain_record, parse_ip_record, proxy::Proxy,
+    server_list::ServerList, strip_boilerplate, strip_percent_comments,
+    who_is_lookup_options::DEFAULT_READ_CAPACITY, Charset, DomainRecord, IpRecord, RWhoisPolicy,
+    ResponseClass, ResponseClassifier, Target, WhoIsError, WhoIsEvent, WhoIsLookupOptions,
+    WhoIsLookupResult, WhoIsServerValue, WhoIsTransport, DEFAULT_TIMEOUT,
+};
+#[cfg(feature = "tokio")]
+use crate::{who_is_lines::LineOptions, WhoIsLines, WhoIsSwap};
+
+/// The standard port of WHOIS servers, which is used if a server has no explicit port.
+pub const DEFAULT_WHOIS_HOST_PORT: u16 = 43;
+
+/// The conventional environment variable for the path of the server list. See `WhoIs::from_env`.
+pub const DEFAULT_SERVERS_ENV_VAR: &str = "WHOIS_SERVERS_JSON";
 
-use crate::{WhoIsError, WhoIsLookupOptions, WhoIsServerValue};
+/// The maximum number of servers which `WhoIs::health_check` checks at the same time.
+pub const HEALTH_CHECK_THREADS: usize = 16;
 
-const DEFAULT_WHOIS_HOST_PORT: u16 = 43;
+const DEFAULT_RWHOIS_HOST_PORT: u16 = 4321;
 const DEFAULT_WHOIS_HOST_QUERY: &str = "$addr\r\n";
+const DEFAULT_QUERY_PLACEHOLDER: &str = "$addr";
+const IP_OBJECT_TYPE: &str = "ip";
+const IP_RANGES_KEY: &str = "ip_ranges";
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// A post-processed body, with its classification and the pattern which matched if it has been classified.
+type ClassifiedBody<'a> = (String, Option<(ResponseClass, Option<&'a str>)>);
 
 static RE_SERVER: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"(ReferralServer|Registrar Whois|Whois Server|WHOIS Server|Registrar WHOIS Server):[^\S\n]*(r?whois://)?(.*)").unwrap()
 });
 
-/// The `WhoIs` structure stores the list of WHOIS servers in-memory.
-#[derive(Debug, Clone)]
-pub struct WhoIs {
-    map: HashMap<String, WhoIsServerValue>,
-    ip:  WhoIsServerValue,
-}
+/// The signature of a hook which resolves a WHOIS host and a port to socket addresses.
+pub type HostResolverFn = dyn Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync;
 
-impl WhoIs {
-    /// Create a `WhoIs` instance which doesn't have a WHOIS server list. You should provide the host that is used for query ip. You may want to use the host `"whois.arin.net"`.
-    pub fn from_host<T: AsRef<str>>(host: T) -> Result<WhoIs, WhoIsError> {
-        Ok(Self {
-            map: HashMap::new(), ip: WhoIsServerValue::from_string(host)?
-        })
-    }
+#[derive(Clone)]
+struct HostResolver(Arc<HostResolverFn>);
 
-    /// Read the list of WHOIS servers (JSON data) from a file to create a `WhoIs` instance.
+impl Debug for HostResolver {
     #[inline]
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
-        let path = path.as_ref();
-
-        let file = File::open(path)?;
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("HostResolver")
+    }
+}
 
-        let map: Map<String, Value> = serde_json::from_reader(file)?;
+/// The signature of a hook which observes the events of a `WhoIs` instance.
+pub type WhoIsObserverFn = dyn Fn(&WhoIsEvent) + Send + Sync;
 
-        Self::from_inner(map)
-    }
+#[derive(Clone)]
+struct Observer(Arc<WhoIsObserverFn>);
 
-    #[cfg(feature = "tokio")]
-    /// Read the list of WHOIS servers (JSON data) from a file to create a `WhoIs` instance. For `serde_json` doesn't support async functions, consider just using the `from_path` function.
+impl Debug for Observer {
     #[inline]
-    pub async fn from_path_async<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
-        let file = tokio::fs::read(path).await?;
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
 
-        let map: Map<String, Value> = serde_json::from_slice(file.as_slice())?;
+/// The signature of a hook which opens a transport to a WHOIS server instead of a TCP connection.
+pub type TransportConnectorFn =
+    dyn Fn(&WhoIsServerValue) -> io::Result<Box<dyn WhoIsTransport>> + Send + Sync;
 
-        Self::from_inner(map)
-    }
+#[derive(Clone)]
+struct TransportConnector(Arc<TransportConnectorFn>);
 
-    /// Read the list of WHOIS servers (JSON data) from a string to create a `WhoIs` instance.
+impl Debug for TransportConnector {
     #[inline]
-    pub fn from_string<S: AsRef<str>>(string: S) -> Result<WhoIs, WhoIsError> {
-        let string = string.as_ref();
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("TransportConnector")
+    }
+}
 
-        let map: Map<String, Value> = serde_json::from_str(string)?;
+/// A key of the server list which is set to null.
+struct UnsupportedTld<'a>(&'a str);
 
-        Self::from_inner(map)
+#[inline]
+fn check_socket_addrs(socket_addrs: Vec<SocketAddr>) -> io::Result<Vec<SocketAddr>> {
+    if socket_addrs.is_empty() {
+        Err(io::Error::new(io::ErrorKind::NotFound, "The WHOIS host cannot be resolved."))
+    } else {
+        Ok(socket_addrs)
     }
+}
 
-    fn from_inner(mut map: Map<String, Value>) -> Result<WhoIs, WhoIsError> {
-        let ip = match map.remove("_") {
-            Some(server) => {
-                if let Value::Object(server) = server {
-                    match server.get("ip") {
-                        Some(server) => {
-                            if server.is_null() {
-                                return Err(WhoIsError::MapError(
-                                    "`ip` in the `_` object in the server list is null.",
-                                ));
-                            }
+/// Parse a server value of the server list. An error is annotated with the key (e.g. `io`, `_.ip` or `_.ip_ranges.193.0.0.0/8`) of the value.
+#[inline]
+fn server_from_entry(
+    key: String,
+    value: &Value,
+    strict: bool,
+) -> Result<WhoIsServerValue, WhoIsError> {
+    WhoIsServerValue::from_value_inner(value, strict).map_err(|error| WhoIsError::InvalidServer {
+        key,
+        source: Box::new(error),
+    })
+}
 
-                            WhoIsServerValue::from_value(server)?
-                        },
-                        None => {
-                            return Err(WhoIsError::MapError(
-                                "Cannot find `ip` in the `_` object in the server list.",
-                            ));
-                        },
-                    }
-                } else {
-                    return Err(WhoIsError::MapError("`_` in the server list is not an object."));
-                }
-            },
-            None => return Err(WhoIsError::MapError("Cannot find `_` in the server list.")),
-        };
+#[inline]
+fn parse_dns_server(dns_server: &str) -> Result<SocketAddr, WhoIsError> {
+    dns_server
+        .parse()
+        .map_err(|_| WhoIsError::MapError("The DNS server is not a correct socket address."))
+}
 
-        let mut new_map: HashMap<String, WhoIsServerValue> = HashMap::with_capacity(map.len());
+/// Get the first `nameserver` in `/etc/resolv.conf`.
+fn system_dns_server() -> Result<SocketAddr, WhoIsError> {
+    let resolv_conf = fs::read_to_string("/etc/resolv.conf")?;
 
-        for (k, v) in map {
-            if !v.is_null() {
-                let server_value = WhoIsServerValue::from_value(&v)?;
-                new_map.insert(k, server_value);
-            }
-        }
+    resolv_conf
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .find_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .ok_or(WhoIsError::MapError("No nameserver is configured in /etc/resolv.conf."))
+}
 
-        Ok(WhoIs {
-            map: new_map,
-            ip,
-        })
+/// Run the `whois` command of the operating system and get its standard output.
+#[cfg(feature = "system-whois")]
+fn system_lookup(domain: &str) -> Result<String, WhoIsError> {
+    let output = std::process::Command::new("whois").arg("--").arg(domain).output()?;
+
+    // many whois implementations exit with a non-zero code when the object is not found
+    if !output.status.success() && output.stdout.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("The system whois command failed ({}).", output.status),
+        )
+        .into());
     }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
-impl WhoIs {
-    pub fn can_find_server_for_tld<T: AsRef<str>, D: AsRef<str>>(
-        &mut self,
-        tld: T,
-        dns_server: D,
-    ) -> bool {
-        let mut tld = tld.as_ref();
-        let dns_server = dns_server.as_ref();
+/// Remove a leading UTF-8 BOM, which some servers send.
+#[inline]
+pub(crate) fn strip_bom(data: &mut Vec<u8>) {
+    if data.starts_with(UTF8_BOM) {
+        data.drain(..UTF8_BOM.len());
+    }
+}
 
-        let address = dns_server.parse().unwrap();
-        let conn = UdpClientConnection::new(address).unwrap();
-        let client = SyncClient::new(conn);
+/// Check whether the data begins with a gzip or a zlib header.
+pub(crate) fn is_compressed(data: &[u8]) -> bool {
+    match data {
+        [0x1F, 0x8B, ..] => true,
+        [cmf, flg, ..] => cmf & 0x0F == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
 
-        loop {
-            if self.map.contains_key(tld) {
-                break;
-            }
+#[cfg(feature = "compression")]
+const COMPRESSED_MESSAGE: &str =
+    "The WHOIS response is gzip/zlib compressed, but it cannot be decompressed.";
+#[cfg(not(feature = "compression"))]
+const COMPRESSED_MESSAGE: &str =
+    "The WHOIS response is gzip/zlib compressed, which needs the `compression` feature.";
+
+/// Decompress the data if it is gzip/zlib compressed. Data which only looks like a zlib header by chance, e.g. plain text starting with `x^`, is kept as it is.
+#[cfg(feature = "compression")]
+fn decompress_body(data: Vec<u8>) -> Vec<u8> {
+    if is_compressed(&data) {
+        if let Ok(decompressed) = crate::inflate::decompress(&data) {
+            return decompressed;
+        }
+    }
 
-            match tld.find('.') {
-                Some(index) => {
-                    tld = &tld[index + 1..];
-                },
-                None => {
-                    tld = "";
-                },
-            }
+    data
+}
 
-            if tld.is_empty() {
-                break;
-            }
+/// Convert the raw response into a string with the charset of the server. Compressed data, which some HTTP-to-WHOIS gateways send, is decompressed first with the `compression` feature. Otherwise, for UTF-8, it is rejected with a clear message instead of a generic UTF-8 error.
+pub(crate) fn decode_body(data: Vec<u8>, charset: Option<Charset>) -> io::Result<String> {
+    #[cfg(feature = "compression")]
+    let data = decompress_body(data);
 
-            let name = Name::from_str(&format!("_nicname._tcp.{}.", tld)).unwrap();
-            let response: DnsResponse = client.query(&name, DNSClass::IN, RecordType::SRV).unwrap();
-            let answers: &[Record] = response.answers();
+    let mut data = data;
 
-            for record in answers {
-                if let Some(RData::SRV(record)) = record.data() {
-                    let target = record.target().to_string();
-                    let new_server =
-                        match WhoIsServerValue::from_string(&target[..target.len() - 1]) {
-                            Ok(new_server) => new_server,
-                            Err(_error) => continue,
-                        };
+    match charset {
+        None | Some(Charset::Utf8) => (),
+        Some(charset) => return charset.decode(data),
+    }
 
-                    self.map.insert(tld.to_string(), new_server);
+    strip_bom(&mut data);
 
-                    return true;
-                }
-            }
+    String::from_utf8(data).map_err(|error| {
+        if is_compressed(error.as_bytes()) {
+            io::Error::new(io::ErrorKind::InvalidData, COMPRESSED_MESSAGE)
+        } else {
+            io::Error::new(io::ErrorKind::InvalidData, error)
         }
+    })
+}
+
+/// Get the time which a socket operation may take, bounded by both the timeout and the deadline.
+pub(crate) fn remaining_time(
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> Option<Duration> {
+    match deadline {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)))
+        },
+        None => timeout,
+    }
+}
 
-        false
+/// Get the earlier one of two optional deadlines.
+#[inline]
+pub(crate) fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
     }
+}
 
-    fn get_server_by_tld(&self, mut tld: &str) -> Option<&WhoIsServerValue> {
-        let mut server;
+#[inline]
+pub(crate) fn deadline_passed(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |deadline| Instant::now() >= deadline)
+}
 
-        loop {
-            server = self.map.get(tld);
+/// Copy the response to `out`. If there is a deadline, the read timeout is shortened before each read so that the whole response has to arrive before the deadline.
+fn copy_until<W: Write>(
+    client: &mut TcpStream,
+    out: &mut W,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    if deadline.is_none() {
+        io::copy(client, out)?;
+
+        return Ok(());
+    }
 
-            if server.is_some() {
-                break;
-            }
+    let mut buffer = [0; 4096];
 
-            if tld.is_empty() {
-                break;
-            }
+    loop {
+        set_remaining_read_timeout(client, timeout, deadline)?;
 
-            match tld.find('.') {
-                Some(index) => {
-                    tld = &tld[index + 1..];
-                },
-                None => {
-                    tld = "";
-                },
-            }
+        match client.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => out.write_all(&buffer[..n])?,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
         }
+    }
+}
 
-        server
+/// Shorten the read timeout so that the next read has to finish before the deadline.
+pub(crate) fn set_remaining_read_timeout(
+    client: &TcpStream,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    match remaining_time(timeout, deadline) {
+        Some(remaining) if remaining.is_zero() => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "The maximum total duration of the query has elapsed.",
+        )),
+        remaining => client.set_read_timeout(remaining),
     }
+}
 
-    fn lookup_once(
-        server: &WhoIsServerValue,
-        text: &str,
-        timeout: Option<Duration>,
-    ) -> Result<(String, String), WhoIsError> {
-        let addr = server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT);
+/// How the end of a response is recognized, for a server which keeps the connection open.
+pub(crate) struct Terminator<'a> {
+    line:    Option<&'a str>,
+    pattern: Option<regex::bytes::Regex>,
+}
 
-        let mut client = if let Some(timeout) = timeout {
-            let socket_addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+impl<'a> Terminator<'a> {
+    /// Get the terminator of the server from its `response_terminator` and `response_terminator_pattern`. **None** means that the response is read until EOF.
+    pub(crate) fn of(server: &'a WhoIsServerValue) -> Result<Option<Terminator<'a>>, WhoIsError> {
+        let pattern = match &server.response_terminator_pattern {
+            Some(pattern) => Some(regex::bytes::Regex::new(pattern).map_err(|_| {
+                WhoIsError::MapError(
+                    "The response_terminator_pattern of the server is not a correct regular \
+                     expression.",
+                )
+            })?),
+            None => None,
+        };
 
-            let mut client = None;
+        if server.response_terminator.is_none() && pattern.is_none() {
+            return Ok(None);
+        }
 
-            for socket_addr in socket_addrs.iter().take(socket_addrs.len() - 1) {
-                if let Ok(c) = TcpStream::connect_timeout(socket_addr, timeout) {
-                    client = Some(c);
-                    break;
-                }
-            }
+        Ok(Some(Terminator {
+            line: server.response_terminator.as_deref(),
+            pattern,
+        }))
+    }
 
-            let client = if let Some(client) = client {
-                client
-            } else {
-                let socket_addr = &socket_addrs[socket_addrs.len() - 1];
-                TcpStream::connect_timeout(socket_addr, timeout)?
-            };
+    /// Check whether a line (without its line ending) ends the response.
+    fn matches(&self, line: &[u8]) -> bool {
+        self.line.map_or(false, |terminator| line == terminator.as_bytes())
+            || self.pattern.as_ref().map_or(false, |pattern| pattern.is_match(line))
+    }
+}
 
-            client.set_read_timeout(Some(timeout))?;
-            client.set_write_timeout(Some(timeout))?;
-            client
-        } else {
-            TcpStream::connect(&addr)?
-        };
+/// Find the start of the first complete line at or after `from` which matches the terminator. The offset of the first incomplete line is stored in `from`, so that the next search can start there.
+fn find_terminator(data: &[u8], terminator: &Terminator, from: &mut usize) -> Option<usize> {
+    while let Some(end) = data[*from..].iter().position(|&b| b == b'\n') {
+        let start = *from;
+        let line = &data[start..start + end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
 
-        if let Some(query) = &server.query {
-            client.write_all(query.replace("$addr", text).as_bytes())?;
-        } else {
-            client.write_all(DEFAULT_WHOIS_HOST_QUERY.replace("$addr", text).as_bytes())?;
+        *from = start + end + 1;
+
+        if terminator.matches(line) {
+            return Some(start);
         }
+    }
 
-        client.flush()?;
+    None
+}
 
-        let mut query_result = String::new();
+/// Check whether the response of the server can be copied to a writer as it arrives, i.e. it is not ended by a terminator and it needs no decoding into UTF-8.
+#[inline]
+fn can_stream(server: &WhoIsServerValue) -> bool {
+    server.response_terminator.is_none()
+        && server.response_terminator_pattern.is_none()
+        && server.body_charset().map_or(true, |charset| charset == Charset::Utf8)
+}
 
-        client.read_to_string(&mut query_result)?;
+/// Write the query and flush it. If `tolerate_errors` is true, an error after some bytes have been written is ignored, so that the response of a server which closes its side early (as soon as it has seen the address) can still be read.
+pub(crate) fn write_query<W: Write + ?Sized>(
+    writer: &mut W,
+    query: &[u8],
+    tolerate_errors: bool,
+) -> io::Result<()> {
+    let mut written = 0;
+
+    while written < query.len() {
+        let error = match writer.write(&query[written..]) {
+            Ok(0) => io::Error::from(io::ErrorKind::WriteZero),
+            Ok(n) => {
+                written += n;
+
+                continue;
+            },
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => error,
+        };
 
-        Ok((addr, query_result))
+        return if tolerate_errors && written > 0 { Ok(()) } else { Err(error) };
     }
 
-    fn lookup_inner(
-        server: &WhoIsServerValue,
-        text: &str,
-        timeout: Option<Duration>,
-        mut follow: u16,
-    ) -> Result<String, WhoIsError> {
-        let mut query_result = Self::lookup_once(server, text, timeout)?;
+    match writer.flush() {
+        Err(_) if tolerate_errors && written > 0 => Ok(()),
+        result => result,
+    }
+}
 
-        while follow > 0 {
-            if let Some(c) = RE_SERVER.captures(&query_result.1) {
-                if let Some(h) = c.get(3) {
-                    let h = h.as_str();
-                    if h.ne(&query_result.0) {
-                        if let Ok(server) = WhoIsServerValue::from_string(h) {
-                            query_result = Self::lookup_once(&server, text, timeout)?;
+/// Read the response into `data` until the terminator line is received or the reader reaches EOF. The terminator line and everything after it are removed. `before_read` is called before each read.
+pub(crate) fn read_until_terminator<R: Read>(
+    reader: &mut R,
+    data: &mut Vec<u8>,
+    terminator: &Terminator,
+    mut before_read: impl FnMut(&mut R) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut buffer = [0; 4096];
+    let mut from = 0;
 
-                            follow -= 1;
+    loop {
+        before_read(reader)?;
 
-                            continue;
-                        }
-                    }
-                }
-            }
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                data.extend_from_slice(&buffer[..n]);
+
+                if let Some(start) = find_terminator(data, terminator, &mut from) {
+                    data.truncate(start);
 
-            break;
+                    return Ok(());
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
         }
+    }
+}
 
-        Ok(query_result.1)
+/// Get the text which will be substituted for `$addr` in a query.
+fn target_text(target: &Target) -> Cow<'_, str> {
+    match target.host() {
+        Host::IPv4(_) | Host::IPv6(_) => Cow::Owned(target.to_string()),
+        // punycode check is not necessary because `Target` parsing has ascii-encoded the domain
+        Host::Domain(domain) => Cow::Borrowed(domain.as_str()),
     }
+}
 
-    /// Lookup a domain or an IP.
-    pub fn lookup(&self, options: WhoIsLookupOptions) -> Result<String, WhoIsError> {
-        match &options.target.0 {
-            Host::IPv4(_) | Host::IPv6(_) => {
-                let server = match &options.server {
-                    Some(server) => server,
-                    None => &self.ip,
-                };
+/// Get the text which will be substituted for `$addr` in a query for `target`, or for the `raw_target` of `options` if it is set. The surrounding whitespace of a raw handle is trimmed.
+fn query_text<'a>(target: &'a Target, options: &'a WhoIsLookupOptions) -> Cow<'a, str> {
+    match &options.raw_target {
+        Some(handle) => Cow::Borrowed(handle.trim()),
+        None => target_text(target),
+    }
+}
 
-                Self::lookup_inner(
-                    server,
-                    options.target.to_uri_authority_string().as_ref(),
-                    options.timeout,
-                    options.follow,
-                )
-            },
-            Host::Domain(domain) => {
-                let server = match &options.server {
-                    Some(server) => server,
-                    None => match self.get_server_by_tld(domain.as_str()) {
-                        Some(server) => server,
-                        None => {
-                            return Err(WhoIsError::MapError(
-                                "No whois server is known for this kind of object.",
-                            ));
-                        },
-                    },
-                };
+/// Split the concatenated responses of a batch query. Every response is assumed to start with the same header lines as the first one. The first line which appears exactly once per response is used as the marker.
+fn split_batch_response(body: &str, count: usize) -> Option<Vec<String>> {
+    if count == 1 {
+        return Some(vec![String::from(body)]);
+    }
 
-                // punycode check is not necessary because the domain has been ascii-encoded
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
 
-                Self::lookup_inner(server, domain, options.timeout, options.follow)
-            },
+    let (marker_index, positions) = lines.iter().enumerate().find_map(|(i, line)| {
+        let marker = line.trim_end();
+
+        if marker.is_empty() {
+            return None;
         }
-    }
-}
 
-#[cfg(feature = "tokio")]
-impl WhoIs {
-    async fn lookup_inner_once_async<'a>(
-        server: &WhoIsServerValue,
-        text: &str,
-        timeout: Option<Duration>,
-    ) -> Result<(String, String), WhoIsError> {
-        let addr = server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT);
+        let positions: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.trim_end() == marker)
+            .map(|(j, _)| j)
+            .collect();
 
-        if let Some(timeout) = timeout {
-            let socket_addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        if positions.len() == count && positions[0] == i {
+            Some((i, positions))
+        } else {
+            None
+        }
+    })?;
 
-            let mut client = None;
+    let mut offsets = Vec::with_capacity(lines.len() + 1);
+    let mut offset = 0;
 
-            for socket_addr in socket_addrs.iter().take(socket_addrs.len() - 1) {
-                if let Ok(c) =
-                    tokio::time::timeout(timeout, tokio::net::TcpStream::connect(&socket_addr))
-                        .await?
-                {
-                    client = Some(c);
-                    break;
-                }
-            }
+    for line in lines.iter() {
+        offsets.push(offset);
+        offset += line.len();
+    }
 
-            let mut client = if let Some(client) = client {
-                client
-            } else {
-                let socket_addr = &socket_addrs[socket_addrs.len() - 1];
-                tokio::time::timeout(timeout, tokio::net::TcpStream::connect(socket_addr)).await??
-            };
+    offsets.push(offset);
 
-            if let Some(query) = &server.query {
-                tokio::time::timeout(
-                    timeout,
-                    client.write_all(query.replace("$addr", text).as_bytes()),
-                )
-                .await??;
-            } else {
-                tokio::time::timeout(
-                    timeout,
-                    client.write_all(DEFAULT_WHOIS_HOST_QUERY.replace("$addr", text).as_bytes()),
-                )
-                .await??;
-            }
+    let mut starts: Vec<usize> = Vec::with_capacity(count + 1);
 
-            tokio::time::timeout(timeout, client.flush()).await??;
+    for position in positions {
+        let start = offsets[position - marker_index];
 
-            let mut query_result = String::new();
+        // the header lines of two responses must not overlap
+        if starts.last().map(|last| *last >= start).unwrap_or(false) {
+            return None;
+        }
 
-            tokio::time::timeout(timeout, client.read_to_string(&mut query_result)).await??;
+        starts.push(start);
+    }
 
-            Ok((addr, query_result))
-        } else {
-            let mut client = tokio::net::TcpStream::connect(&addr).await?;
+    starts.push(body.len());
 
-            if let Some(query) = &server.query {
-                client.write_all(query.replace("$addr", text).as_bytes()).await?;
-            } else {
-                client
-                    .write_all(DEFAULT_WHOIS_HOST_QUERY.replace("$addr", text).as_bytes())
-                    .await?;
-            }
+    Some(starts.windows(2).map(|w| String::from(&body[w[0]..w[1]])).collect())
+}
+
+/// Substitute the text for the placeholder (`$addr` by default) in the query of the server verbatim. No line ending is appended, so a query such as `"$addr"` is sent as is.
+#[inline]
+fn make_query(server: &WhoIsServerValue, text: &str, placeholder: &str) -> String {
+    match &server.query {
+        Some(query) => query.replace(placeholder, text),
+        None => DEFAULT_WHOIS_HOST_QUERY.replace(DEFAULT_QUERY_PLACEHOLDER, text),
+    }
+}
 
-            client.flush().await?;
+/// Reject a text containing a line break, so that a target cannot inject another query. The line ending of the query template itself is not checked.
+#[inline]
+fn check_target_text(text: &str) -> Result<(), WhoIsError> {
+    if text.contains(['\r', '\n']) {
+        return Err(WhoIsError::InvalidTarget {
+            target: String::from(text)
+        });
+    }
 
-            let mut query_result = String::new();
+    Ok(())
+}
 
-            client.read_to_string(&mut query_result).await?;
+/// Build the query, put the flags before it with a space, and encode it with the charset of the server.
+fn encode_query(
+    server: &WhoIsServerValue,
+    text: &str,
+    placeholder: &str,
+    query_flags: Option<&str>,
+) -> io::Result<Vec<u8>> {
+    let query = match query_flags {
+        Some(query_flags) => format!("{} {}", query_flags, make_query(server, text, placeholder)),
+        None => make_query(server, text, placeholder),
+    };
+
+    server.charset.unwrap_or(Charset::Utf8).encode(&query)
+}
 
-            Ok((addr, query_result))
-        }
+/// Remove the surrounding whitespace and any trailing path (e.g. the `/` in `rwhois://host:4321/`) from a captured referral value. An explicit port is preserved.
+fn normalize_referral(referral: &str) -> &str {
+    let referral = referral.trim();
+
+    match referral.find('/') {
+        Some(index) => &referral[..index],
+        None => referral,
     }
+}
 
-    async fn lookup_inner_async<'a>(
-        server: &'a WhoIsServerValue,
-        text: &'a str,
-        timeout: Option<Duration>,
-        mut follow: u16,
-    ) -> Result<String, WhoIsError> {
-        let mut query_result = Self::lookup_inner_once_async(server, text, timeout).await?;
+/// The response of a single query.
+pub(crate) struct QueryResult {
+    /// The `host:port` string of the queried server.
+    pub(crate) addr:        String,
+    /// The socket address which the connection used.
+    pub(crate) socket_addr: SocketAddr,
+    pub(crate) body:        String,
+}
+
+/// The runtime-agnostic parts of a query, which are shared by the sync and async transports.
+pub(crate) struct PreparedQuery {
+    /// The `host:port` string of the server.
+    pub(crate) addr:  String,
+    /// The host of the server, without brackets.
+    pub(crate) host:  String,
+    pub(crate) port:  u16,
+    pub(crate) proxy: Option<Proxy>,
+    /// The encoded query.
+    pub(crate) query: Vec<u8>,
+}
+
+impl PreparedQuery {
+    pub(crate) fn new(
+        server: &WhoIsServerValue,
+        text: &str,
+        placeholder: &str,
+        options: &WhoIsLookupOptions,
+    ) -> Result<PreparedQuery, WhoIsError> {
+        check_target_text(text)?;
 
-        while follow > 0 {
-            if let Some(c) = RE_SERVER.captures(&query_result.1) {
-                if let Some(h) = c.get(3) {
-                    let h = h.as_str();
-                    if h.ne(&query_result.0) {
-                        if let Ok(server) = WhoIsServerValue::from_string(h) {
-                            query_result =
-                                Self::lookup_inner_once_async(&server, text, timeout).await?;
+        let addr = server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT);
+        let host = server.host.to_host_string();
+        let port = server.host.port.unwrap_or(DEFAULT_WHOIS_HOST_PORT);
 
-                            follow -= 1;
+        let with_addr = |source| WhoIsError::from_io_with_addr(&addr, source);
 
-                            continue;
-                        }
-                    }
-                }
-            }
+        let proxy =
+            if options.use_env_proxy { Proxy::from_env(&host).map_err(with_addr)? } else { None };
 
-            break;
-        }
+        let query = encode_query(server, text, placeholder, options.query_flags.as_deref())
+            .map_err(with_addr)?;
 
-        Ok(query_result.1)
+        Ok(PreparedQuery {
+            addr,
+            host,
+            port,
+            proxy,
+            query,
+        })
     }
+}
 
-    /// Lookup a domain or an IP.
-    pub async fn lookup_async(&self, options: WhoIsLookupOptions) -> Result<String, WhoIsError> {
-        match &options.target.0 {
-            Host::IPv4(_) | Host::IPv6(_) => {
-                let server = match &options.server {
-                    Some(server) => server,
-                    None => &self.ip,
-                };
+#[inline]
+fn last_hop(hops: &[QueryResult]) -> &QueryResult {
+    hops.last().expect("a referral chain has at least one hop")
+}
 
-                Self::lookup_inner_async(
-                    server,
-                    options.target.to_uri_authority_string().as_ref(),
-                    options.timeout,
-                    options.follow,
-                )
-                .await
+/// Split the results of a referral chain into the last result and the `host:port` strings of all hops.
+fn split_hops(mut hops: Vec<QueryResult>) -> (QueryResult, Vec<String>) {
+    let chain = hops.iter().map(|hop| hop.addr.clone()).collect();
+
+    let last = hops.pop().expect("a referral chain has at least one hop");
+
+    (last, chain)
+}
+
+/// Run `operation` within `timeout`. A `WhoIsError::Timeout` error is returned if it elapses, and an I/O error is attributed to `addr`.
+#[cfg(feature = "tokio")]
+async fn within_async<T>(
+    addr: &str,
+    timeout: Option<Duration>,
+    operation: impl std::future::Future<Output = io::Result<T>>,
+) -> Result<T, WhoIsError> {
+    let result = match timeout {
+        Some(timeout) => {
+            tokio::time::timeout(timeout, operation).await.map_err(|_| WhoIsError::Timeout {
+                addr: String::from(addr),
+            })?
+        },
+        None => operation.await,
+    };
+
+    result.map_err(|source| WhoIsError::from_io_with_addr(addr, source))
+}
+
+/// Write the query and flush it. See `write_query`.
+#[cfg(feature = "tokio")]
+async fn write_query_async(
+    client: &mut tokio::net::TcpStream,
+    query: &[u8],
+    tolerate_errors: bool,
+) -> io::Result<()> {
+    let mut written = 0;
+
+    while written < query.len() {
+        let error = match client.write(&query[written..]).await {
+            Ok(0) => io::Error::from(io::ErrorKind::WriteZero),
+            Ok(n) => {
+                written += n;
+
+                continue;
+            },
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => error,
+        };
+
+        return if tolerate_errors && written > 0 { Ok(()) } else { Err(error) };
+    }
+
+    match client.flush().await {
+        Err(_) if tolerate_errors && written > 0 => Ok(()),
+        result => result,
+    }
+}
+
+/// Read the response into `data` until the terminator line is received, or until EOF if there is no terminator. See `read_until_terminator`.
+#[cfg(feature = "tokio")]
+async fn read_until_terminator_async(
+    client: &mut tokio::net::TcpStream,
+    data: &mut Vec<u8>,
+    terminator: Option<&Terminator<'_>>,
+) -> io::Result<()> {
+    let terminator = match terminator {
+        Some(terminator) => terminator,
+        None => return client.read_to_end(data).await.map(|_| ()),
+    };
+
+    let mut buffer = [0; 4096];
+    let mut from = 0;
+
+    loop {
+        match client.read(&mut buffer).await {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                data.extend_from_slice(&buffer[..n]);
+
+                if let Some(start) = find_terminator(data, terminator, &mut from) {
+                    data.truncate(start);
+
+                    return Ok(());
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Get the modification time and the size of a file, to know whether it has been changed.
+#[cfg(feature = "tokio")]
+async fn file_version(path: &Path) -> Option<(std::time::SystemTime, u64)> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Run the futures with at most `limit` of them at the same time, and get their outputs in the input order regardless of the completion order. A future is started as soon as another one finishes.
+#[cfg(feature = "tokio")]
+async fn join_bounded<F: std::future::Future>(futures: Vec<F>, limit: usize) -> Vec<F::Output> {
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+
+    let limit = limit.max(1);
+    let mut next = 0;
+    let mut running: Vec<usize> = Vec::with_capacity(limit.min(futures.len()));
+
+    std::future::poll_fn(|cx| loop {
+        while running.len() < limit && next < futures.len() {
+            running.push(next);
+            next += 1;
+        }
+
+        let before = running.len();
+
+        running.retain(|&index| match futures[index].as_mut().poll(cx) {
+            std::task::Poll::Ready(value) => {
+                outputs[index] = Some(value);
+
+                false
+            },
+            std::task::Poll::Pending => true,
+        });
+
+        if running.is_empty() && next == futures.len() {
+            return std::task::Poll::Ready(());
+        }
+
+        // start the next futures if any has finished, otherwise wait to be woken
+        if running.len() == before {
+            return std::task::Poll::Pending;
+        }
+    })
+    .await;
+
+    outputs.into_iter().map(|output| output.expect("every future is ready")).collect()
+}
+
+/// Apply the `preserve_case` and `query_override` of `options` to the server chosen for `target` and the text to be queried.
+fn apply_server_options<'a>(
+    server: &'a WhoIsServerValue,
+    target: &'a Target,
+    mut text: Cow<'a, str>,
+    options: &'a WhoIsLookupOptions,
+) -> (Cow<'a, WhoIsServerValue>, Cow<'a, str>) {
+    if options.preserve_case {
+        if let (None, Host::Domain(_), Some(input)) =
+            (&options.raw_target, target.host(), &target.1)
+        {
+            // a Unicode domain has been converted to punycode, so its input cannot be sent
+            if input.eq_ignore_ascii_case(&text) {
+                text = Cow::Borrowed(input);
+            }
+        }
+    }
+
+    let server = match &options.query_override {
+        Some(query) => {
+            let mut server = server.clone();
+
+            server.query = Some(query.clone());
+
+            Cow::Owned(server)
+        },
+        None => Cow::Borrowed(server),
+    };
+
+    (server, text)
+}
+
+/// Check whether a parsed referral host is plausible. A domain must have at least two labels.
+fn is_plausible_referral(server: &WhoIsServerValue) -> bool {
+    match &server.host.host {
+        Host::IPv4(_) | Host::IPv6(_) => true,
+        Host::Domain(domain) => domain.contains('.') && !domain.ends_with('.'),
+    }
+}
+
+/// The `WhoIs` structure stores the list of WHOIS servers in-memory. The list is shared between clones, so cloning a `WhoIs` instance is cheap. Mutating methods copy the list on write if it is shared.
+#[derive(Debug, Clone)]
+pub struct WhoIs {
+    map:                   Arc<HashMap<String, WhoIsServerValue>>,
+    /// The TLDs which are set to null in the list, i.e. unsupported intentionally.
+    null_tlds:             HashSet<String>,
+    object_servers:        HashMap<String, WhoIsServerValue>,
+    /// The IP servers for CIDR ranges, the most specific first.
+    ip_ranges:             Vec<(IpCidr, WhoIsServerValue)>,
+    default_domain_server: Option<WhoIsServerValue>,
+    classifier:            ResponseClassifier,
+    resolver:              Option<HostResolver>,
+    observer:              Option<Observer>,
+    connector:             Option<TransportConnector>,
+    dns_server:            Option<SocketAddr>,
+    duplicate_keys:        Vec<String>,
+    /// The token in `query` templates which is replaced with the target.
+    query_placeholder:     String,
+}
+
+impl WhoIs {
+    /// Create a `WhoIs` instance which doesn't have a WHOIS server list. You should provide the host that is used for query ip. You may want to use the host `"whois.arin.net"`.
+    #[inline]
+    pub fn from_host<T: AsRef<str>>(host: T) -> Result<WhoIs, WhoIsError> {
+        Ok(Self::from_ip_server(WhoIsServerValue::from_string(host)?))
+    }
+
+    /// Create a `WhoIs` instance which doesn't have a WHOIS server list. You should provide the host (with an optional port, such as `"whois.arin.net:43"`) and the query template (such as `"n + $addr\r\n"`) that are used for query ip.
+    #[inline]
+    pub fn from_host_with<T: AsRef<str>, Q: Into<String>>(
+        host: T,
+        query: Q,
+    ) -> Result<WhoIs, WhoIsError> {
+        let mut server = WhoIsServerValue::from_string(host)?;
+
+        server.query = Some(query.into());
+
+        Ok(Self::from_ip_server(server))
+    }
+
+    /// Create a `WhoIs` instance which doesn't have a WHOIS server list. You should provide the server that is used for query ip.
+    #[inline]
+    pub fn from_ip_server(ip: WhoIsServerValue) -> WhoIs {
+        let mut who = Self::without_ip_server();
+
+        who.object_servers.insert(String::from(IP_OBJECT_TYPE), ip);
+
+        who
+    }
+
+    /// Create a `WhoIs` instance which doesn't have a WHOIS server list or a server for IP lookups. IP lookups fail fast unless `options.server` is set, instead of falling back to a possibly wrong server.
+    #[inline]
+    pub fn without_ip_server() -> WhoIs {
+        WhoIs {
+            map:                   Arc::new(HashMap::new()),
+            null_tlds:             HashSet::new(),
+            object_servers:        HashMap::new(),
+            ip_ranges:             Vec::new(),
+            default_domain_server: None,
+            classifier:            ResponseClassifier::default(),
+            resolver:              None,
+            observer:              None,
+            connector:             None,
+            dns_server:            None,
+            duplicate_keys:        Vec::new(),
+            query_placeholder:     String::from(DEFAULT_QUERY_PLACEHOLDER),
+        }
+    }
+
+    /// Read the list of WHOIS servers (JSON data) from a file to create a `WhoIs` instance.
+    #[inline]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
+        Self::from_path_inner(path.as_ref(), false)
+    }
+
+    /// Read the list of WHOIS servers (JSON data) from a file to create a `WhoIs` instance. The list is validated strictly, e.g. a `query` string without the `$addr` placeholder is rejected.
+    #[inline]
+    pub fn from_path_strict<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
+        Self::from_path_inner(path.as_ref(), true)
+    }
+
+    /// Read the list of WHOIS servers (JSON data) from the file whose path is in the environment variable `var`, so that the location can be changed without rebuilding. See `DEFAULT_SERVERS_ENV_VAR` for a conventional name.
+    pub fn from_env<S: AsRef<str>>(var: S) -> Result<WhoIs, WhoIsError> {
+        let var = var.as_ref();
+
+        let path = match std::env::var_os(var) {
+            Some(path) if !path.is_empty() => path,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The environment variable {} is not set.", var),
+                )
+                .into());
+            },
+        };
+
+        Self::from_path(&path).map_err(|error| match error {
+            WhoIsError::IOError(error) => io::Error::new(
+                error.kind(),
+                format!("{} (from {}): {}", Path::new(&path).display(), var, error),
+            )
+            .into(),
+            error => error,
+        })
+    }
+
+    fn from_path_inner(path: &Path, strict: bool) -> Result<WhoIs, WhoIsError> {
+        let mut file = fs::read(path)?;
+
+        strip_bom(&mut file);
+
+        let list = ServerList::from_slice(file.as_slice())?;
+
+        Self::from_server_list(list, strict)
+    }
+
+    #[cfg(feature = "jsonc")]
+    /// Read the list of WHOIS servers (JSONC data, i.e. JSON with comments and trailing commas) from a file to create a `WhoIs` instance.
+    #[inline]
+    pub fn from_jsonc_path<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
+        let string = fs::read_to_string(path)?;
+
+        Self::from_jsonc_string(string)
+    }
+
+    #[cfg(feature = "jsonc")]
+    /// Read the list of WHOIS servers (JSONC data, i.e. JSON with comments and trailing commas) from a string to create a `WhoIs` instance.
+    #[inline]
+    pub fn from_jsonc_string<S: AsRef<str>>(string: S) -> Result<WhoIs, WhoIsError> {
+        let string = crate::relaxed_json::strip_comments_and_trailing_commas(string.as_ref());
+
+        Self::from_string_inner(&string, false)
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Read the list of WHOIS servers (JSON data) from a file to create a `WhoIs` instance. For `serde_json` doesn't support async functions, consider just using the `from_path` function.
+    #[inline]
+    pub async fn from_path_async<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
+        Self::from_path_async_inner(path.as_ref(), false).await
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Read the list of WHOIS servers (JSON data) from a file to create a `WhoIs` instance. The list is validated strictly, e.g. a `query` string without the `$addr` placeholder is rejected.
+    #[inline]
+    pub async fn from_path_async_strict<P: AsRef<Path>>(path: P) -> Result<WhoIs, WhoIsError> {
+        Self::from_path_async_inner(path.as_ref(), true).await
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Read the list of WHOIS servers (JSON data) from a file, and spawn a task on the current tokio runtime which checks the file every `interval` and reloads it when its modification time or size changes. A successfully parsed list replaces the instance in the returned `WhoIsSwap` atomically, while a list which cannot be read or parsed is ignored and the old instance is kept. A reloaded instance has the default settings (e.g. no host resolver or observer). Abort the returned handle to stop watching.
+    pub async fn watch_path<P: Into<std::path::PathBuf>>(
+        path: P,
+        interval: Duration,
+    ) -> Result<(Arc<WhoIsSwap>, tokio::task::JoinHandle<()>), WhoIsError> {
+        let path = path.into();
+
+        let mut version = file_version(&path).await;
+
+        let swap = Arc::new(WhoIsSwap::new(Self::from_path_async(&path).await?));
+
+        let handle = tokio::spawn({
+            let swap = Arc::clone(&swap);
+
+            async move {
+                let mut ticks = tokio::time::interval(interval);
+                ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+                // the first tick completes immediately
+                ticks.tick().await;
+
+                loop {
+                    ticks.tick().await;
+
+                    let new_version = file_version(&path).await;
+
+                    if new_version.is_none() || new_version == version {
+                        continue;
+                    }
+
+                    if let Ok(who) = Self::from_path_async(&path).await {
+                        swap.store(who);
+
+                        version = new_version;
+                    }
+                }
+            }
+        });
+
+        Ok((swap, handle))
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn from_path_async_inner(path: &Path, strict: bool) -> Result<WhoIs, WhoIsError> {
+        let mut file = tokio::fs::read(path).await?;
+
+        strip_bom(&mut file);
+
+        let list = ServerList::from_slice(file.as_slice())?;
+
+        Self::from_server_list(list, strict)
+    }
+
+    /// Read the list of WHOIS servers (JSON data) from a string to create a `WhoIs` instance.
+    #[inline]
+    pub fn from_string<S: AsRef<str>>(string: S) -> Result<WhoIs, WhoIsError> {
+        Self::from_string_inner(string.as_ref(), false)
+    }
+
+    /// Read the list of WHOIS servers (JSON data) from a string to create a `WhoIs` instance. The list is validated strictly, e.g. a `query` string without the `$addr` placeholder is rejected.
+    #[inline]
+    pub fn from_string_strict<S: AsRef<str>>(string: S) -> Result<WhoIs, WhoIsError> {
+        Self::from_string_inner(string.as_ref(), true)
+    }
+
+    /// Read the list of WHOIS servers (JSON data) from bytes, e.g. embedded with `include_bytes!`, to create a `WhoIs` instance without copying them into a `String` first.
+    #[inline]
+    pub fn from_slice(bytes: &[u8]) -> Result<WhoIs, WhoIsError> {
+        let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+
+        let list = ServerList::from_slice(bytes)?;
+
+        Self::from_server_list(list, false)
+    }
+
+    /// Create a `WhoIs` instance from the list of WHOIS servers which has been parsed as a JSON value, e.g. merged from multiple config sources. The value must be an object.
+    #[inline]
+    pub fn from_value(value: Value) -> Result<WhoIs, WhoIsError> {
+        match value {
+            Value::Object(map) => Self::from_inner(map, false),
+            _ => Err(WhoIsError::MapError("The server list is not an object.")),
+        }
+    }
+
+    fn from_string_inner(string: &str, strict: bool) -> Result<WhoIs, WhoIsError> {
+        let string = string.strip_prefix('\u{FEFF}').unwrap_or(string);
+
+        let list = ServerList::from_slice(string.as_bytes())?;
+
+        Self::from_server_list(list, strict)
+    }
+
+    /// A duplicate key is an error in the strict mode, otherwise the last value wins and the key is recorded.
+    fn from_server_list(list: ServerList, strict: bool) -> Result<WhoIs, WhoIsError> {
+        if strict {
+            if let Some(key) = list.duplicate_keys.into_iter().next() {
+                return Err(WhoIsError::DuplicateKey {
+                    key,
+                });
+            }
+
+            return Self::from_inner(list.map, strict);
+        }
+
+        let mut who = Self::from_inner(list.map, strict)?;
+
+        who.duplicate_keys = list.duplicate_keys;
+
+        Ok(who)
+    }
+
+    fn from_inner(mut map: Map<String, Value>, strict: bool) -> Result<WhoIs, WhoIsError> {
+        let mut ip_ranges = Vec::new();
+
+        let object_servers = match map.remove("_") {
+            Some(Value::Object(mut servers)) => {
+                // without `ip` (or with a null `ip`), IP lookups require `options.server`
+                if let Some(ranges) = servers.remove(IP_RANGES_KEY) {
+                    ip_ranges = Self::parse_ip_ranges(ranges, strict)?;
+                }
+
+                let mut object_servers = HashMap::with_capacity(servers.len());
+
+                for (object_type, server) in servers {
+                    if !server.is_null() {
+                        let key = format!("_.{}", object_type);
+
+                        object_servers
+                            .insert(object_type, server_from_entry(key, &server, strict)?);
+                    }
+                }
+
+                object_servers
+            },
+            Some(_) => {
+                return Err(WhoIsError::MapError("`_` in the server list is not an object."));
+            },
+            // a list without `_` can only be used for domain lookups
+            None => HashMap::new(),
+        };
+
+        let default_domain_server = match map.remove("") {
+            Some(server) if !server.is_null() => {
+                Some(server_from_entry(String::new(), &server, strict)?)
+            },
+            _ => None,
+        };
+
+        let mut new_map: HashMap<String, WhoIsServerValue> = HashMap::with_capacity(map.len());
+        let mut null_tlds = HashSet::new();
+
+        for (k, v) in map {
+            if v.is_null() {
+                if k.is_ascii() {
+                    null_tlds.insert(k);
+                } else {
+                    null_tlds.insert(idna::domain_to_ascii(&k).unwrap_or(k));
+                }
+            } else {
+                let server_value = server_from_entry(k.clone(), &v, strict)?;
+
+                if k.is_ascii() {
+                    new_map.insert(k, server_value);
+                } else {
+                    // an explicit `xn--` key takes precedence over its Unicode equivalent
+                    match idna::domain_to_ascii(&k) {
+                        Ok(ascii_key) => {
+                            new_map.entry(ascii_key).or_insert(server_value);
+                        },
+                        Err(_) => {
+                            new_map.insert(k, server_value);
+                        },
+                    }
+                }
+            }
+        }
+
+        // a TLD which has a server is supported even if it is also set to null via its Unicode equivalent
+        null_tlds.retain(|tld| !new_map.contains_key(tld));
+
+        Ok(WhoIs {
+            map: Arc::new(new_map),
+            null_tlds,
+            object_servers,
+            ip_ranges,
+            default_domain_server,
+            classifier: ResponseClassifier::default(),
+            resolver: None,
+            observer: None,
+            connector: None,
+            dns_server: None,
+            duplicate_keys: Vec::new(),
+            query_placeholder: String::from(DEFAULT_QUERY_PLACEHOLDER),
+        })
+    }
+
+    /// Parse the `ip_ranges` object of the `_` object, whose keys are CIDR ranges.
+    fn parse_ip_ranges(
+        ranges: Value,
+        strict: bool,
+    ) -> Result<Vec<(IpCidr, WhoIsServerValue)>, WhoIsError> {
+        let ranges = match ranges {
+            Value::Object(ranges) => ranges,
+            Value::Null => return Ok(Vec::new()),
+            _ => {
+                return Err(WhoIsError::MapError(
+                    "`ip_ranges` in the `_` object in the server list is not an object.",
+                ));
+            },
+        };
+
+        let mut ip_ranges = Vec::with_capacity(ranges.len());
+
+        for (cidr, server) in ranges {
+            if server.is_null() {
+                continue;
+            }
+
+            let key = format!("_.{}.{}", IP_RANGES_KEY, cidr);
+
+            let cidr = match IpCidr::parse(&cidr) {
+                Some(cidr) => cidr,
+                None => {
+                    return Err(WhoIsError::InvalidServer {
+                        key,
+                        source: Box::new(WhoIsError::MapError(
+                            "A key of `ip_ranges` in the `_` object in the server list is not a \
+                             correct CIDR range.",
+                        )),
+                    });
+                },
+            };
+
+            ip_ranges.push((cidr, server_from_entry(key, &server, strict)?));
+        }
+
+        ip_ranges.sort_by_key(|(cidr, _)| std::cmp::Reverse(cidr.prefix()));
+
+        Ok(ip_ranges)
+    }
+}
+
+impl WhoIs {
+    /// Insert (or replace) the WHOIS server for a TLD (or a domain suffix). An empty key sets the default domain server. A Unicode key is converted to its ASCII (`xn--`) form. The old server is returned.
+    pub fn insert_server<T: Into<String>>(
+        &mut self,
+        tld: T,
+        server: WhoIsServerValue,
+    ) -> Option<WhoIsServerValue> {
+        let mut tld = tld.into();
+
+        if tld.is_empty() {
+            return self.default_domain_server.replace(server);
+        }
+
+        if !tld.is_ascii() {
+            if let Ok(ascii_tld) = idna::domain_to_ascii(&tld) {
+                tld = ascii_tld;
+            }
+        }
+
+        self.null_tlds.remove(&tld);
+
+        Arc::make_mut(&mut self.map).insert(tld, server)
+    }
+
+    /// Check whether the list has a WHOIS server for a TLD, a domain suffix or a domain (e.g. `"io"`, `".io"` or `"www.example.io"`), trimming labels from the left like a lookup does. The default domain server is not taken into account. A Unicode input is converted to its ASCII (`xn--`) form.
+    pub fn contains_tld<T: AsRef<str>>(&self, tld: T) -> bool {
+        let tld = tld.as_ref().trim_start_matches('.');
+
+        if tld.is_ascii() {
+            self.get_listed_server_by_tld(&tld.to_ascii_lowercase()).is_some()
+        } else {
+            match idna::domain_to_ascii(tld) {
+                Ok(ascii_tld) => self.get_listed_server_by_tld(&ascii_tld).is_some(),
+                Err(_) => false,
+            }
+        }
+    }
+
+    /// Get the top-level keys which appear more than once in the list that this instance was created from. Only the last value of each of them is used. The strict constructors reject duplicate keys instead.
+    #[inline]
+    pub fn duplicate_keys(&self) -> &[String] {
+        &self.duplicate_keys
+    }
+
+    /// Get the number of TLDs (and domain suffixes) in the list. The default domain server is not counted.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Check whether the list has no TLD.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Get the WHOIS server for an object type (a key of the `_` object in the JSON data, e.g. `"ip"`).
+    #[inline]
+    pub fn object_server<T: AsRef<str>>(&self, object_type: T) -> Option<&WhoIsServerValue> {
+        self.object_servers.get(object_type.as_ref())
+    }
+
+    /// Insert (or replace) the WHOIS server for an object type (e.g. `"ip"`). The old server is returned.
+    #[inline]
+    pub fn insert_object_server<T: Into<String>>(
+        &mut self,
+        object_type: T,
+        server: WhoIsServerValue,
+    ) -> Option<WhoIsServerValue> {
+        self.object_servers.insert(object_type.into(), server)
+    }
+
+    /// Get the WHOIS server for an IP. It is the server of the most specific range in the `ip_ranges` object of the `_` object which contains the IP, or the `ip` server.
+    pub fn get_server_by_ip(&self, ip: IpAddr) -> Option<&WhoIsServerValue> {
+        self.ip_ranges
+            .iter()
+            .find(|(cidr, _)| cidr.contains(ip))
+            .map(|(_, server)| server)
+            .or_else(|| self.object_servers.get(IP_OBJECT_TYPE))
+    }
+
+    /// Insert (or replace) the WHOIS server for an IP range in CIDR notation, such as `"193.0.0.0/8"`. The old server is returned.
+    pub fn insert_ip_range_server<S: AsRef<str>>(
+        &mut self,
+        cidr: S,
+        server: WhoIsServerValue,
+    ) -> Result<Option<WhoIsServerValue>, WhoIsError> {
+        let cidr = match IpCidr::parse(cidr.as_ref()) {
+            Some(cidr) => cidr,
+            None => return Err(WhoIsError::MapError("The IP range is not a correct CIDR range.")),
+        };
+
+        if let Some((_, old)) = self.ip_ranges.iter_mut().find(|(c, _)| *c == cidr) {
+            return Ok(Some(std::mem::replace(old, server)));
+        }
+
+        let index = self.ip_ranges.partition_point(|(c, _)| c.prefix() >= cidr.prefix());
+
+        self.ip_ranges.insert(index, (cidr, server));
+
+        Ok(None)
+    }
+
+    /// Get the default WHOIS server for domains whose TLD is not in the list. It is the `""` key in the JSON data.
+    #[inline]
+    pub fn default_domain_server(&self) -> Option<&WhoIsServerValue> {
+        self.default_domain_server.as_ref()
+    }
+
+    /// Set (or unset) the default WHOIS server for domains whose TLD is not in the list.
+    #[inline]
+    pub fn set_default_domain_server(&mut self, server: Option<WhoIsServerValue>) {
+        self.default_domain_server = server;
+    }
+
+    /// Set (or unset) the DNS server (e.g. `8.8.8.8:53`) for the SRV discovery of the `can_find_server` and `discover_server` methods. If it is unset, the first `nameserver` in `/etc/resolv.conf` is used.
+    #[inline]
+    pub fn set_dns_server(&mut self, dns_server: Option<SocketAddr>) {
+        self.dns_server = dns_server;
+    }
+
+    /// Get the token in `query` templates which is replaced with the target. The default value is `$addr`.
+    #[inline]
+    pub fn query_placeholder(&self) -> &str {
+        &self.query_placeholder
+    }
+
+    /// Set the token in `query` templates which is replaced with the target, e.g. `{query}`, so that a template can contain the literal string `$addr`. It affects all `query` templates of this instance, including the servers in the list, `options.server`, `query_override` and followed referrals, while a server without a `query` still sends the target followed by `\r\n`. The strict loaders keep checking for `$addr`. An empty token is rejected.
+    pub fn set_query_placeholder<S: Into<String>>(
+        &mut self,
+        placeholder: S,
+    ) -> Result<(), WhoIsError> {
+        let placeholder = placeholder.into();
+
+        if placeholder.is_empty() {
+            return Err(WhoIsError::MapError("The query placeholder is empty."));
+        }
+
+        self.query_placeholder = placeholder;
+
+        Ok(())
+    }
+
+    /// Set a hook which observes the events (such as an unparsable referral) of this instance.
+    #[inline]
+    pub fn set_observer(&mut self, observer: Box<WhoIsObserverFn>) {
+        self.observer = Some(Observer(Arc::from(observer)));
+    }
+
+    #[inline]
+    fn notify(&self, event: WhoIsEvent) {
+        if let Some(observer) = &self.observer {
+            (observer.0)(&event);
+        }
+    }
+
+    /// Handle an error of querying a referred server. The error is returned if `fail_on_referral_error` is set, otherwise it is reported to the observer and the caller should use the previous response.
+    fn referral_failed(
+        &self,
+        referral: &WhoIsServerValue,
+        error: WhoIsError,
+        options: &WhoIsLookupOptions,
+    ) -> Result<(), WhoIsError> {
+        if options.fail_on_referral_error {
+            return Err(error);
+        }
+
+        self.notify(WhoIsEvent::ReferralFailed {
+            referral: referral.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT),
+            error:    error.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Get the result of the previous server after querying `referral` failed, like `lookup_chain` does. If there is no previous server (i.e. the first query failed) or the deadline of the referral chain has passed, the error is returned.
+    fn previous_result_or_error(
+        &self,
+        previous: Option<QueryResult>,
+        referral: &WhoIsServerValue,
+        error: WhoIsError,
+        options: &WhoIsLookupOptions,
+        (deadline, follow_deadline): (Option<Instant>, Option<Instant>),
+    ) -> Result<QueryResult, WhoIsError> {
+        match previous {
+            Some(_) if deadline_passed(deadline) => Err(error),
+            // the time budget of following referrals is exhausted, so the last response is kept
+            Some(previous) if deadline_passed(follow_deadline) => Ok(previous),
+            Some(previous) => {
+                self.referral_failed(referral, error, options)?;
+
+                Ok(previous)
+            },
+            None => Err(error),
+        }
+    }
+
+    /// Find the referral server in a query result. An empty or unparsable referral value is skipped, and a referral to the server which has been queried, or to a host which is not allowed by the options, is ignored.
+    fn find_referral(
+        &self,
+        query_result: &QueryResult,
+        options: &WhoIsLookupOptions,
+    ) -> Option<WhoIsServerValue> {
+        let server = RE_SERVER.captures_iter(&query_result.body).find_map(|c| {
+            let h = normalize_referral(c.get(3)?.as_str());
+
+            if h.is_empty() {
+                return None;
+            }
+
+            let rwhois = c.get(2).map_or(false, |scheme| scheme.as_str() == "rwhois://");
+
+            if rwhois && options.rwhois_referrals == RWhoisPolicy::Skip {
+                self.notify(WhoIsEvent::SkippedReferral {
+                    server:   query_result.addr.clone(),
+                    referral: String::from(h),
+                });
+
+                return None;
+            }
+
+            match WhoIsServerValue::from_string(h) {
+                Ok(mut server) if is_plausible_referral(&server) => {
+                    if rwhois && server.host.port.is_none() {
+                        server.host.port = Some(DEFAULT_RWHOIS_HOST_PORT);
+                    }
+
+                    Some(server)
+                },
+                _ => {
+                    self.notify(WhoIsEvent::InvalidReferral {
+                        server:   query_result.addr.clone(),
+                        referral: String::from(h),
+                    });
+
+                    None
+                },
+            }
+        })?;
+
+        let addr = server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT);
+
+        if addr == query_result.addr {
+            return None;
+        }
+
+        if let Some(allowed_referral_hosts) = &options.allowed_referral_hosts {
+            if !allowed_referral_hosts.contains(&server.host.to_host_string())
+                && !allowed_referral_hosts.contains(&addr)
+            {
+                return None;
+            }
+        }
+
+        Some(server)
+    }
+
+    /// Find the referral server which should be followed next, consuming one of the remaining `follow` times.
+    fn next_referral(
+        &self,
+        query_result: &QueryResult,
+        follow: &mut u16,
+        options: &WhoIsLookupOptions,
+    ) -> Option<WhoIsServerValue> {
+        if *follow == 0 {
+            return None;
+        }
+
+        let server = self.find_referral(query_result, options)?;
+
+        *follow -= 1;
+
+        Some(server)
+    }
+
+    /// Get the patterns used to classify response bodies.
+    #[inline]
+    pub fn classifier(&self) -> &ResponseClassifier {
+        &self.classifier
+    }
+
+    /// Replace the patterns used to classify response bodies.
+    #[inline]
+    pub fn set_classifier(&mut self, classifier: ResponseClassifier) {
+        self.classifier = classifier;
+    }
+
+    /// Set a hook which resolves a WHOIS host (and the port that will be connected) to socket addresses. It is consulted before the system resolver, and an empty result falls back to the system resolver. Both the sync and async APIs use this hook.
+    #[inline]
+    pub fn set_host_resolver(&mut self, resolver: Box<HostResolverFn>) {
+        self.resolver = Some(HostResolver(Arc::from(resolver)));
+    }
+}
+
+impl WhoIs {
+    /// Set a hook which opens the transport to a WHOIS server, such as a Unix socket, a TLS stream or an in-memory pipe, instead of a TCP connection. It is used by the blocking lookup methods, including the queries to referred servers. The resolver, the proxy and the socket timeouts are not used for such a transport, and the `socket_addr` of a `WhoIsLookupResult` is `0.0.0.0:0`.
+    #[inline]
+    pub fn set_transport_connector(&mut self, connector: Box<TransportConnectorFn>) {
+        self.connector = Some(TransportConnector(Arc::from(connector)));
+    }
+
+    /// Search the `_nicname._tcp` SRV records of the TLD (and its parent labels) on the DNS server (e.g. `"8.8.8.8:53"`), and add the found WHOIS server to the list. **Ok(false)** means that no SRV record was found (or the TLD is already in the list), while a DNS failure is an error. See `discover_server_for_tld` for a version which doesn't change the list.
+    #[inline]
+    pub fn can_find_server_for_tld<T: AsRef<str>, D: AsRef<str>>(
+        &mut self,
+        tld: T,
+        dns_server: D,
+    ) -> Result<bool, WhoIsError> {
+        let dns_server = parse_dns_server(dns_server.as_ref())?;
+
+        self.can_find_server_via(tld.as_ref(), dns_server)
+    }
+
+    /// Like `can_find_server_for_tld`, but the DNS server set by the `set_dns_server` method (or the system one) is used.
+    #[inline]
+    pub fn can_find_server<T: AsRef<str>>(&mut self, tld: T) -> Result<bool, WhoIsError> {
+        let dns_server = self.default_dns_server()?;
+
+        self.can_find_server_via(tld.as_ref(), dns_server)
+    }
+
+    fn can_find_server_via(
+        &mut self,
+        tld: &str,
+        dns_server: SocketAddr,
+    ) -> Result<bool, WhoIsError> {
+        match self.discover_server_for_tld_inner(tld, dns_server)? {
+            Some((tld, server)) => {
+                Arc::make_mut(&mut self.map).insert(tld, server);
+
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Search the `_nicname._tcp` SRV records of the TLD (and its parent labels) on the DNS server (e.g. `"8.8.8.8:53"`) like `can_find_server_for_tld`, but only return the found WHOIS server without adding it to the list, so that it works with a shared instance. The server can be added by the `insert_server` method. **Ok(None)** means that no SRV record was found (or the TLD is already in the list), while a DNS failure is an error.
+    #[inline]
+    pub fn discover_server_for_tld<T: AsRef<str>, D: AsRef<str>>(
+        &self,
+        tld: T,
+        dns_server: D,
+    ) -> Result<Option<WhoIsServerValue>, WhoIsError> {
+        let dns_server = parse_dns_server(dns_server.as_ref())?;
+
+        Ok(self.discover_server_for_tld_inner(tld.as_ref(), dns_server)?.map(|(_, server)| server))
+    }
+
+    /// Like `discover_server_for_tld`, but the DNS server set by the `set_dns_server` method (or the system one) is used.
+    #[inline]
+    pub fn discover_server<T: AsRef<str>>(
+        &self,
+        tld: T,
+    ) -> Result<Option<WhoIsServerValue>, WhoIsError> {
+        let dns_server = self.default_dns_server()?;
+
+        Ok(self.discover_server_for_tld_inner(tld.as_ref(), dns_server)?.map(|(_, server)| server))
+    }
+
+    fn default_dns_server(&self) -> Result<SocketAddr, WhoIsError> {
+        match self.dns_server {
+            Some(dns_server) => Ok(dns_server),
+            None => system_dns_server(),
+        }
+    }
+
+    /// Get the found WHOIS server together with the label whose SRV record has it.
+    fn discover_server_for_tld_inner(
+        &self,
+        mut tld: &str,
+        dns_server: SocketAddr,
+    ) -> Result<Option<(String, WhoIsServerValue)>, WhoIsError> {
+        let conn = UdpClientConnection::new(dns_server)?;
+        let client = SyncClient::new(conn);
+
+        loop {
+            if self.map.contains_key(tld) {
+                break;
+            }
+
+            match tld.find('.') {
+                Some(index) => {
+                    tld = &tld[index + 1..];
+                },
+                None => {
+                    tld = "";
+                },
+            }
+
+            if tld.is_empty() {
+                break;
+            }
+
+            let name = match Name::from_str(&format!("_nicname._tcp.{}.", tld)) {
+                Ok(name) => name,
+                // a label which cannot be in a DNS name cannot have an SRV record
+                Err(_) => continue,
+            };
+
+            let response: DnsResponse = client.query(&name, DNSClass::IN, RecordType::SRV)?;
+            let answers: &[Record] = response.answers();
+
+            for record in answers {
+                if let Some(RData::SRV(record)) = record.data() {
+                    let target = record.target().to_string();
+                    let new_server =
+                        match WhoIsServerValue::from_string(&target[..target.len() - 1]) {
+                            Ok(new_server) => new_server,
+                            Err(_error) => continue,
+                        };
+
+                    return Ok(Some((tld.to_string(), new_server)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The `tld` should be ASCII-encoded. Labels are trimmed from the left until a key is matched, and if none is matched, the default domain server is used.
+    fn get_server_by_tld(&self, tld: &str) -> Option<&WhoIsServerValue> {
+        match self.find_listed_server_by_tld(tld) {
+            Ok(Some(server)) => Some(server),
+            // falls through to the `""` server
+            Ok(None) => self.default_domain_server.as_ref(),
+            // a TLD set to null doesn't fall through
+            Err(_) => None,
+        }
+    }
+
+    #[inline]
+    fn get_listed_server_by_tld(&self, tld: &str) -> Option<&WhoIsServerValue> {
+        self.find_listed_server_by_tld(tld).ok().flatten()
+    }
+
+    /// The `tld` should be ASCII-encoded. Labels are trimmed from the left until a key is matched. `UnsupportedTld` is returned if the matched key is set to null.
+    fn find_listed_server_by_tld<'a>(
+        &self,
+        mut tld: &'a str,
+    ) -> Result<Option<&WhoIsServerValue>, UnsupportedTld<'a>> {
+        while !tld.is_empty() {
+            if let Some(server) = self.map.get(tld) {
+                return Ok(Some(server));
+            }
+
+            if self.null_tlds.contains(tld) {
+                return Err(UnsupportedTld(tld));
+            }
+
+            match tld.find('.') {
+                Some(index) => {
+                    tld = &tld[index + 1..];
+                },
+                None => {
+                    tld = "";
+                },
+            }
+        }
+
+        Ok(None)
+    }
+
+    #[inline]
+    pub(crate) fn post_process(
+        &self,
+        options: &WhoIsLookupOptions,
+        server: String,
+        body: String,
+    ) -> Result<String, WhoIsError> {
+        self.post_process_classified(options, server, body, false).map(|(body, _)| body)
+    }
+
+    /// Post-process a response body like `post_process`. The body is classified before it is rewritten if `classify` or `options.detect_rate_limit` is set, and the classification is returned so that it doesn't need to be done again.
+    fn post_process_classified(
+        &self,
+        options: &WhoIsLookupOptions,
+        server: String,
+        mut body: String,
+        classify: bool,
+    ) -> Result<ClassifiedBody<'_>, WhoIsError> {
+        let classification = if classify || options.detect_rate_limit {
+            Some(self.classifier.classify_with_pattern(&body))
+        } else {
+            None
+        };
+
+        if options.detect_rate_limit
+            && classification.map_or(false, |(class, _)| class == ResponseClass::RateLimited)
+        {
+            return Err(WhoIsError::RateLimited {
+                server,
+                body,
+            });
+        }
+
+        // skip the copy if there is nothing to rewrite
+        if options.normalize_line_endings && body.contains('\r') {
+            body = normalize_line_endings(body);
+        }
+
+        if options.trim_boilerplate {
+            body = strip_boilerplate(body);
+        }
+
+        if options.strip_percent_comments {
+            body = strip_percent_comments(body);
+        }
+
+        Ok((body, classification))
+    }
+
+    fn resolve_with_hook(
+        &self,
+        server: &WhoIsServerValue,
+    ) -> io::Result<(String, u16, Option<Vec<SocketAddr>>)> {
+        let host = server.host.to_host_string();
+        let port = server.host.port.unwrap_or(DEFAULT_WHOIS_HOST_PORT);
+
+        if let Some(resolver) = &self.resolver {
+            let socket_addrs = (resolver.0)(&host, port)?;
+
+            // an empty result means that the hook doesn't know this host
+            if !socket_addrs.is_empty() {
+                return Ok((host, port, Some(socket_addrs)));
+            }
+        }
+
+        Ok((host, port, None))
+    }
+
+    fn resolve(&self, server: &WhoIsServerValue) -> io::Result<Vec<SocketAddr>> {
+        let socket_addrs = match self.resolve_with_hook(server)? {
+            (_, _, Some(socket_addrs)) => socket_addrs,
+            (host, port, None) => (host.as_str(), port).to_socket_addrs()?.collect(),
+        };
+
+        check_socket_addrs(socket_addrs)
+    }
+
+    #[inline]
+    pub(crate) fn select_server<'a>(
+        &'a self,
+        options: &'a WhoIsLookupOptions,
+    ) -> Result<(Cow<'a, WhoIsServerValue>, Cow<'a, str>), WhoIsError> {
+        self.select_server_for(&options.target, options)
+    }
+
+    /// Select the server for `target`, with the `query_override` and `preserve_case` of `options` applied. The target of `options` is ignored.
+    pub(crate) fn select_server_for<'a>(
+        &'a self,
+        target: &'a Target,
+        options: &'a WhoIsLookupOptions,
+    ) -> Result<(Cow<'a, WhoIsServerValue>, Cow<'a, str>), WhoIsError> {
+        let (server, text) = self.route(target, options)?;
+
+        Ok(apply_server_options(server, target, text, options))
+    }
+
+    fn route<'a>(
+        &'a self,
+        target: &'a Target,
+        options: &'a WhoIsLookupOptions,
+    ) -> Result<(&'a WhoIsServerValue, Cow<'a, str>), WhoIsError> {
+        if options.raw_target.is_some() {
+            return match &options.server {
+                Some(server) => Ok((server, query_text(target, options))),
+                None => Err(WhoIsError::MapError(
+                    "A raw target needs a whois server; set one via `options.server`.",
+                )),
+            };
+        }
+
+        match target.host() {
+            host @ (Host::IPv4(_) | Host::IPv6(_)) => {
+                let ip = match host {
+                    Host::IPv4(ip) => IpAddr::V4(*ip),
+                    Host::IPv6(ip) => IpAddr::V6(*ip),
+                    Host::Domain(_) => unreachable!(),
+                };
+
+                let server = match &options.server {
+                    Some(server) => server,
+                    None => match self.get_server_by_ip(ip) {
+                        Some(server) => server,
+                        None => {
+                            return Err(WhoIsError::MapError(
+                                "No whois server configured for IP lookups; set one via \
+                                 options.server",
+                            ));
+                        },
+                    },
+                };
+
+                Ok((server, target_text(target)))
+            },
+            Host::Domain(domain) => {
+                let server = match &options.server {
+                    Some(server) => server,
+                    None => match self.get_server_by_tld(domain) {
+                        Some(server) => server,
+                        None => {
+                            if let Err(UnsupportedTld(tld)) = self.find_listed_server_by_tld(domain)
+                            {
+                                return Err(WhoIsError::UnsupportedTld {
+                                    tld: String::from(tld)
+                                });
+                            }
+
+                            return Err(WhoIsError::MapError(
+                                "No whois server is known for this kind of object.",
+                            ));
+                        },
+                    },
+                };
+
+                Ok((server, target_text(target)))
+            },
+        }
+    }
+
+    /// Connect to the server, or to the proxy if there is one. The proxy handshake is up to the caller.
+    pub(crate) fn connect(
+        &self,
+        server: &WhoIsServerValue,
+        timeout: Option<Duration>,
+        nodelay: bool,
+        proxy: Option<&Proxy>,
+    ) -> io::Result<TcpStream> {
+        let socket_addrs = match proxy {
+            Some(proxy) => {
+                check_socket_addrs((proxy.host.as_str(), proxy.port).to_socket_addrs()?.collect())?
+            },
+            None => self.resolve(server)?,
+        };
+
+        let client = if let Some(timeout) = timeout {
+            let mut client = None;
+
+            for socket_addr in socket_addrs.iter().take(socket_addrs.len() - 1) {
+                if let Ok(c) = TcpStream::connect_timeout(socket_addr, timeout) {
+                    client = Some(c);
+                    break;
+                }
+            }
+
+            let client = if let Some(client) = client {
+                client
+            } else {
+                let socket_addr = &socket_addrs[socket_addrs.len() - 1];
+                TcpStream::connect_timeout(socket_addr, timeout)?
+            };
+
+            client.set_read_timeout(Some(timeout))?;
+            client.set_write_timeout(Some(timeout))?;
+            client
+        } else {
+            TcpStream::connect(socket_addrs.as_slice())?
+        };
+
+        client.set_nodelay(nodelay)?;
+
+        Ok(client)
+    }
+
+    fn connect_and_query(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        deadline: Option<Instant>,
+    ) -> Result<(String, TcpStream), WhoIsError> {
+        let prepared = PreparedQuery::new(server, text, &self.query_placeholder, options)?;
+
+        if deadline_passed(deadline) {
+            return Err(WhoIsError::Timeout {
+                addr: prepared.addr
+            });
+        }
+
+        let with_addr = |source| WhoIsError::from_io_with_addr(&prepared.addr, source);
+
+        let mut client = self
+            .connect(
+                server,
+                remaining_time(options.socket_timeout(server), deadline),
+                options.tcp_nodelay,
+                prepared.proxy.as_ref(),
+            )
+            .map_err(with_addr)?;
+
+        if let Some(proxy) = &prepared.proxy {
+            proxy.handshake(&mut client, &prepared.host, prepared.port).map_err(with_addr)?;
+        }
+
+        write_query(&mut client, &prepared.query, options.tolerate_write_errors)
+            .map_err(with_addr)?;
+
+        Ok((prepared.addr, client))
+    }
+
+    /// Query a server. The query is bounded by both `max_total_duration` and the deadline of the referral chain.
+    fn lookup_once(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        chain_deadline: Option<Instant>,
+    ) -> Result<QueryResult, WhoIsError> {
+        if let Some(connector) = &self.connector {
+            let prepared = PreparedQuery::new(server, text, &self.query_placeholder, options)?;
+
+            let transport = (connector.0)(server)
+                .map_err(|source| WhoIsError::from_io_with_addr(&prepared.addr, source))?;
+
+            return Self::lookup_once_over(transport, &prepared, server, options);
+        }
+
+        let deadline = earliest(options.query_deadline(), chain_deadline);
+
+        let (addr, mut client) = self.connect_and_query(server, text, options, deadline)?;
+
+        let with_addr = |source| WhoIsError::from_io_with_addr(&addr, source);
+
+        let socket_addr = client.peer_addr().map_err(with_addr)?;
+
+        let mut data = Vec::with_capacity(options.read_capacity);
+
+        let timeout = options.socket_timeout(server);
+
+        match Terminator::of(server)? {
+            Some(terminator) => {
+                read_until_terminator(&mut client, &mut data, &terminator, |client| {
+                    set_remaining_read_timeout(client, timeout, deadline)
+                })
+            },
+            None => copy_until(&mut client, &mut data, timeout, deadline),
+        }
+        .map_err(with_addr)?;
+
+        let body = decode_body(data, server.body_charset()).map_err(with_addr)?;
+
+        Ok(QueryResult {
+            addr,
+            socket_addr,
+            body,
+        })
+    }
+
+    /// Send a query over a transport and read the response until the transport reaches EOF. Timeouts are up to the transport.
+    fn lookup_once_over(
+        mut transport: Box<dyn WhoIsTransport>,
+        prepared: &PreparedQuery,
+        server: &WhoIsServerValue,
+        options: &WhoIsLookupOptions,
+    ) -> Result<QueryResult, WhoIsError> {
+        let with_addr = |source| WhoIsError::from_io_with_addr(&prepared.addr, source);
+
+        write_query(&mut transport, &prepared.query, options.tolerate_write_errors)
+            .map_err(with_addr)?;
+
+        let mut data = Vec::with_capacity(options.read_capacity);
+
+        match Terminator::of(server)? {
+            Some(terminator) => {
+                read_until_terminator(&mut transport, &mut data, &terminator, |_| Ok(()))
+            },
+            None => transport.read_to_end(&mut data).map(|_| ()),
+        }
+        .map_err(with_addr)?;
+
+        Ok(QueryResult {
+            addr:        prepared.addr.clone(),
+            socket_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            body:        decode_body(data, server.body_charset()).map_err(with_addr)?,
+        })
+    }
+
+    fn lookup_inner(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+    ) -> Result<(QueryResult, Vec<String>), WhoIsError> {
+        let hops = self.lookup_chain(server, options, |server, deadline| {
+            self.lookup_once(server, text, options, deadline)
+        })?;
+
+        Ok(split_hops(hops))
+    }
+
+    /// Query the server with `query` and follow the referrals. `query` is called with a server and the deadline of the referral chain. The results of all hops are returned in order.
+    pub(crate) fn lookup_chain<F>(
+        &self,
+        server: &WhoIsServerValue,
+        options: &WhoIsLookupOptions,
+        mut query: F,
+    ) -> Result<Vec<QueryResult>, WhoIsError>
+    where
+        F: FnMut(&WhoIsServerValue, Option<Instant>) -> Result<QueryResult, WhoIsError>, {
+        let deadline = options.chain_deadline();
+        let mut follow = options.follow_limit();
+
+        let mut hops = vec![query(server, deadline)?];
+
+        let follow_deadline = options.follow_deadline();
+        let hop_deadline = earliest(deadline, follow_deadline);
+
+        while let Some(server) = self.next_referral(last_hop(&hops), &mut follow, options) {
+            // without a delay, `remaining_time` would be the whole remaining time
+            if let Some(delay) =
+                options.follow_delay.and_then(|delay| remaining_time(Some(delay), hop_deadline))
+            {
+                thread::sleep(delay);
+            }
+
+            match query(&server, hop_deadline) {
+                Ok(referred_result) => hops.push(referred_result),
+                Err(error) if deadline_passed(deadline) => return Err(error),
+                // the time budget of following referrals is exhausted, so the last response is kept
+                Err(_) if deadline_passed(follow_deadline) => break,
+                Err(error) => {
+                    self.referral_failed(&server, error, options)?;
+
+                    break;
+                },
+            }
+        }
+
+        Ok(hops)
+    }
+
+    fn lookup_to_writer_inner<W: Write>(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        out: &mut W,
+    ) -> Result<(), WhoIsError> {
+        // the time budget of following referrals starts after the first query
+        let mut deadlines = (options.chain_deadline(), None);
+
+        let mut follow = options.follow_limit();
+        let mut server = Cow::Borrowed(server);
+        let mut previous: Option<QueryResult> = None;
+        // the first query is only bounded by the deadline of the whole chain
+        let mut hop_deadline = deadlines.0;
+
+        loop {
+            if follow == 0
+                && self.connector.is_none()
+                && can_stream(&server)
+                && !options.post_processes()
+            {
+                // no more referrals will be followed, so this response can be streamed
+                let deadline = earliest(options.query_deadline(), hop_deadline);
+
+                let (addr, mut client) =
+                    match self.connect_and_query(&server, text, options, deadline) {
+                        Ok(connection) => connection,
+                        Err(error) => {
+                            let previous = self.previous_result_or_error(
+                                previous, &server, error, options, deadlines,
+                            )?;
+
+                            let body = self.post_process(options, previous.addr, previous.body)?;
+
+                            out.write_all(body.as_bytes())?;
+
+                            return Ok(());
+                        },
+                    };
+
+                copy_until(&mut client, out, options.socket_timeout(&server), deadline)
+                    .map_err(|error| WhoIsError::from_io_with_addr(&addr, error))?;
+
+                return Ok(());
+            }
+
+            let query_result = match self.lookup_once(&server, text, options, hop_deadline) {
+                Ok(query_result) => query_result,
+                Err(error) => {
+                    let previous = self
+                        .previous_result_or_error(previous, &server, error, options, deadlines)?;
+
+                    let body = self.post_process(options, previous.addr, previous.body)?;
+
+                    out.write_all(body.as_bytes())?;
+
+                    return Ok(());
+                },
+            };
+
+            match self.next_referral(&query_result, &mut follow, options) {
+                Some(referral) => {
+                    if previous.is_none() {
+                        deadlines.1 = options.follow_deadline();
+                    }
+
+                    hop_deadline = earliest(deadlines.0, deadlines.1);
+
+                    if let Some(delay) = options
+                        .follow_delay
+                        .and_then(|delay| remaining_time(Some(delay), hop_deadline))
+                    {
+                        thread::sleep(delay);
+                    }
+
+                    server = Cow::Owned(referral);
+                    previous = Some(query_result);
+                },
+                None => {
+                    let body = self.post_process(options, query_result.addr, query_result.body)?;
+
+                    out.write_all(body.as_bytes())?;
+
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    /// Lookup a domain or an IP.
+    #[inline]
+    pub fn lookup(&self, options: WhoIsLookupOptions) -> Result<String, WhoIsError> {
+        self.lookup_target(&options.target, &options)
+    }
+
+    /// Lookup a domain or an IP with the default options, e.g. `who.lookup_str("magiclen.org")`.
+    #[inline]
+    pub fn lookup_str<S: AsRef<str>>(&self, target: S) -> Result<String, WhoIsError> {
+        self.lookup(WhoIsLookupOptions::from_str(target)?)
+    }
+
+    /// Lookup a domain with the default options, and extract the common fields of the response body. See the `parse_domain_record` function.
+    #[inline]
+    pub fn lookup_domain_record<S: AsRef<str>>(
+        &self,
+        target: S,
+    ) -> Result<DomainRecord, WhoIsError> {
+        self.lookup_str(target).map(parse_domain_record)
+    }
+
+    /// Lookup an IP with the default options, and extract the common fields of the response body. See the `parse_ip_record` function.
+    #[inline]
+    pub fn lookup_ip_record<S: AsRef<str>>(&self, target: S) -> Result<IpRecord, WhoIsError> {
+        self.lookup_str(target).map(parse_ip_record)
+    }
+
+    /// Lookup `target` with borrowed options, so that the same options can be reused for several targets. The target of `options` is ignored.
+    #[inline]
+    pub fn lookup_target(
+        &self,
+        target: &Target,
+        options: &WhoIsLookupOptions,
+    ) -> Result<String, WhoIsError> {
+        self.lookup_target_classified(target, options, false).map(|(body, _)| body)
+    }
+
+    fn lookup_target_classified(
+        &self,
+        target: &Target,
+        options: &WhoIsLookupOptions,
+        classify: bool,
+    ) -> Result<ClassifiedBody<'_>, WhoIsError> {
+        #[cfg(feature = "system-whois")]
+        if options.system_fallback && options.server.is_none() {
+            if let (None, Host::Domain(domain)) = (&options.raw_target, target.host()) {
+                // a TLD set to null is not looked up by the system command either
+                if self.get_server_by_tld(domain).is_none()
+                    && self.find_listed_server_by_tld(domain).is_ok()
+                {
+                    let body = system_lookup(domain)?;
+
+                    return self.post_process_classified(
+                        options,
+                        String::from("whois"),
+                        body,
+                        classify,
+                    );
+                }
+            }
+        }
+
+        let (server, text) = self.select_server_for(target, options)?;
+
+        let (query_result, _) = self.lookup_inner(&server, &text, options)?;
+
+        self.post_process_classified(options, query_result.addr, query_result.body, classify)
+    }
+
+    /// Lookup `target` on an already chosen server (e.g. one got by `get_server_by_tld` and cached), skipping the server list and `options.server`. The target of `options` is ignored, while referrals are still followed according to `options.follow`, and `query_override` and `preserve_case` still apply.
+    pub fn lookup_with(
+        &self,
+        target: &Target,
+        server: &WhoIsServerValue,
+        options: &WhoIsLookupOptions,
+    ) -> Result<String, WhoIsError> {
+        let (server, text) =
+            apply_server_options(server, target, query_text(target, options), options);
+
+        let (query_result, _) = self.lookup_inner(&server, &text, options)?;
+
+        self.post_process(options, query_result.addr, query_result.body)
+    }
+
+    /// Lookup a domain or an IP, and get the response body together with the information about which server responded.
+    pub fn lookup_detailed(
+        &self,
+        options: WhoIsLookupOptions,
+    ) -> Result<WhoIsLookupResult, WhoIsError> {
+        let (server, text) = self.select_server(&options)?;
+
+        let (query_result, chain) = self.lookup_inner(&server, &text, &options)?;
+
+        self.detailed_result(&options, query_result, chain)
+    }
+
+    /// Lookup a domain or an IP, and get the server (in the `host:port` form) and the response body of every hop in the referral chain, in order, e.g. both the thin registry record and the thick registrar record.
+    pub fn lookup_all(
+        &self,
+        options: WhoIsLookupOptions,
+    ) -> Result<Vec<(String, String)>, WhoIsError> {
+        let (server, text) = self.select_server(&options)?;
+
+        let hops = self.lookup_chain(&server, &options, |server, deadline| {
+            self.lookup_once(server, &text, &options, deadline)
+        })?;
+
+        self.all_results(&options, hops)
+    }
+
+    fn all_results(
+        &self,
+        options: &WhoIsLookupOptions,
+        hops: Vec<QueryResult>,
+    ) -> Result<Vec<(String, String)>, WhoIsError> {
+        hops.into_iter()
+            .map(|hop| {
+                let body = self.post_process(options, hop.addr.clone(), hop.body)?;
+
+                Ok((hop.addr, body))
+            })
+            .collect()
+    }
+
+    fn detailed_result(
+        &self,
+        options: &WhoIsLookupOptions,
+        query_result: QueryResult,
+        chain: Vec<String>,
+    ) -> Result<WhoIsLookupResult, WhoIsError> {
+        let body = self.post_process(options, query_result.addr.clone(), query_result.body)?;
+
+        Ok(WhoIsLookupResult {
+            body,
+            server: query_result.addr,
+            socket_addr: query_result.socket_addr,
+            followed: chain.len() > 1,
+            // the chain is bounded by `max_connections`
+            hops: (chain.len() - 1) as u16,
+            chain,
+        })
+    }
+
+    /// Lookup a domain or an IP, and write the response body into a writer. Responses which may contain a referral that will be followed are buffered, and the response of the last server is streamed into the writer as the bytes arrive, unless it has a terminator or another charset than UTF-8, or a post-processing option (`detect_rate_limit`, `trim_boilerplate`, `normalize_line_endings` or `strip_percent_comments`) is set. A post-processed body is read completely and processed like in `lookup` before it is written, so a rate-limit response is returned as a `WhoIsError::RateLimited` error without writing anything. The body is always written in UTF-8.
+    #[inline]
+    pub fn lookup_to_writer<W: Write>(
+        &self,
+        options: WhoIsLookupOptions,
+        mut out: W,
+    ) -> Result<(), WhoIsError> {
+        let (server, text) = self.select_server(&options)?;
+
+        self.lookup_to_writer_inner(&server, &text, &options, &mut out)?;
+
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Lookup several targets on one connection to the same WHOIS server, such as `whois.arin.net` which accepts multiple queries per connection. The concatenated responses are split by assuming that every response starts with the same header line. This only works for servers which keep the connection open between queries; if the responses cannot be split into one response per target, every result is an error. Referrals are not followed, and the default timeout is used unless the server has its own timeout.
+    pub fn lookup_batch_same_server(
+        &self,
+        server: &WhoIsServerValue,
+        targets: &[Target],
+    ) -> Vec<Result<String, WhoIsError>> {
+        if targets.is_empty() {
+            return Vec::new();
+        }
+
+        let body = match self.lookup_batch_inner(server, targets) {
+            Ok(body) => body,
+            Err(error) => {
+                let mut results = Vec::with_capacity(targets.len());
+
+                results.push(Err(error));
+
+                for _ in 1..targets.len() {
+                    results.push(Err(WhoIsError::MapError(
+                        "The batch query failed on the shared connection.",
+                    )));
+                }
+
+                return results;
             },
-            Host::Domain(domain) => {
-                let server = match &options.server {
-                    Some(server) => server,
-                    None => match self.get_server_by_tld(domain.as_str()) {
-                        Some(server) => server,
-                        None => {
-                            return Err(WhoIsError::MapError(
-                                "No whois server is known for this kind of object.",
-                            ));
-                        },
+        };
+
+        match split_batch_response(&body, targets.len()) {
+            Some(bodies) => bodies.into_iter().map(Ok).collect(),
+            None => targets
+                .iter()
+                .map(|_| {
+                    Err(WhoIsError::MapError(
+                        "The batch response cannot be split into one response per target.",
+                    ))
+                })
+                .collect(),
+        }
+    }
+
+    fn lookup_batch_inner(
+        &self,
+        server: &WhoIsServerValue,
+        targets: &[Target],
+    ) -> Result<String, WhoIsError> {
+        let timeout = server.timeout.unwrap_or(Duration::from_millis(DEFAULT_TIMEOUT));
+
+        let addr = server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT);
+
+        let with_addr = |source| WhoIsError::from_io_with_addr(&addr, source);
+
+        let mut client = self.connect(server, Some(timeout), true, None).map_err(with_addr)?;
+
+        for target in targets {
+            check_target_text(&target_text(target))?;
+        }
+
+        for target in targets {
+            let query = encode_query(server, &target_text(target), &self.query_placeholder, None)
+                .map_err(with_addr)?;
+
+            client.write_all(&query).map_err(with_addr)?;
+        }
+
+        client.flush().map_err(with_addr)?;
+
+        // tell the server that there are no more queries
+        client.shutdown(Shutdown::Write).map_err(with_addr)?;
+
+        let mut data = Vec::with_capacity(DEFAULT_READ_CAPACITY);
+
+        client.read_to_end(&mut data).map_err(with_addr)?;
+
+        let body = decode_body(data, server.body_charset()).map_err(with_addr)?;
+
+        Ok(body)
+    }
+
+    /// Get the exact query which would be sent to the server for the target, without connecting.
+    #[inline]
+    pub fn build_query(&self, target: &Target, server: &WhoIsServerValue) -> String {
+        make_query(server, &target_text(target), &self.query_placeholder)
+    }
+
+    /// Get every configured server, keyed by the `host:port` string so that a server used by many TLDs appears once.
+    fn distinct_servers(&self) -> BTreeMap<String, &WhoIsServerValue> {
+        self.map
+            .values()
+            .chain(self.object_servers.values())
+            .chain(self.ip_ranges.iter().map(|(_, server)| server))
+            .chain(self.default_domain_server.as_ref())
+            .map(|server| (server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT), server))
+            .collect()
+    }
+
+    /// Get the distinct endpoints (in the `host:port` form, e.g. `"whois.verisign-grs.com:43"`) of the servers in the list, including the servers in the `_` object (e.g. `ip`), the `ip_ranges` servers and the `""` server, e.g. to configure a rate limit or a connection pool per host.
+    #[inline]
+    pub fn server_hosts(&self) -> HashSet<String> {
+        self.distinct_servers().into_keys().collect()
+    }
+
+    /// Try to connect (without sending a query) to each distinct configured server, and report its reachability. Up to `HEALTH_CHECK_THREADS` servers are checked at the same time on their own threads. The results are sorted by the `host:port` strings of the servers, regardless of which check finishes first.
+    pub fn health_check(&self, timeout: Duration) -> Vec<(String, Result<(), WhoIsError>)> {
+        let servers: Vec<(String, &WhoIsServerValue)> =
+            self.distinct_servers().into_iter().collect();
+
+        let mut results = Vec::with_capacity(servers.len());
+
+        for chunk in servers.chunks(HEALTH_CHECK_THREADS) {
+            thread::scope(|scope| {
+                let checks: Vec<_> = chunk
+                    .iter()
+                    .map(|(addr, server)| {
+                        scope.spawn(move || {
+                            self.connect(server, Some(timeout), true, None)
+                                .map(|_| ())
+                                .map_err(|source| WhoIsError::from_io_with_addr(addr, source))
+                        })
+                    })
+                    .collect();
+
+                for ((addr, _), check) in chunk.iter().zip(checks) {
+                    let result = match check.join() {
+                        Ok(result) => result,
+                        Err(panic) => std::panic::resume_unwind(panic),
+                    };
+
+                    results.push((addr.clone(), result));
+                }
+            });
+        }
+
+        results
+    }
+
+    /// Lookup a domain or an IP, and classify the response body before it is post-processed (e.g. by `trim_boilerplate`), the same body that `detect_rate_limit` checks. The pattern of the classifier which matched is returned as well, see `ResponseClassifier::classify_with_pattern`.
+    #[inline]
+    pub fn lookup_classified(
+        &self,
+        options: WhoIsLookupOptions,
+    ) -> Result<(String, ResponseClass, Option<String>), WhoIsError> {
+        let (body, classification) =
+            self.lookup_target_classified(&options.target, &options, true)?;
+        let (class, pattern) = classification.expect("the body is classified");
+        let pattern = pattern.map(String::from);
+
+        Ok((body, class, pattern))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl WhoIs {
+    async fn resolve_async(&self, server: &WhoIsServerValue) -> io::Result<Vec<SocketAddr>> {
+        let socket_addrs = match self.resolve_with_hook(server)? {
+            (_, _, Some(socket_addrs)) => socket_addrs,
+            (host, port, None) => tokio::net::lookup_host((host.as_str(), port)).await?.collect(),
+        };
+
+        check_socket_addrs(socket_addrs)
+    }
+
+    /// Like `connect_and_query`, connecting, the proxy handshake and writing the query are all bounded by `deadline`.
+    async fn connect_and_query_async(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        deadline: Option<Instant>,
+    ) -> Result<(String, tokio::net::TcpStream), WhoIsError> {
+        let PreparedQuery {
+            addr,
+            host,
+            port,
+            proxy,
+            query,
+        } = PreparedQuery::new(server, text, &self.query_placeholder, options)?;
+
+        if deadline_passed(deadline) {
+            return Err(WhoIsError::Timeout {
+                addr,
+            });
+        }
+
+        let timeout = || remaining_time(options.socket_timeout(server), deadline);
+
+        let resolve = async {
+            match &proxy {
+                Some(proxy) => check_socket_addrs(
+                    tokio::net::lookup_host((proxy.host.as_str(), proxy.port)).await?.collect(),
+                ),
+                None => self.resolve_async(server).await,
+            }
+        };
+
+        let socket_addrs = within_async(&addr, remaining_time(None, deadline), resolve).await?;
+
+        let mut client = None;
+
+        for socket_addr in socket_addrs.iter().take(socket_addrs.len() - 1) {
+            // a timed out address is skipped like a refused one
+            if let Ok(c) =
+                within_async(&addr, timeout(), tokio::net::TcpStream::connect(socket_addr)).await
+            {
+                client = Some(c);
+                break;
+            }
+        }
+
+        let mut client = if let Some(client) = client {
+            client
+        } else {
+            let socket_addr = &socket_addrs[socket_addrs.len() - 1];
+
+            within_async(&addr, timeout(), tokio::net::TcpStream::connect(socket_addr)).await?
+        };
+
+        client
+            .set_nodelay(options.tcp_nodelay)
+            .map_err(|source| WhoIsError::from_io_with_addr(&addr, source))?;
+
+        if let Some(proxy) = &proxy {
+            within_async(&addr, timeout(), proxy.handshake_async(&mut client, &host, port)).await?;
+        }
+
+        within_async(
+            &addr,
+            timeout(),
+            write_query_async(&mut client, &query, options.tolerate_write_errors),
+        )
+        .await?;
+
+        Ok((addr, client))
+    }
+
+    async fn lookup_inner_once_async(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+    ) -> Result<QueryResult, WhoIsError> {
+        let deadline = options.query_deadline();
+
+        let (addr, mut client) =
+            self.connect_and_query_async(server, text, options, deadline).await?;
+
+        let with_addr = |source| WhoIsError::from_io_with_addr(&addr, source);
+
+        let socket_addr = client.peer_addr().map_err(with_addr)?;
+
+        let mut data = Vec::with_capacity(options.read_capacity);
+
+        let terminator = Terminator::of(server)?;
+
+        let read = read_until_terminator_async(&mut client, &mut data, terminator.as_ref());
+
+        within_async(&addr, remaining_time(options.socket_timeout(server), deadline), read).await?;
+
+        let body = decode_body(data, server.body_charset()).map_err(with_addr)?;
+
+        Ok(QueryResult {
+            addr,
+            socket_addr,
+            body,
+        })
+    }
+
+    /// Like `lookup_inner_once_async`, but a `WhoIsError::Timeout` error is returned if the deadline of the referral chain passes.
+    async fn lookup_inner_once_before_async(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        deadline: Option<Instant>,
+    ) -> Result<QueryResult, WhoIsError> {
+        let query = self.lookup_inner_once_async(server, text, options);
+
+        match deadline {
+            Some(deadline) => {
+                tokio::time::timeout_at(deadline.into(), query).await.map_err(|_| {
+                    WhoIsError::Timeout {
+                        addr: server.host.to_addr_string(DEFAULT_WHOIS_HOST_PORT),
+                    }
+                })?
+            },
+            None => query.await,
+        }
+    }
+
+    async fn lookup_inner_async(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+    ) -> Result<(QueryResult, Vec<String>), WhoIsError> {
+        let hops = self.lookup_hops_async(server, text, options).await?;
+
+        Ok(split_hops(hops))
+    }
+
+    async fn lookup_hops_async(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+    ) -> Result<Vec<QueryResult>, WhoIsError> {
+        let deadline = options.chain_deadline();
+        let mut follow = options.follow_limit();
+
+        let mut hops =
+            vec![self.lookup_inner_once_before_async(server, text, options, deadline).await?];
+
+        let follow_deadline = options.follow_deadline();
+        let hop_deadline = earliest(deadline, follow_deadline);
+
+        while let Some(server) = self.next_referral(last_hop(&hops), &mut follow, options) {
+            // without a delay, `remaining_time` would be the whole remaining time
+            if let Some(delay) =
+                options.follow_delay.and_then(|delay| remaining_time(Some(delay), hop_deadline))
+            {
+                tokio::time::sleep(delay).await;
+            }
+
+            match self.lookup_inner_once_before_async(&server, text, options, hop_deadline).await {
+                Ok(referred_result) => hops.push(referred_result),
+                Err(error) if deadline_passed(deadline) => return Err(error),
+                // the time budget of following referrals is exhausted, so the last response is kept
+                Err(_) if deadline_passed(follow_deadline) => break,
+                Err(error) => {
+                    self.referral_failed(&server, error, options)?;
+
+                    break;
+                },
+            }
+        }
+
+        Ok(hops)
+    }
+
+    /// Write the response body of the last server into `out`, see `lookup_to_writer`. The address of the server is returned.
+    async fn lookup_to_async_writer_inner<W: AsyncWrite + Unpin>(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        out: &mut W,
+    ) -> Result<String, WhoIsError> {
+        // the time budget of following referrals starts after the first query
+        let mut deadlines = (options.chain_deadline(), None);
+
+        let mut follow = options.follow_limit();
+        let mut server = Cow::Borrowed(server);
+        let mut previous: Option<QueryResult> = None;
+        // the first query is only bounded by the deadline of the whole chain
+        let mut hop_deadline = deadlines.0;
+
+        loop {
+            if follow == 0 && can_stream(&server) && !options.post_processes() {
+                // no more referrals will be followed, so this response can be streamed
+                let deadline = earliest(options.query_deadline(), hop_deadline);
+
+                let connection =
+                    self.connect_and_query_async(&server, text, options, deadline).await;
+
+                let (addr, mut client) = match connection {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        let previous = self.previous_result_or_error(
+                            previous, &server, error, options, deadlines,
+                        )?;
+
+                        let body =
+                            self.post_process(options, previous.addr.clone(), previous.body)?;
+
+                        out.write_all(body.as_bytes()).await?;
+
+                        return Ok(previous.addr);
                     },
                 };
 
-                // punycode check is not necessary because the domain has been ascii-encoded
+                let with_addr = |source| WhoIsError::from_io_with_addr(&addr, source);
 
-                Self::lookup_inner_async(server, domain, options.timeout, options.follow).await
-            },
+                if let Some(timeout) = remaining_time(options.socket_timeout(&server), deadline) {
+                    tokio::time::timeout(timeout, tokio::io::copy(&mut client, out))
+                        .await
+                        .map_err(|_| WhoIsError::Timeout {
+                            addr: addr.clone()
+                        })?
+                        .map_err(with_addr)?;
+                } else {
+                    tokio::io::copy(&mut client, out).await.map_err(with_addr)?;
+                }
+
+                return Ok(addr);
+            }
+
+            let query_result = match self
+                .lookup_inner_once_before_async(&server, text, options, hop_deadline)
+                .await
+            {
+                Ok(query_result) => query_result,
+                Err(error) => {
+                    let previous = self
+                        .previous_result_or_error(previous, &server, error, options, deadlines)?;
+
+                    let body = self.post_process(options, previous.addr.clone(), previous.body)?;
+
+                    out.write_all(body.as_bytes()).await?;
+
+                    return Ok(previous.addr);
+                },
+            };
+
+            match self.next_referral(&query_result, &mut follow, options) {
+                Some(referral) => {
+                    if previous.is_none() {
+                        deadlines.1 = options.follow_deadline();
+                    }
+
+                    hop_deadline = earliest(deadlines.0, deadlines.1);
+
+                    if let Some(delay) = options
+                        .follow_delay
+                        .and_then(|delay| remaining_time(Some(delay), hop_deadline))
+                    {
+                        tokio::time::sleep(delay).await;
+                    }
+
+                    server = Cow::Owned(referral);
+                    previous = Some(query_result);
+                },
+                None => {
+                    let body =
+                        self.post_process(options, query_result.addr.clone(), query_result.body)?;
+
+                    out.write_all(body.as_bytes()).await?;
+
+                    return Ok(query_result.addr);
+                },
+            }
+        }
+    }
+
+    /// Lookup a domain or an IP by running `lookup_async` on a new current-thread runtime, for a synchronous context in a codebase which uses the async APIs. If it is called from within a Tokio runtime, the lookup runs on another thread, and the current thread is blocked until it finishes.
+    pub fn lookup_blocking(&self, options: WhoIsLookupOptions) -> Result<String, WhoIsError> {
+        let run = move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(self.lookup_async(options))
+        };
+
+        if tokio::runtime::Handle::try_current().is_err() {
+            return run();
         }
+
+        // a runtime cannot be started on a thread which is driving another one
+        thread::scope(|scope| match scope.spawn(run).join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        })
+    }
+
+    /// Lookup a domain or an IP.
+    #[inline]
+    pub async fn lookup_async(&self, options: WhoIsLookupOptions) -> Result<String, WhoIsError> {
+        self.lookup_target_async(&options.target, &options).await
+    }
+
+    /// Lookup a domain or an IP with the default options. See `lookup_str`.
+    #[inline]
+    pub async fn lookup_str_async<S: AsRef<str>>(&self, target: S) -> Result<String, WhoIsError> {
+        self.lookup_async(WhoIsLookupOptions::from_str(target)?).await
+    }
+
+    /// Lookup `target` with borrowed options. See `lookup_target`.
+    #[inline]
+    pub async fn lookup_target_async(
+        &self,
+        target: &Target,
+        options: &WhoIsLookupOptions,
+    ) -> Result<String, WhoIsError> {
+        self.lookup_target_classified_async(target, options, false).await.map(|(body, _)| body)
+    }
+
+    async fn lookup_target_classified_async(
+        &self,
+        target: &Target,
+        options: &WhoIsLookupOptions,
+        classify: bool,
+    ) -> Result<ClassifiedBody<'_>, WhoIsError> {
+        let (server, text) = self.select_server_for(target, options)?;
+
+        let (query_result, _) = self.lookup_inner_async(&server, &text, options).await?;
+
+        self.post_process_classified(options, query_result.addr, query_result.body, classify)
+    }
+
+    /// Lookup a domain or an IP, and get the response body together with the information about which server responded. See `lookup_detailed`.
+    pub async fn lookup_detailed_async(
+        &self,
+        options: WhoIsLookupOptions,
+    ) -> Result<WhoIsLookupResult, WhoIsError> {
+        let (server, text) = self.select_server(&options)?;
+
+        let (query_result, chain) = self.lookup_inner_async(&server, &text, &options).await?;
+
+        self.detailed_result(&options, query_result, chain)
+    }
+
+    /// Lookup a domain or an IP, and get the server and the response body of every hop in the referral chain. See `lookup_all`.
+    pub async fn lookup_all_async(
+        &self,
+        options: WhoIsLookupOptions,
+    ) -> Result<Vec<(String, String)>, WhoIsError> {
+        let (server, text) = self.select_server(&options)?;
+
+        let hops = self.lookup_hops_async(&server, &text, &options).await?;
+
+        self.all_results(&options, hops)
+    }
+
+    /// Lookup a domain or an IP, and write the response body into an async writer. See `lookup_to_writer`.
+    #[inline]
+    pub async fn lookup_to_async_writer<W: AsyncWrite + Unpin>(
+        &self,
+        options: WhoIsLookupOptions,
+        mut out: W,
+    ) -> Result<(), WhoIsError> {
+        let (server, text) = self.select_server(&options)?;
+
+        self.lookup_to_async_writer_inner(&server, &text, &options, &mut out).await?;
+
+        out.flush().await?;
+
+        Ok(())
+    }
+
+    /// Lookup a domain or an IP, and get a stream of the lines of the response body. Only the response of the last server in the referral chain is yielded, decoded like the body of `lookup_async`. Its lines are delivered as they arrive if no referral will be followed from it (e.g. `follow` is 0) and it can be streamed like in `lookup_to_async_writer`. Otherwise, the response has to be read completely, so its lines are yielded after it ends. `detect_rate_limit` and `strip_percent_comments` are applied line by line, so the lines still stream: the lines before the one which matches a rate-limit pattern have been yielded already when the `WhoIsError::RateLimited` error (whose body starts at that line) is yielded. `normalize_line_endings` has no effect because line endings are removed. If `trim_boilerplate` is set, the whole body is read and post-processed like in `lookup_to_async_writer` before its lines are yielded.
+    pub fn lookup_lines_async(&self, mut options: WhoIsLookupOptions) -> WhoIsLines<'_> {
+        let (mut writer, reader) = tokio::io::duplex(DEFAULT_READ_CAPACITY);
+
+        // `trim_boilerplate` needs the whole body, and the rate-limit check has to see the comments which it removes
+        let line_options = if options.trim_boilerplate {
+            LineOptions::default()
+        } else {
+            // line endings are removed from the lines anyway
+            options.normalize_line_endings = false;
+
+            LineOptions {
+                rate_limit:             std::mem::take(&mut options.detect_rate_limit)
+                    .then_some(&self.classifier),
+                strip_percent_comments: std::mem::take(&mut options.strip_percent_comments),
+            }
+        };
+
+        let lookup = async move {
+            let (server, text) = self.select_server(&options)?;
+
+            let addr =
+                self.lookup_to_async_writer_inner(&server, &text, &options, &mut writer).await?;
+
+            writer.flush().await?;
+
+            Ok(addr)
+        };
+
+        WhoIsLines::new(Box::pin(lookup), reader, line_options)
+    }
+
+    /// Resolve the server and try to open a TCP connection to it within `timeout`, without sending a query. See `health_check_async`.
+    async fn check_server_async(
+        &self,
+        addr: &str,
+        server: &WhoIsServerValue,
+        timeout: Duration,
+    ) -> Result<(), WhoIsError> {
+        let with_addr = |source| WhoIsError::from_io_with_addr(addr, source);
+
+        let connect = async {
+            let socket_addrs = self.resolve_async(server).await?;
+
+            tokio::net::TcpStream::connect(socket_addrs.as_slice()).await
+        };
+
+        match tokio::time::timeout(timeout, connect).await {
+            Ok(result) => result.map(|_| ()).map_err(with_addr),
+            Err(_) => Err(WhoIsError::Timeout {
+                addr: String::from(addr)
+            }),
+        }
+    }
+
+    /// Try to connect (without sending a query) to each distinct configured server, and report its reachability. At most `concurrency` servers are checked at the same time, and the next one is checked as soon as another check finishes. The results are sorted like the ones of `health_check`.
+    pub async fn health_check_async(
+        &self,
+        timeout: Duration,
+        concurrency: usize,
+    ) -> Vec<(String, Result<(), WhoIsError>)> {
+        let servers: Vec<(String, &WhoIsServerValue)> =
+            self.distinct_servers().into_iter().collect();
+
+        let checks = servers
+            .iter()
+            .map(|(addr, server)| self.check_server_async(addr, server, timeout))
+            .collect();
+
+        let results = join_bounded(checks, concurrency).await;
+
+        servers.into_iter().map(|(addr, _)| addr).zip(results).collect()
+    }
+
+    /// Lookup many targets with at most `concurrency` lookups at the same time. The results are in the same order as `options`, regardless of which lookup finishes first, and a failed lookup doesn't abort the others.
+    pub async fn lookup_many_async(
+        &self,
+        options: Vec<WhoIsLookupOptions>,
+        concurrency: usize,
+    ) -> Vec<Result<String, WhoIsError>> {
+        let lookups = options.into_iter().map(|options| self.lookup_async(options)).collect();
+
+        join_bounded(lookups, concurrency).await
+    }
+
+    /// Lookup a domain or an IP, and classify the response body before it is post-processed (e.g. by `trim_boilerplate`), the same body that `detect_rate_limit` checks. The pattern of the classifier which matched is returned as well, see `ResponseClassifier::classify_with_pattern`.
+    #[inline]
+    pub async fn lookup_classified_async(
+        &self,
+        options: WhoIsLookupOptions,
+    ) -> Result<(String, ResponseClass, Option<String>), WhoIsError> {
+        let (body, classification) =
+            self.lookup_target_classified_async(&options.target, &options, true).await?;
+        let (class, pattern) = classification.expect("the body is classified");
+        let pattern = pattern.map(String::from);
+
+        Ok((body, class, pattern))
     }
 }
@@ -18,6 +18,46 @@ pub enum WhoIsError {
     Elapsed(tokio::time::error::Elapsed),
     /// This kind of errors is recommended to be panic!
     MapError(&'static str),
+    /// The WHOIS server responded with a rate-limit message.
+    RateLimited {
+        server: String,
+        body:   String,
+    },
+    /// Failed to connect to, write to or read from the WHOIS server at `addr`.
+    ConnectError {
+        addr:   String,
+        source: io::Error,
+    },
+    /// The socket timeout elapsed while connecting to, writing to or reading from the WHOIS server at `addr`, or the `total_timeout` of the lookup elapsed while querying it.
+    Timeout {
+        addr: String,
+    },
+    /// The text which would be substituted for `$addr` contains `\r` or `\n`, which could inject another WHOIS query.
+    InvalidTarget {
+        target: String,
+    },
+    /// The TLD (or the domain suffix) `tld` is set to null in the server list, i.e. it is unsupported intentionally.
+    UnsupportedTld {
+        tld: String,
+    },
+    /// The top-level key `key` appears more than once in the server list, which is rejected by the strict constructors.
+    DuplicateKey {
+        key: String,
+    },
+    /// The value of the key `key` in the server list is not a correct server. The key of an entry in the `_` object is prefixed with `_.`, e.g. `_.ip` or `_.ip_ranges.193.0.0.0/8`, and the key of the default domain server is empty.
+    InvalidServer {
+        key:    String,
+        source: Box<WhoIsError>,
+    },
+    /// The DNS query for the SRV record of a WHOIS server failed.
+    DnsError(trust_dns_client::error::ClientError),
+}
+
+impl From<trust_dns_client::error::ClientError> for WhoIsError {
+    #[inline]
+    fn from(error: trust_dns_client::error::ClientError) -> Self {
+        WhoIsError::DnsError(error)
+    }
 }
 
 impl From<serde_json::Error> for WhoIsError {
@@ -49,6 +89,21 @@ impl From<tokio::time::error::Elapsed> for WhoIsError {
     }
 }
 
+impl WhoIsError {
+    /// Attach the address of the WHOIS server to an I/O error, telling timeouts apart from other failures.
+    pub(crate) fn from_io_with_addr(addr: &str, source: io::Error) -> WhoIsError {
+        match source.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => WhoIsError::Timeout {
+                addr: String::from(addr),
+            },
+            _ => WhoIsError::ConnectError {
+                addr: String::from(addr),
+                source,
+            },
+        }
+    }
+}
+
 impl Display for WhoIsError {
     #[inline]
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
@@ -59,8 +114,51 @@ impl Display for WhoIsError {
             #[cfg(feature = "tokio")]
             WhoIsError::Elapsed(error) => Display::fmt(error, f),
             WhoIsError::MapError(text) => f.write_str(text),
+            WhoIsError::RateLimited {
+                server, ..
+            } => f.write_fmt(format_args!("The WHOIS server {} is rate limiting queries.", server)),
+            WhoIsError::ConnectError {
+                addr,
+                source,
+            } => f.write_fmt(format_args!("{}: {}", addr, source)),
+            WhoIsError::Timeout {
+                addr,
+            } => f.write_fmt(format_args!("The WHOIS server {} timed out.", addr)),
+            WhoIsError::InvalidTarget {
+                target,
+            } => f.write_fmt(format_args!("The target {:?} contains a line break.", target)),
+            WhoIsError::UnsupportedTld {
+                tld,
+            } => f.write_fmt(format_args!(
+                "The TLD {:?} is set to null in the server list, so it is not supported.",
+                tld
+            )),
+            WhoIsError::DuplicateKey {
+                key,
+            } => f.write_fmt(format_args!(
+                "The key {:?} appears more than once in the server list.",
+                key
+            )),
+            WhoIsError::InvalidServer {
+                key,
+                source,
+            } => f.write_fmt(format_args!("The server for the key {:?}: {}", key, source)),
+            WhoIsError::DnsError(error) => Display::fmt(error, f),
         }
     }
 }
 
-impl Error for WhoIsError {}
+impl Error for WhoIsError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WhoIsError::ConnectError {
+                source, ..
+            } => Some(source),
+            WhoIsError::InvalidServer {
+                source, ..
+            } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+/// The events which are reported to the observer of a `WhoIs` instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhoIsEvent {
+    /// A response contains a referral which cannot be parsed as a WHOIS host, so it is not followed.
+    InvalidReferral {
+        /// The WHOIS server (in the `host:port` form) which responded with the referral.
+        server:   String,
+        /// The trimmed referral value.
+        referral: String,
+    },
+    /// A response contains an `rwhois://` referral which is skipped because of the `rwhois_referrals` option.
+    SkippedReferral {
+        /// The WHOIS server (in the `host:port` form) which responded with the referral.
+        server:   String,
+        /// The trimmed referral value.
+        referral: String,
+    },
+    /// Querying a referred server failed, so the response of the previous server is used.
+    ReferralFailed {
+        /// The referred WHOIS server (in the `host:port` form).
+        referral: String,
+        /// The description of the error.
+        error:    String,
+    },
+}
@@ -1,6 +1,9 @@
+use std::fmt::{self, Display, Formatter};
+
 use validators::prelude::*;
 use validators_prelude::Host;
 
+/// The host (and the optional port) of a WHOIS server.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Validator)]
 #[validator(host)]
 pub struct WhoIsHost {
@@ -9,7 +12,28 @@ pub struct WhoIsHost {
 }
 
 impl WhoIsHost {
-    pub(crate) fn to_addr_string(&self, default_port: u16) -> String {
+    /// Get the domain or the IP of this WHOIS server.
+    #[inline]
+    pub const fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Get the explicit port of this WHOIS server.
+    #[inline]
+    pub const fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    pub(crate) fn to_host_string(&self) -> String {
+        match &self.host {
+            Host::IPv4(ip) => ip.to_string(),
+            Host::IPv6(ip) => ip.to_string(),
+            Host::Domain(domain) => domain.clone(),
+        }
+    }
+
+    /// Format this WHOIS server as a `host:port` string which can be connected. An IPv6 address is enclosed in brackets. If there is no explicit port, `default_port` is used.
+    pub fn to_addr_string(&self, default_port: u16) -> String {
         let port = self.port.unwrap_or(default_port);
 
         match &self.host {
@@ -19,3 +43,25 @@ impl WhoIsHost {
         }
     }
 }
+
+impl Display for WhoIsHost {
+    /// Format the host with the optional `:port`. An IPv6 address is enclosed in brackets only if there is a port.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.host, self.port) {
+            (Host::IPv6(ip), Some(port)) => f.write_fmt(format_args!("[{}]:{}", ip, port)),
+            (host, port) => {
+                match host {
+                    Host::IPv4(ip) => Display::fmt(ip, f)?,
+                    Host::IPv6(ip) => Display::fmt(ip, f)?,
+                    Host::Domain(domain) => f.write_str(domain)?,
+                }
+
+                if let Some(port) = port {
+                    f.write_fmt(format_args!(":{}", port))?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
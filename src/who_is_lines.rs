@@ -0,0 +1,232 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, DuplexStream, ReadBuf};
+
+use crate::{
+    response_processing::is_percent_comment,
+    who_is::{decode_body, is_compressed, strip_bom},
+    Charset, ResponseClass, ResponseClassifier, WhoIsError,
+};
+
+/// The lookup which writes the response body, and resolves to the address of the server.
+type LookupFuture<'a> = Pin<Box<dyn Future<Output = Result<String, WhoIsError>> + Send + 'a>>;
+
+/// The post-processing options which are applied line by line.
+#[derive(Debug, Default)]
+pub(crate) struct LineOptions<'a> {
+    /// The classifier to detect a rate-limit response with, if `detect_rate_limit` is set.
+    pub(crate) rate_limit:             Option<&'a ResponseClassifier>,
+    pub(crate) strip_percent_comments: bool,
+}
+
+/// A stream of the lines of a response body, created by `WhoIs::lookup_lines_async`. Line endings (`\n` or `\r\n`) and a leading BOM are removed, and a line which cannot be decoded as UTF-8 is an error. A compressed body is decoded as a whole after it ends. An error of the lookup is yielded after the lines which have already been received.
+pub struct WhoIsLines<'a> {
+    lookup:       Option<LookupFuture<'a>>,
+    reader:       DuplexStream,
+    buffer:       Vec<u8>,
+    error:        Option<WhoIsError>,
+    eof:          bool,
+    /// Whether the start of the body has been checked for a BOM and compression.
+    started:      bool,
+    compressed:   bool,
+    options:      LineOptions<'a>,
+    /// The address of the server, after the lookup has finished.
+    server:       Option<String>,
+    /// The lines from the one which matched a rate-limit pattern, which are not yielded.
+    rate_limited: Option<String>,
+}
+
+impl<'a> Debug for WhoIsLines<'a> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WhoIsLines").field("buffer", &self.buffer).field("eof", &self.eof).finish()
+    }
+}
+
+impl<'a> WhoIsLines<'a> {
+    /// The lookup writes the response body into the other end of `reader`, and drops it when it finishes.
+    #[inline]
+    pub(crate) fn new(
+        lookup: LookupFuture<'a>,
+        reader: DuplexStream,
+        options: LineOptions<'a>,
+    ) -> WhoIsLines<'a> {
+        WhoIsLines {
+            lookup: Some(lookup),
+            reader,
+            buffer: Vec::new(),
+            error: None,
+            eof: false,
+            started: false,
+            compressed: false,
+            options,
+            server: None,
+            rate_limited: None,
+        }
+    }
+
+    /// Get the next line, or **None** if the response has ended.
+    #[inline]
+    pub async fn next_line(&mut self) -> Option<Result<String, WhoIsError>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    fn take_line(&mut self, end: usize) -> Result<String, WhoIsError> {
+        let mut line: Vec<u8> = self.buffer.drain(..end).collect();
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        Charset::Utf8.decode(line).map_err(WhoIsError::IOError)
+    }
+
+    /// Apply the line options to a line. **None** means that the line is not yielded.
+    fn filter_line(&mut self, line: String) -> Option<String> {
+        if let Some(body) = self.rate_limited.as_mut() {
+            body.push_str(&line);
+            body.push('\n');
+
+            return None;
+        }
+
+        // like in `WhoIs::lookup`, the comments are checked before they are removed
+        if self
+            .options
+            .rate_limit
+            .map_or(false, |classifier| classifier.classify(&line) == ResponseClass::RateLimited)
+        {
+            self.rate_limited = Some(line + "\n");
+
+            return None;
+        }
+
+        if self.options.strip_percent_comments && is_percent_comment(&line) {
+            return None;
+        }
+
+        Some(line)
+    }
+
+    /// Take the next complete line which is yielded, or the last line at EOF.
+    fn next_line_in_buffer(&mut self) -> Option<Result<String, WhoIsError>> {
+        loop {
+            let end = match self.buffer.iter().position(|&b| b == b'\n') {
+                Some(index) => index + 1,
+                None if self.eof && !self.buffer.is_empty() => self.buffer.len(),
+                None => return None,
+            };
+
+            match self.take_line(end) {
+                Ok(line) => {
+                    if let Some(line) = self.filter_line(line) {
+                        return Some(Ok(line));
+                    }
+                },
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+
+    /// Get the error which is yielded at the end of the stream.
+    fn take_error(&mut self) -> Option<WhoIsError> {
+        if let Some(error) = self.error.take() {
+            return Some(error);
+        }
+
+        let body = self.rate_limited.take()?;
+
+        Some(WhoIsError::RateLimited {
+            server: self.server.take().unwrap_or_default(),
+            body,
+        })
+    }
+}
+
+impl<'a> Stream for WhoIsLines<'a> {
+    type Item = Result<String, WhoIsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            if !this.started && (this.buffer.len() >= 3 || this.eof) {
+                this.started = true;
+
+                strip_bom(&mut this.buffer);
+
+                this.compressed = is_compressed(&this.buffer);
+            }
+
+            if this.started && !this.compressed {
+                if let Some(line) = this.next_line_in_buffer() {
+                    return Poll::Ready(Some(line));
+                }
+            }
+
+            if this.eof {
+                if this.compressed {
+                    // a compressed body is decoded as a whole, like in `lookup_async`
+                    this.compressed = false;
+
+                    match decode_body(mem::take(&mut this.buffer), None) {
+                        Ok(body) => this.buffer = body.into_bytes(),
+                        Err(error) => return Poll::Ready(Some(Err(WhoIsError::IOError(error)))),
+                    }
+
+                    continue;
+                }
+
+                if let Some(line) = this.next_line_in_buffer() {
+                    return Poll::Ready(Some(line));
+                }
+
+                return Poll::Ready(this.take_error().map(Err));
+            }
+
+            if let Some(lookup) = this.lookup.as_mut() {
+                if let Poll::Ready(result) = lookup.as_mut().poll(cx) {
+                    // dropping the lookup closes the writing end, so the reader reaches EOF
+                    this.lookup = None;
+
+                    match result {
+                        Ok(server) => this.server = Some(server),
+                        Err(error) => this.error = Some(error),
+                    }
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            let mut read_buf = ReadBuf::new(&mut chunk);
+
+            match Pin::new(&mut this.reader).poll_read(cx, &mut read_buf) {
+                Poll::Ready(Ok(())) => {
+                    let filled = read_buf.filled();
+
+                    if filled.is_empty() {
+                        this.eof = true;
+                    } else {
+                        this.buffer.extend_from_slice(filled);
+                    }
+                },
+                Poll::Ready(Err(error)) => {
+                    this.eof = true;
+
+                    this.error.get_or_insert(WhoIsError::IOError(error));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -1,23 +1,84 @@
-use std::time::Duration;
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
 
-use validators::prelude::*;
+use validators::models::Host;
 
-use crate::{Target, WhoIsError, WhoIsServerValue};
+use crate::{RWhoisPolicy, Target, WhoIsError, WhoIsServerValue};
 
-const DEFAULT_FOLLOW: u16 = 2;
-const DEFAULT_TIMEOUT: u64 = 60000;
+const DEFAULT_MAX_CONNECTIONS: u16 = 8;
+pub(crate) const DEFAULT_READ_CAPACITY: usize = 4096;
+
+/// The default value of `WhoIsLookupOptions::follow`.
+pub const DEFAULT_FOLLOW: u16 = 2;
+
+/// The default value of `WhoIsLookupOptions::timeout`, in milliseconds.
+pub const DEFAULT_TIMEOUT: u64 = 60000;
+
+/// The ceiling of `WhoIsLookupOptions::follow`. Larger values are rejected by `try_set_follow` and clamped during lookups.
+pub const MAX_FOLLOW: u16 = 16;
 
 /// The options about how to lookup.
 #[derive(Debug, Clone)]
 pub struct WhoIsLookupOptions {
     /// The target that you want to lookup.
-    pub target:  Target,
+    pub target:                 Target,
+    /// An object handle (e.g. an ARIN `NET-` handle or a RIPE `ORG-` object) which is looked up instead of the target (also the one given to `WhoIs::lookup_target` or `WhoIs::lookup_with`), and sent through the query template without validation. There is no TLD to choose a WHOIS server by, so `server` is required. The default value is **None**.
+    pub raw_target:             Option<String>,
     /// The WHOIS server that you want to use. If it is **None**, an appropriate WHOIS server will be chosen from the list of WHOIS servers that the `WhoIs` instance have. The default value is **None**.
-    pub server:  Option<WhoIsServerValue>,
-    /// Number of times to follow redirects. The default value is 2.
-    pub follow:  u16,
+    pub server:                 Option<WhoIsServerValue>,
+    /// Number of times to follow redirects (`ReferralServer`, `Registrar WHOIS Server`, etc.). When a referral is followed, the returned body comes from the referred server (e.g. the registrar) instead of the server which was queried first. Values above `MAX_FOLLOW` are clamped. The default value is 2.
+    pub follow:                 u16,
     /// Socket timeout in milliseconds. The default value is 60000.
-    pub timeout: Option<Duration>,
+    pub timeout:                Option<Duration>,
+    /// Whether to return a `WhoIsError::RateLimited` error if the response body matches the rate-limit patterns of the `WhoIs` instance. The default value is **false**.
+    pub detect_rate_limit:      bool,
+    /// Whether to remove leading comment lines and trailing disclaimer sections from the response body. See the `strip_boilerplate` function. The default value is **false**.
+    pub trim_boilerplate:       bool,
+    /// Whether to rewrite the line endings of the response body to `\n`. See the `normalize_line_endings` function. The default value is **false**.
+    pub normalize_line_endings: bool,
+    /// Whether to remove the `%` comment lines (e.g. the metadata and disclaimers of RIPE-family responses) from the response body, keeping `%` lines which look like data fields. See the `strip_percent_comments` function. The default value is **false**.
+    pub strip_percent_comments: bool,
+    /// The hosts (e.g. `"whois.markmonitor.com"`, or `"rwhois.example.net:4321"` with a port) which referrals are allowed to go to. A referral to any other host stops the chain and the last response body is returned. If it is **None**, all referrals are followed. The default value is **None**.
+    pub allowed_referral_hosts: Option<HashSet<String>>,
+    /// The hard cap on the number of connections (the first query plus all followed referrals) that a single lookup may open, regardless of `follow`. When the cap is hit, the last response body is returned. The default value is 8.
+    pub max_connections:        u16,
+    /// Whether to set `TCP_NODELAY` on the connections, which disables Nagle's algorithm so that the small query is sent immediately. The default value is **true**.
+    pub tcp_nodelay:            bool,
+    /// Whether to follow at most one referral, i.e. from a thin registry to the registrar, regardless of `follow`. The default value is **false**.
+    pub follow_thin_only:       bool,
+    /// Whether to run the `whois` command of the operating system when no WHOIS server is known for the domain. Only the blocking `lookup`, `lookup_str`, `lookup_target` and `lookup_classified` methods (and the ones built on them, e.g. `lookup_domain_record`) use it. The async methods (including `lookup_blocking`), `lookup_detailed`, `lookup_all`, `lookup_with` and `lookup_to_writer` ignore it and always query a WHOIS server. The default value is **false**.
+    ///
+    /// The command is resolved via `PATH` and runs with the privileges of the current process, so make sure the `PATH` of the process can be trusted. The target is passed as a single argument after `--` without going through a shell.
+    #[cfg(feature = "system-whois")]
+    pub system_fallback:        bool,
+    /// The maximum time that a single query (connecting, sending the query and reading the whole response) may take, independently of `timeout` which bounds each socket operation. It still applies if `timeout` is **None**. The default value is **None**.
+    pub max_total_duration:     Option<Duration>,
+    /// Whether to connect through the proxy in the `ALL_PROXY` (or `all_proxy`) environment variable, e.g. `socks5://127.0.0.1:1080` (SOCKS5 without authentication) or `http://proxy:3128` (HTTP `CONNECT`). Hosts in the comma-separated `NO_PROXY` (or `no_proxy`) list, and their subdomains, are connected directly; `*` bypasses the proxy for all hosts. The default value is **false**.
+    pub use_env_proxy:          bool,
+    /// Whether to query only the server which is chosen first (e.g. the registry of a thin gTLD) and never follow referrals, regardless of `follow`. The default value is **false**.
+    pub registry_only:          bool,
+    /// Whether to return the error if querying a referred server fails (e.g. the connection is refused or times out). If it is **false**, the response body of the last server which responded is returned instead. The default value is **false**.
+    pub fail_on_referral_error: bool,
+    /// Whether to still read the response if writing the query fails after some bytes have been written (e.g. with `BrokenPipe`), for servers which start responding as soon as they have seen the address and close their side early. The default value is **false**.
+    pub tolerate_write_errors:  bool,
+    /// The number of bytes which are allocated for the response before reading. A larger value reduces reallocations and read calls for large responses (e.g. big IP blocks). The default value is 4096.
+    pub read_capacity:          usize,
+    /// The time limit of the whole `lookup`, `lookup_detailed`, `lookup_to_writer` (or their async versions) operation, including all followed referrals. When it is exceeded, a `WhoIsError::Timeout` error is returned. Unlike `max_total_duration`, which bounds each query, it bounds the whole referral chain. The default value is **None**.
+    pub total_timeout:          Option<Duration>,
+    /// The time limit of following referrals in a `lookup`, `lookup_detailed`, `lookup_to_writer` (or their async versions) operation, which starts after the first query, so the first server still gets its full `timeout`. When it is exceeded, the response body of the last server which responded is returned. The default value is **None**.
+    pub follow_time_budget:     Option<Duration>,
+    /// The flags (e.g. `"-B"` or `"n"`) which are put before the query with a space at send time, so that the same server can be queried verbosely or briefly. They are put before the whole query built from the `query` template of the server, so the template `"n + $addr\r\n"` with the flags `"-B"` sends `"-B n + 192.0.2.1\r\n"`. The flags are sent to every followed referral server as well. The default value is **None**.
+    pub query_flags:            Option<String>,
+    /// How `rwhois://` referrals are handled. The default value is `RWhoisPolicy::Follow`.
+    pub rwhois_referrals:       RWhoisPolicy,
+    /// The pause before each followed referral, to be polite to registrar servers and avoid their rate limits. The pause is cut short if the `total_timeout` would elapse. The default value is **None**.
+    pub follow_delay:           Option<Duration>,
+    /// The query template (e.g. `"z + $addr\r\n"`) which is used instead of the `query` of the server chosen for the target, for this lookup only. The precedence is `query_override`, then the `query` of the server, then the default `"$addr\r\n"`. The servers of followed referrals use their own templates. The default value is **None**.
+    pub query_override:         Option<String>,
+    /// Whether to send a domain in the case which it was given in (e.g. `ExAmple.COM`) to `Target::parse_str`, `from_str` or their `String` versions, instead of the normalized lowercase form, for registries which echo the exact query. Only an ASCII domain keeps its case; the target is still routed by its lowercase form. The default value is **false**.
+    pub preserve_case:          bool,
 }
 
 impl WhoIsLookupOptions {
@@ -25,12 +86,47 @@ impl WhoIsLookupOptions {
     pub fn from_target(target: Target) -> WhoIsLookupOptions {
         WhoIsLookupOptions {
             target,
+            raw_target: None,
             server: None,
             follow: DEFAULT_FOLLOW,
             timeout: Some(Duration::from_millis(DEFAULT_TIMEOUT)),
+            detect_rate_limit: false,
+            trim_boilerplate: false,
+            normalize_line_endings: false,
+            strip_percent_comments: false,
+            allowed_referral_hosts: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            tcp_nodelay: true,
+            follow_thin_only: false,
+            #[cfg(feature = "system-whois")]
+            system_fallback: false,
+            max_total_duration: None,
+            use_env_proxy: false,
+            registry_only: false,
+            fail_on_referral_error: false,
+            tolerate_write_errors: false,
+            read_capacity: DEFAULT_READ_CAPACITY,
+            total_timeout: None,
+            follow_time_budget: None,
+            query_flags: None,
+            rwhois_referrals: RWhoisPolicy::Follow,
+            follow_delay: None,
+            query_override: None,
+            preserve_case: false,
         }
     }
 
+    /// Create options which look up an object handle, see `raw_target`. The `target` is an empty domain, which is ignored.
+    #[inline]
+    pub fn from_raw<S: Into<String>>(handle: S) -> WhoIsLookupOptions {
+        // the target is only a placeholder, so it does not need to be valid
+        let mut options = Self::from_target(Target(Host::Domain(String::new()), None));
+
+        options.raw_target = Some(handle.into());
+
+        options
+    }
+
     #[allow(clippy::should_implement_trait)]
     #[inline]
     pub fn from_str<S: AsRef<str>>(s: S) -> Result<WhoIsLookupOptions, WhoIsError> {
@@ -42,3 +138,108 @@ impl WhoIsLookupOptions {
         Ok(Self::from_target(Target::parse_string(s)?))
     }
 }
+
+impl TryFrom<&str> for WhoIsLookupOptions {
+    type Error = WhoIsError;
+
+    #[inline]
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::from_str(s)
+    }
+}
+
+impl TryFrom<String> for WhoIsLookupOptions {
+    type Error = WhoIsError;
+
+    #[inline]
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_string(s)
+    }
+}
+
+impl WhoIsLookupOptions {
+    /// Disable following referrals, so that the body always comes from the first queried server.
+    #[inline]
+    pub fn no_follow(mut self) -> Self {
+        self.follow = 0;
+
+        self
+    }
+
+    /// Set the WHOIS server by a host string such as `"whois.nic.io"` or `"127.0.0.1:4343"`. The default query of the server is used. Set the `server` field directly for full control.
+    #[inline]
+    pub fn with_server_host<S: AsRef<str>>(mut self, host: S) -> Result<Self, WhoIsError> {
+        self.server = Some(WhoIsServerValue::from_string(host)?);
+
+        Ok(self)
+    }
+
+    /// Remove the socket timeout, so that a slow server can take as long as it needs. Consider setting `max_total_duration` as well, or a server which never closes the connection blocks the lookup forever.
+    #[inline]
+    pub fn unbounded(mut self) -> Self {
+        self.timeout = None;
+
+        self
+    }
+
+    /// The timeout of each socket operation on the server, bounded by `max_total_duration`.
+    #[inline]
+    pub(crate) fn socket_timeout(&self, server: &WhoIsServerValue) -> Option<Duration> {
+        match (server.timeout.or(self.timeout), self.max_total_duration) {
+            (Some(timeout), Some(max_total_duration)) => Some(timeout.min(max_total_duration)),
+            (timeout, max_total_duration) => timeout.or(max_total_duration),
+        }
+    }
+
+    /// Whether the response body is post-processed, i.e. checked for a rate limit or rewritten, so that it has to be read completely before it is returned.
+    #[inline]
+    pub(crate) fn post_processes(&self) -> bool {
+        self.detect_rate_limit
+            || self.trim_boilerplate
+            || self.normalize_line_endings
+            || self.strip_percent_comments
+    }
+
+    /// The deadline of a single query which starts now, according to `max_total_duration`.
+    #[inline]
+    pub(crate) fn query_deadline(&self) -> Option<Instant> {
+        self.max_total_duration.map(|max_total_duration| Instant::now() + max_total_duration)
+    }
+
+    /// The deadline of a whole referral chain which starts now, according to `total_timeout`.
+    #[inline]
+    pub(crate) fn chain_deadline(&self) -> Option<Instant> {
+        self.total_timeout.map(|total_timeout| Instant::now() + total_timeout)
+    }
+
+    /// The deadline of following referrals which starts now, according to `follow_time_budget`.
+    #[inline]
+    pub(crate) fn follow_deadline(&self) -> Option<Instant> {
+        self.follow_time_budget.map(|budget| Instant::now() + budget)
+    }
+
+    /// Set `follow`, rejecting values above `MAX_FOLLOW`.
+    #[inline]
+    pub fn try_set_follow(&mut self, follow: u16) -> Result<(), WhoIsError> {
+        if follow > MAX_FOLLOW {
+            return Err(WhoIsError::MapError("The follow value exceeds `MAX_FOLLOW`."));
+        }
+
+        self.follow = follow;
+
+        Ok(())
+    }
+
+    /// The number of referrals that will be followed, bounded by `registry_only`, `MAX_FOLLOW`, `follow_thin_only` and `max_connections`.
+    #[inline]
+    pub(crate) fn follow_limit(&self) -> u16 {
+        if self.registry_only {
+            return 0;
+        }
+
+        let follow = self.follow.min(MAX_FOLLOW);
+        let follow = if self.follow_thin_only { follow.min(1) } else { follow };
+
+        follow.min(self.max_connections.saturating_sub(1))
+    }
+}
@@ -0,0 +1,36 @@
+use std::net::SocketAddr;
+
+/// The detailed result of a lookup.
+#[derive(Debug, Clone)]
+pub struct WhoIsLookupResult {
+    /// The response body of the last queried server.
+    pub body:        String,
+    /// The WHOIS server (in the `host:port` form) which responded with the body.
+    pub server:      String,
+    /// The socket address which the connection to the server used.
+    pub socket_addr: SocketAddr,
+    /// Whether any referral was followed, i.e. the body comes from a referred server instead of the server which was queried first.
+    pub followed:    bool,
+    /// The number of referrals which were followed, i.e. the length of `chain` minus one.
+    pub hops:        u16,
+    /// The WHOIS servers (in the `host:port` form) which responded, in the order that they were queried. The first one is the server which was chosen for the target, and the last one is `server`.
+    pub chain:       Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for WhoIsLookupResult {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut result = serializer.serialize_struct("WhoIsLookupResult", 6)?;
+
+        result.serialize_field("body", &self.body)?;
+        result.serialize_field("server", &self.server)?;
+        result.serialize_field("socket_addr", &self.socket_addr)?;
+        result.serialize_field("followed", &self.followed)?;
+        result.serialize_field("hops", &self.hops)?;
+        result.serialize_field("chain", &self.chain)?;
+
+        result.end()
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    who_is::{
+        deadline_passed, decode_body, earliest, read_until_terminator, remaining_time,
+        set_remaining_read_timeout, write_query, PreparedQuery, QueryResult, Terminator,
+    },
+    WhoIs, WhoIsError, WhoIsLookupOptions, WhoIsPoolOptions, WhoIsServerValue,
+};
+
+/// A `WhoIs` instance with a pool of idle connections for each server, for synchronous deployments which query the same servers repeatedly. Connections are only reused for servers which keep them open after a response, see `WhoIsPoolOptions::response_end`. Stale or errored connections are discarded.
+#[derive(Debug)]
+pub struct WhoIsPool {
+    who:     WhoIs,
+    options: WhoIsPoolOptions,
+    idle:    Mutex<HashMap<String, Vec<(TcpStream, Instant)>>>,
+    opened:  AtomicUsize,
+    reused:  AtomicUsize,
+}
+
+impl WhoIsPool {
+    /// Create a `WhoIsPool` instance.
+    #[inline]
+    pub fn new(who: WhoIs, options: WhoIsPoolOptions) -> WhoIsPool {
+        WhoIsPool {
+            who,
+            options,
+            idle: Mutex::new(HashMap::new()),
+            opened: AtomicUsize::new(0),
+            reused: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the wrapped `WhoIs` instance.
+    #[inline]
+    pub fn who_is(&self) -> &WhoIs {
+        &self.who
+    }
+
+    /// Get the number of connections which have been opened.
+    #[inline]
+    pub fn opened_connections(&self) -> usize {
+        self.opened.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of queries which have been sent over a reused connection.
+    #[inline]
+    pub fn reused_connections(&self) -> usize {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// Close all idle connections.
+    #[inline]
+    pub fn clear(&self) {
+        self.idle().clear();
+    }
+
+    /// Lock the idle connections. A caller which panicked while holding the lock cannot leave them inconsistent, so the poison is ignored.
+    #[inline]
+    fn idle(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<(TcpStream, Instant)>>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn check_out(&self, addr: &str) -> Option<TcpStream> {
+        let mut idle = self.idle();
+
+        let connections = idle.get_mut(addr)?;
+
+        while let Some((client, since)) = connections.pop() {
+            if since.elapsed() < self.options.idle_timeout {
+                return Some(client);
+            }
+        }
+
+        None
+    }
+
+    fn check_in(&self, addr: &str, client: TcpStream) {
+        let mut idle = self.idle();
+
+        let connections = idle.entry(String::from(addr)).or_default();
+
+        if connections.len() < self.options.max_idle {
+            connections.push((client, Instant::now()));
+        }
+    }
+
+    /// Send the query and read the response. The connection is returned to the pool if the response ended with `response_end`. A response of a server with a terminator is read like in `WhoIs::lookup`, and its connection is not reused. Like in `WhoIs::lookup`, the read timeout is shortened before each read so that the whole response has to arrive before the deadline.
+    #[allow(clippy::too_many_arguments)]
+    fn exchange(
+        &self,
+        mut client: TcpStream,
+        prepared: &PreparedQuery,
+        server: &WhoIsServerValue,
+        terminator: Option<&Terminator>,
+        options: &WhoIsLookupOptions,
+        timeout: Option<Duration>,
+        deadline: Option<Instant>,
+        reused: bool,
+    ) -> io::Result<QueryResult> {
+        client.set_write_timeout(remaining_time(timeout, deadline))?;
+
+        write_query(&mut client, &prepared.query, options.tolerate_write_errors)?;
+
+        let socket_addr = client.peer_addr()?;
+
+        let mut data = Vec::with_capacity(options.read_capacity);
+
+        if let Some(terminator) = terminator {
+            read_until_terminator(&mut client, &mut data, terminator, |client| {
+                set_remaining_read_timeout(client, timeout, deadline)
+            })?;
+
+            if reused && data.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The idle connection has been closed by the server.",
+                ));
+            }
+
+            return Ok(QueryResult {
+                addr: prepared.addr.clone(),
+                socket_addr,
+                body: decode_body(data, server.body_charset())?,
+            });
+        }
+
+        let response_end = self.options.response_end.as_deref().map(str::as_bytes);
+
+        let mut buffer = [0; 4096];
+
+        let keep = loop {
+            set_remaining_read_timeout(&client, timeout, deadline)?;
+
+            match client.read(&mut buffer) {
+                Ok(0) => {
+                    if reused && data.is_empty() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "The idle connection has been closed by the server.",
+                        ));
+                    }
+
+                    break false;
+                },
+                Ok(n) => {
+                    data.extend_from_slice(&buffer[..n]);
+
+                    if let Some(response_end) = response_end {
+                        if data.ends_with(response_end) {
+                            data.truncate(data.len() - response_end.len());
+
+                            break true;
+                        }
+                    }
+                },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(error) => return Err(error),
+            }
+        };
+
+        if keep {
+            self.check_in(&prepared.addr, client);
+        }
+
+        Ok(QueryResult {
+            addr: prepared.addr.clone(),
+            socket_addr,
+            body: decode_body(data, server.body_charset())?,
+        })
+    }
+
+    fn lookup_once(
+        &self,
+        server: &WhoIsServerValue,
+        text: &str,
+        options: &WhoIsLookupOptions,
+        chain_deadline: Option<Instant>,
+    ) -> Result<QueryResult, WhoIsError> {
+        let deadline = earliest(options.query_deadline(), chain_deadline);
+
+        let prepared = PreparedQuery::new(server, text, self.who.query_placeholder(), options)?;
+
+        if deadline_passed(deadline) {
+            return Err(WhoIsError::Timeout {
+                addr: prepared.addr
+            });
+        }
+
+        let with_addr = |source| WhoIsError::from_io_with_addr(&prepared.addr, source);
+
+        let timeout = options.socket_timeout(server);
+
+        let terminator = Terminator::of(server)?;
+        let terminator = terminator.as_ref();
+
+        if let Some(client) = self.check_out(&prepared.addr) {
+            // a stale connection is discarded, and a new one is opened below
+            if let Ok(query_result) = self
+                .exchange(client, &prepared, server, terminator, options, timeout, deadline, true)
+            {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+
+                return Ok(query_result);
+            }
+        }
+
+        let mut client = self
+            .who
+            .connect(
+                server,
+                remaining_time(timeout, deadline),
+                options.tcp_nodelay,
+                prepared.proxy.as_ref(),
+            )
+            .map_err(with_addr)?;
+
+        if let Some(proxy) = &prepared.proxy {
+            proxy.handshake(&mut client, &prepared.host, prepared.port).map_err(with_addr)?;
+        }
+
+        self.opened.fetch_add(1, Ordering::Relaxed);
+
+        self.exchange(client, &prepared, server, terminator, options, timeout, deadline, false)
+            .map_err(with_addr)
+    }
+
+    /// Lookup a domain or an IP like `WhoIs::lookup`, reusing the idle connections.
+    pub fn lookup(&self, options: WhoIsLookupOptions) -> Result<String, WhoIsError> {
+        let (server, text) = self.who.select_server(&options)?;
+
+        let mut hops = self.who.lookup_chain(&server, &options, |server, deadline| {
+            self.lookup_once(server, &text, &options, deadline)
+        })?;
+
+        let query_result = hops.pop().expect("a referral chain has at least one hop");
+
+        self.who.post_process(&options, query_result.addr, query_result.body)
+    }
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+const DEFAULT_MAX_IDLE: usize = 4;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The options of a `WhoIsPool` instance.
+#[derive(Debug, Clone)]
+pub struct WhoIsPoolOptions {
+    /// The maximum number of idle connections kept for each server. The default value is 4.
+    pub max_idle:     usize,
+    /// How long an idle connection can be kept. An older connection is closed instead of being reused. The default value is 30 seconds.
+    pub idle_timeout: Duration,
+    /// The text which ends every response of a server that keeps the connection open, such as a server in a persistent (`-k`) mode. A connection can only be reused if its response ended with this text, which is removed from the body. If it is **None**, responses end when the server closes the connection, so no connection is reused. The default value is **None**.
+    pub response_end: Option<String>,
+}
+
+impl Default for WhoIsPoolOptions {
+    #[inline]
+    fn default() -> Self {
+        WhoIsPoolOptions {
+            max_idle:     DEFAULT_MAX_IDLE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            response_end: None,
+        }
+    }
+}
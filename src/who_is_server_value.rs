@@ -1,20 +1,55 @@
+use std::time::Duration;
+
 use serde_json::Value;
 use validators::prelude::*;
 
-use crate::{WhoIsError, WhoIsHost};
+use crate::{Charset, WhoIsError, WhoIsHost, WhoIsServerValueBuilder};
 
 const DEFAULT_PUNYCODE: bool = true;
+const ADDR_PLACEHOLDER: &str = "$addr";
 
 /// The model of a WHOIS server.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct WhoIsServerValue {
-    pub host:     WhoIsHost,
-    pub query:    Option<String>,
-    pub punycode: bool,
+    pub host:                        WhoIsHost,
+    pub query:                       Option<String>,
+    pub punycode:                    bool,
+    /// The socket timeout for this server. It takes precedence over the `timeout` of `WhoIsLookupOptions`. It is parsed from the `timeout_ms` field of the server object.
+    pub timeout:                     Option<Duration>,
+    /// The charset which the query is encoded in and the response is decoded from. If it is **None**, the query is sent as UTF-8 and the response is expected to be UTF-8 (unless `response_charset` is set). It is parsed from the `charset` field of the server object.
+    pub charset:                     Option<Charset>,
+    /// The charset which the response is decoded from, e.g. for a server which accepts ASCII queries but responds in UTF-16 or a Windows codepage. It takes precedence over `charset` for the response. It is parsed from the `response_charset` field of the server object.
+    pub response_charset:            Option<Charset>,
+    /// A line which ends the response of a server that keeps the connection open. The response is read until this line is received instead of until the server closes the connection, and the line is not a part of the body. If it is **None**, the response is read until EOF. It is parsed from the `response_terminator` field of the server object.
+    pub response_terminator:         Option<String>,
+    /// A regular expression which ends the response like `response_terminator` when a line (without its line ending) matches it, e.g. `^>>> Last update` or `^$` for a blank line. The matched line is not a part of the body either. If both are set, either one ends the response. It is parsed from the `response_terminator_pattern` field of the server object.
+    pub response_terminator_pattern: Option<String>,
 }
 
 impl WhoIsServerValue {
+    /// Create a builder with a host string (with an optional port, such as `"whois.arin.net:43"`), so that the other fields can be set in Rust.
+    #[inline]
+    pub fn builder<S: AsRef<str>>(host: S) -> Result<WhoIsServerValueBuilder, WhoIsError> {
+        Ok(WhoIsServerValueBuilder {
+            server: Self::from_string(host)?
+        })
+    }
+
+    #[inline]
     pub fn from_value(value: &Value) -> Result<WhoIsServerValue, WhoIsError> {
+        Self::from_value_inner(value, false)
+    }
+
+    /// Like `from_value`, but a `query` string without the `$addr` placeholder is rejected.
+    #[inline]
+    pub fn from_value_strict(value: &Value) -> Result<WhoIsServerValue, WhoIsError> {
+        Self::from_value_inner(value, true)
+    }
+
+    pub(crate) fn from_value_inner(
+        value: &Value,
+        strict: bool,
+    ) -> Result<WhoIsServerValue, WhoIsError> {
         match value {
             Value::Object(map) => match map.get("host") {
                 Some(Value::String(host)) => {
@@ -31,6 +66,13 @@ impl WhoIsServerValue {
                     let query = match map.get("query") {
                         Some(query) => {
                             if let Value::String(query) = query {
+                                if strict && !query.contains(ADDR_PLACEHOLDER) {
+                                    return Err(WhoIsError::MapError(
+                                        "The server value is an object, but its query string \
+                                         doesn't contain the `$addr` placeholder.",
+                                    ));
+                                }
+
                                 Some(String::from(query))
                             } else {
                                 return Err(WhoIsError::MapError(
@@ -56,10 +98,82 @@ impl WhoIsServerValue {
                         None => DEFAULT_PUNYCODE,
                     };
 
+                    let timeout = match map.get("timeout_ms") {
+                        Some(timeout) => match timeout.as_u64() {
+                            Some(timeout) => Some(Duration::from_millis(timeout)),
+                            None => {
+                                return Err(WhoIsError::MapError(
+                                    "The server value is an object, but it has an incorrect \
+                                     timeout_ms number.",
+                                ));
+                            },
+                        },
+                        None => None,
+                    };
+
+                    let charset = match map.get("charset") {
+                        Some(charset) => match charset.as_str().and_then(Charset::from_label) {
+                            Some(charset) => Some(charset),
+                            None => {
+                                return Err(WhoIsError::MapError(
+                                    "The server value is an object, but it has an incorrect or \
+                                     unsupported charset string.",
+                                ));
+                            },
+                        },
+                        None => None,
+                    };
+
+                    let response_charset = match map.get("response_charset") {
+                        Some(charset) => match charset.as_str().and_then(Charset::from_label) {
+                            Some(charset) => Some(charset),
+                            None => {
+                                return Err(WhoIsError::MapError(
+                                    "The server value is an object, but it has an incorrect or \
+                                     unsupported response_charset string.",
+                                ));
+                            },
+                        },
+                        None => None,
+                    };
+
+                    let response_terminator = match map.get("response_terminator") {
+                        Some(Value::String(terminator)) if !terminator.is_empty() => {
+                            Some(String::from(terminator))
+                        },
+                        Some(_) => {
+                            return Err(WhoIsError::MapError(
+                                "The server value is an object, but it has an incorrect \
+                                 response_terminator string.",
+                            ));
+                        },
+                        None => None,
+                    };
+
+                    let response_terminator_pattern = match map.get("response_terminator_pattern") {
+                        Some(Value::String(pattern))
+                            if !pattern.is_empty() && regex::bytes::Regex::new(pattern).is_ok() =>
+                        {
+                            Some(String::from(pattern))
+                        },
+                        Some(_) => {
+                            return Err(WhoIsError::MapError(
+                                "The server value is an object, but it has an incorrect \
+                                 response_terminator_pattern regular expression.",
+                            ));
+                        },
+                        None => None,
+                    };
+
                     Ok(WhoIsServerValue {
                         host,
                         query,
                         punycode,
+                        timeout,
+                        charset,
+                        response_charset,
+                        response_terminator,
+                        response_terminator_pattern,
                     })
                 },
                 _ => Err(WhoIsError::MapError(
@@ -67,10 +181,27 @@ impl WhoIsServerValue {
                 )),
             },
             Value::String(host) => Self::from_string(host),
-            _ => Err(WhoIsError::MapError("The server value is not an object or a host string.")),
+            Value::Number(_) => Err(WhoIsError::MapError(
+                "The server value is a number, not an object or a host string.",
+            )),
+            Value::Array(_) => Err(WhoIsError::MapError(
+                "The server value is an array, not an object or a host string.",
+            )),
+            Value::Bool(_) => Err(WhoIsError::MapError(
+                "The server value is a boolean, not an object or a host string.",
+            )),
+            Value::Null => Err(WhoIsError::MapError(
+                "The server value is null, not an object or a host string.",
+            )),
         }
     }
 
+    /// Get the charset which the response is decoded from, i.e. `response_charset` or `charset`.
+    #[inline]
+    pub(crate) fn body_charset(&self) -> Option<Charset> {
+        self.response_charset.or(self.charset)
+    }
+
     #[inline]
     pub fn from_string<S: AsRef<str>>(string: S) -> Result<WhoIsServerValue, WhoIsError> {
         let host = string.as_ref();
@@ -86,6 +217,11 @@ impl WhoIsServerValue {
             host,
             query: None,
             punycode: DEFAULT_PUNYCODE,
+            timeout: None,
+            charset: None,
+            response_charset: None,
+            response_terminator: None,
+            response_terminator_pattern: None,
         })
     }
 }
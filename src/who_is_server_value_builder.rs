@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use crate::{Charset, WhoIsServerValue};
+
+/// The builder of a `WhoIsServerValue` instance. Use the `WhoIsServerValue::builder` associated function to create one.
+#[derive(Debug, Clone)]
+pub struct WhoIsServerValueBuilder {
+    pub(crate) server: WhoIsServerValue,
+}
+
+impl WhoIsServerValueBuilder {
+    /// Set the query template, such as `"n + $addr\r\n"`.
+    #[inline]
+    pub fn query<S: Into<String>>(mut self, query: S) -> Self {
+        self.server.query = Some(query.into());
+
+        self
+    }
+
+    /// Set whether a Unicode domain is sent in its punycode form.
+    #[inline]
+    pub fn punycode(mut self, punycode: bool) -> Self {
+        self.server.punycode = punycode;
+
+        self
+    }
+
+    /// Set the socket timeout for this server.
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.server.timeout = Some(timeout);
+
+        self
+    }
+
+    /// Set the charset which the query is encoded in and the response is decoded from.
+    #[inline]
+    pub fn charset(mut self, charset: Charset) -> Self {
+        self.server.charset = Some(charset);
+
+        self
+    }
+
+    /// Set the charset which the response is decoded from, if it differs from the charset of the query.
+    #[inline]
+    pub fn response_charset(mut self, charset: Charset) -> Self {
+        self.server.response_charset = Some(charset);
+
+        self
+    }
+
+    /// Set the line which ends the response, for a server which keeps the connection open.
+    #[inline]
+    pub fn response_terminator<S: Into<String>>(mut self, terminator: S) -> Self {
+        self.server.response_terminator = Some(terminator.into());
+
+        self
+    }
+
+    /// Set the regular expression which ends the response when a line matches it, for a server which keeps the connection open.
+    #[inline]
+    pub fn response_terminator_pattern<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.server.response_terminator_pattern = Some(pattern.into());
+
+        self
+    }
+
+    /// Create the `WhoIsServerValue` instance.
+    #[inline]
+    pub fn build(self) -> WhoIsServerValue {
+        self.server
+    }
+}
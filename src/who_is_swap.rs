@@ -0,0 +1,31 @@
+use std::sync::{Arc, RwLock};
+
+use crate::WhoIs;
+
+/// A `WhoIs` instance which can be replaced atomically while it is being used, e.g. by `WhoIs::watch_path`. A lookup in progress keeps using the instance which it has loaded.
+#[derive(Debug)]
+pub struct WhoIsSwap {
+    current: RwLock<Arc<WhoIs>>,
+}
+
+impl WhoIsSwap {
+    /// Create a `WhoIsSwap` instance with the initial `WhoIs` instance.
+    #[inline]
+    pub fn new(who: WhoIs) -> WhoIsSwap {
+        WhoIsSwap {
+            current: RwLock::new(Arc::new(who))
+        }
+    }
+
+    /// Get the current `WhoIs` instance.
+    #[inline]
+    pub fn load(&self) -> Arc<WhoIs> {
+        Arc::clone(&self.current.read().unwrap_or_else(|error| error.into_inner()))
+    }
+
+    /// Replace the current `WhoIs` instance.
+    #[inline]
+    pub fn store(&self, who: WhoIs) {
+        *self.current.write().unwrap_or_else(|error| error.into_inner()) = Arc::new(who);
+    }
+}
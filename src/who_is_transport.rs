@@ -0,0 +1,6 @@
+use std::io::{Read, Write};
+
+/// A stream which a WHOIS query can be sent over, such as a Unix socket, a TLS stream or an in-memory pipe. It is implemented for every `Read + Write + Send` type.
+pub trait WhoIsTransport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> WhoIsTransport for T {}
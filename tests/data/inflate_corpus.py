@@ -0,0 +1,106 @@
+#!/usr/bin/env python3
+# Generate `inflate_large.txt` and `inflate_corpus.bin`, the reference corpus of `test_inflate_corpus`.
+#
+# Every entry of the corpus is `[payload id: u8][length: u32 LE][compressed data]`. The payload ids are
+# 0 for `verisign.txt`, 1 for `inflate_large.txt`, 2 for the empty text, and 255 for data which must not be
+# decompressed.
+
+import gzip
+import random
+import struct
+import zlib
+from pathlib import Path
+
+DATA = Path(__file__).parent
+
+rng = random.Random(20240601)
+
+words = [
+    "Domain", "Name", "Registrar", "WHOIS", "Server", "Updated", "Date", "Creation", "Expiry",
+    "Status", "clientTransferProhibited", "https://icann.org/epp", "NetRange", "CIDR", "OrgName",
+    "Abuse", "Contact", "Email", "Phone", "Country", "%", "#", ":", "-", ".", "é", "測試",
+]
+
+
+def random_lines(size):
+    out = []
+    length = 0
+
+    while length < size:
+        line = " ".join(rng.choice(words) for _ in range(rng.randint(1, 12)))
+        line += " " + str(rng.randint(0, 10 ** rng.randint(1, 12))) + "\r\n"
+        out.append(line)
+        length += len(line.encode())
+
+    return "".join(out)
+
+
+# random lines, runs for length 258 matches at distance 1, and a block repeated at nearly the window size
+block = random_lines(4096)
+large = (
+    random_lines(2000)
+    + block
+    + random_lines(32768 - 4096 - len(block.encode()) - 200)
+    + block
+    + "=" * 3000
+    + "\r\n"
+    + random_lines(8000)
+)
+
+(DATA / "inflate_large.txt").write_text(large, encoding="utf-8", newline="")
+
+payloads = [
+    (DATA / "verisign.txt").read_bytes(),
+    large.encode(),
+    b"",
+]
+
+strategies = [zlib.Z_DEFAULT_STRATEGY, zlib.Z_FILTERED, zlib.Z_HUFFMAN_ONLY, zlib.Z_RLE, zlib.Z_FIXED]
+
+entries = []
+
+# every combination for the small payloads, and one of each for the large one to keep the corpus small
+matrix = [
+    (level, strategy, wbits)
+    for level in (0, 1, 6, 9)
+    # the strategy does not matter without compression
+    for strategy in (strategies if level else strategies[:1])
+    # zlib with the largest window, zlib with the smallest window, and gzip
+    for wbits in (15, 9, 31)
+]
+selected = [(0, strategies[0], 15)] + [(9, strategy, 31) for strategy in strategies] + [(1, strategies[0], 15)]
+
+for payload_id, payload in enumerate(payloads):
+    for level, strategy, wbits in selected if payload_id == 1 else matrix:
+        compressor = zlib.compressobj(level, zlib.DEFLATED, wbits, 9, strategy)
+        entries.append((payload_id, compressor.compress(payload) + compressor.flush()))
+
+    # many small blocks
+    compressor = zlib.compressobj(6, zlib.DEFLATED, 15)
+    data = b""
+
+    for i in range(0, len(payload), 997):
+        data += compressor.compress(payload[i:i + 997]) + compressor.flush(zlib.Z_FULL_FLUSH)
+
+    entries.append((payload_id, data + compressor.flush()))
+
+    # a gzip header with every optional field
+    body = zlib.compressobj(9, zlib.DEFLATED, -15)
+    body = body.compress(payload) + body.flush()
+    header = b"\x1f\x8b\x08\x1e" + struct.pack("<I", 1700000000) + b"\x02\x03"
+    header += struct.pack("<H", 6) + b"AB\x02\x00xy" + b"response.txt\x00" + b"a comment\x00"
+    header += struct.pack("<H", zlib.crc32(header) & 0xFFFF)
+    entries.append(
+        (payload_id, header + body + struct.pack("<II", zlib.crc32(payload), len(payload)))
+    )
+
+    # only the first member of a multi-member gzip file is decompressed
+    entries.append((payload_id, gzip.compress(payload, mtime=0) + gzip.compress(b"ignored", mtime=0)))
+
+# a preset dictionary is not supported
+compressor = zlib.compressobj(6, zlib.DEFLATED, 15, zdict=b"Domain Name Registrar")
+entries.append((255, compressor.compress(payloads[0]) + compressor.flush()))
+
+with open(DATA / "inflate_corpus.bin", "wb") as f:
+    for payload_id, data in entries:
+        f.write(struct.pack("<BI", payload_id, len(data)) + data)
@@ -1,5 +1,154 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
 use whois_rust::*;
 
+/// What a scripted WHOIS server does with a connection after reading the query.
+#[derive(Clone)]
+enum Script {
+    /// Send the bytes and close the connection.
+    Respond(Vec<u8>),
+    /// Send the bytes and keep the connection open without sending anything else.
+    Partial(Vec<u8>),
+    /// Never respond, and keep the connection open.
+    Stall,
+    /// Send the bytes every 50 milliseconds without closing the connection.
+    Trickle(Vec<u8>),
+    /// Wait, then send the bytes and close the connection.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    Delay(Duration, Vec<u8>),
+    /// Send the bytes after every query on the connection, until the client closes it.
+    Persistent(Vec<u8>),
+}
+
+impl Script {
+    fn respond<S: Into<String>>(response: S) -> Script {
+        Script::Respond(response.into().into_bytes())
+    }
+}
+
+/// An in-process WHOIS server on `127.0.0.1`. Each connection is handled by the next script, and the last script is repeated. The queries and the number of accepted connections are recorded.
+struct MockServer {
+    addr:     SocketAddr,
+    queries:  Receiver<String>,
+    accepted: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    fn spawn(scripts: Vec<Script>) -> MockServer {
+        Self::spawn_on(TcpListener::bind("127.0.0.1:0").unwrap(), scripts)
+    }
+
+    /// Serve on a listener which has been bound already, so that its address can be used in the scripts of other servers.
+    fn spawn_on(listener: TcpListener, scripts: Vec<Script>) -> MockServer {
+        assert!(!scripts.is_empty());
+
+        let addr = listener.local_addr().unwrap();
+
+        let (sender, queries) = mpsc::channel();
+
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let script = scripts[i.min(scripts.len() - 1)].clone();
+                let sender = sender.clone();
+
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.unwrap());
+
+                    let mut query = String::new();
+                    reader.read_line(&mut query).unwrap();
+
+                    let _ = sender.send(query);
+
+                    match script {
+                        Script::Respond(response) => {
+                            reader.get_mut().write_all(&response).unwrap();
+                        },
+                        Script::Partial(response) => {
+                            reader.get_mut().write_all(&response).unwrap();
+
+                            thread::sleep(Duration::from_secs(30));
+                        },
+                        Script::Stall => thread::sleep(Duration::from_secs(30)),
+                        Script::Trickle(response) => {
+                            for _ in 0..600 {
+                                if reader.get_mut().write_all(&response).is_err() {
+                                    break;
+                                }
+
+                                thread::sleep(Duration::from_millis(50));
+                            }
+                        },
+                        Script::Delay(delay, response) => {
+                            thread::sleep(delay);
+
+                            let _ = reader.get_mut().write_all(&response);
+                        },
+                        Script::Persistent(response) => loop {
+                            if reader.get_mut().write_all(&response).is_err() {
+                                break;
+                            }
+
+                            let mut query = String::new();
+
+                            if reader.read_line(&mut query).unwrap_or(0) == 0 {
+                                break;
+                            }
+
+                            let _ = sender.send(query);
+                        },
+                    }
+                });
+            }
+        });
+
+        MockServer {
+            addr,
+            queries,
+            accepted,
+        }
+    }
+
+    fn respond<S: Into<String>>(response: S) -> MockServer {
+        Self::spawn(vec![Script::respond(response)])
+    }
+
+    fn server(&self) -> WhoIsServerValue {
+        WhoIsServerValue::from_string(self.addr.to_string()).unwrap()
+    }
+
+    fn queries(&self) -> Vec<String> {
+        self.queries.try_iter().collect()
+    }
+
+    fn accepted(&self) -> usize {
+        self.accepted.load(Ordering::SeqCst)
+    }
+}
+
+/// Serializes the tests which change the process environment, which all the tests share.
+static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Hold the environment for the rest of a test. A test which panicked with the lock held does not fail the other ones.
+fn lock_env() -> std::sync::MutexGuard<'static, ()> {
+    ENV_LOCK.lock().unwrap_or_else(|error| error.into_inner())
+}
+
 #[test]
 fn test() {
     let who = WhoIs::from_path("node-whois/servers.json").unwrap();
@@ -15,7 +164,7 @@ fn test() {
 fn test_srv() {
     let mut who = WhoIs::from_host("whois.arin.net").unwrap();
 
-    assert!(who.can_find_server_for_tld(".lotteryusa.us", "8.8.8.8:53"));
+    assert!(who.can_find_server_for_tld(".lotteryusa.us", "8.8.8.8:53").unwrap());
 
     let result = who.lookup(WhoIsLookupOptions::from_string("lotteryusa.us").unwrap()).unwrap();
     println!("{}", result);
@@ -36,3 +185,3866 @@ async fn test_async() {
         .unwrap();
     println!("{}", result);
 }
+
+#[test]
+fn test_classifier() {
+    let classifier = ResponseClassifier::default();
+
+    assert_eq!(
+        ResponseClass::Found,
+        classifier.classify("Domain Name: MAGICLEN.ORG\r\nRegistry Domain ID: 1\r\n")
+    );
+    assert_eq!(
+        ResponseClass::NotFound,
+        classifier.classify("No match for \"EXAMPLE-NX.COM\".\r\n")
+    );
+    assert_eq!(
+        ResponseClass::RateLimited,
+        classifier.classify("% Query rate limit exceeded. Try again later.\n")
+    );
+    assert_eq!(ResponseClass::Unknown, classifier.classify(" \r\n"));
+
+    let classifier = ResponseClassifier::from_string(r#"{"not_found": ["nope"]}"#).unwrap();
+
+    assert_eq!(ResponseClass::NotFound, classifier.classify("NOPE"));
+    assert_eq!(ResponseClass::Found, classifier.classify("No match for \"EXAMPLE-NX.COM\"."));
+    assert_eq!(ResponseClass::RateLimited, classifier.classify("WHOIS LIMIT EXCEEDED"));
+
+    assert!(ResponseClassifier::from_string(r#"{"not_found": [1]}"#).is_err());
+}
+
+#[test]
+fn test_rate_limit() {
+    const RIPE_THROTTLE: &str = "%ERROR:201: access denied for 192.0.2.1\n%\n% Queries from your \
+                                 IP address have passed the daily limit of controlled objects.\n";
+    const VERISIGN_THROTTLE: &str = "WHOIS LIMIT EXCEEDED - SEE WWW.PIR.ORG/WHOIS FOR DETAILS\r\n";
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("magiclen.org").unwrap();
+    options.server = Some(MockServer::respond(VERISIGN_THROTTLE).server());
+
+    assert_eq!(VERISIGN_THROTTLE, who.lookup(options.clone()).unwrap());
+
+    options.server = Some(MockServer::respond(VERISIGN_THROTTLE).server());
+    options.detect_rate_limit = true;
+
+    match who.lookup(options.clone()) {
+        Err(WhoIsError::RateLimited {
+            body, ..
+        }) => assert_eq!(VERISIGN_THROTTLE, body),
+        other => panic!("{:?}", other),
+    }
+
+    options.server = Some(MockServer::respond(RIPE_THROTTLE).server());
+
+    assert!(matches!(who.lookup(options.clone()), Err(WhoIsError::RateLimited { .. })));
+
+    options.server = Some(MockServer::respond("Domain Name: MAGICLEN.ORG\r\n").server());
+
+    assert_eq!("Domain Name: MAGICLEN.ORG\r\n", who.lookup(options).unwrap());
+}
+
+#[test]
+fn test_strip_boilerplate() {
+    let verisign = strip_boilerplate(include_str!("data/verisign.txt"));
+
+    assert!(verisign.starts_with("   Domain Name: EXAMPLE.COM\r\n"));
+    assert!(verisign.ends_with("   DNSSEC: signedDelegation\r\n"));
+    assert!(!verisign.contains("NOTICE:"));
+    assert!(!verisign.contains("TERMS OF USE:"));
+
+    let ripe = strip_boilerplate(include_str!("data/ripe.txt"));
+
+    assert!(ripe.starts_with("inetnum:        193.0.0.0 - 193.0.7.255\n"));
+    assert!(ripe.ends_with("source:         RIPE # Filtered\n"));
+    assert!(!ripe.contains('%'));
+
+    let afnic = strip_boilerplate(include_str!("data/afnic.txt"));
+
+    assert!(afnic.starts_with("domain:                        afnic.fr\n"));
+    assert!(afnic.ends_with("source:                        FRNIC\n"));
+
+    assert_eq!("", strip_boilerplate("% only comments\n%\n"));
+}
+
+#[test]
+fn test_strip_percent_comments() {
+    let ripe = strip_percent_comments(include_str!("data/ripe.txt"));
+
+    assert!(ripe.starts_with("\n\n\ninetnum:        193.0.0.0 - 193.0.7.255\n"));
+    assert!(ripe.contains("source:         RIPE # Filtered\n"));
+    assert!(!ripe.contains("% "));
+
+    // a `%` which doesn't begin the line, or a `%` field, is data
+    assert_eq!(
+        "descr: 100% uptime\r\n%status: active\r\n",
+        strip_percent_comments("% comment\r\ndescr: 100% uptime\r\n  %\r\n%status: active\r\n")
+    );
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("193.0.0.1").unwrap();
+    options.server = Some(MockServer::respond(include_str!("data/ripe.txt")).server());
+    options.strip_percent_comments = true;
+
+    let body = who.lookup(options).unwrap();
+
+    assert!(!body.contains('%'));
+    assert!(body.contains("netname:        RIPE-NCC\n"));
+}
+
+#[test]
+fn test_normalize_line_endings() {
+    const EXPECTED: &str = "Domain Name: MÜNCHEN.DE\nStatus: connect\nChanged: \
+                            2024-01-01\nNserver: ns1.例え.jp\n\nEnd\n";
+
+    assert_eq!(EXPECTED, normalize_line_endings(include_str!("data/mixed_line_endings.txt")));
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("magiclen.org").unwrap();
+    options.server =
+        Some(MockServer::respond(include_str!("data/mixed_line_endings.txt")).server());
+    options.normalize_line_endings = true;
+
+    assert_eq!(EXPECTED, who.lookup(options).unwrap());
+}
+
+#[test]
+fn test_idn() {
+    let who =
+        WhoIs::from_string(r#"{"de": "whois.denic.de", "_": {"ip": "whois.arin.net"}}"#).unwrap();
+
+    for target in ["münchen.de", "MÜNCHEN.DE", "xn--mnchen-3ya.de"] {
+        let mock = MockServer::respond("Domain: xn--mnchen-3ya.de\n");
+
+        let mut options = WhoIsLookupOptions::from_string(target).unwrap();
+        options.server = Some(mock.server());
+
+        who.lookup(options).unwrap();
+
+        assert_eq!("xn--mnchen-3ya.de\r\n", mock.queries.recv().unwrap());
+    }
+}
+
+#[test]
+fn test_idn_tld() {
+    let server = MockServer::respond("domain: XN--E1AFMKFD.XN--P1AI\n");
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"рф": "{}", "_": {{"ip": "whois.arin.net"}}}}"#,
+        server.addr
+    ))
+    .unwrap();
+
+    who.lookup(WhoIsLookupOptions::from_string("пример.рф").unwrap()).unwrap();
+
+    assert_eq!("xn--e1afmkfd.xn--p1ai\r\n", server.queries.recv().unwrap());
+
+    let server = MockServer::respond("domain: XN--E1AFMKFD.XN--P1AI\n");
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"рф": "127.0.0.1:1", "xn--p1ai": "{}", "_": {{"ip": "whois.arin.net"}}}}"#,
+        server.addr
+    ))
+    .unwrap();
+
+    who.lookup(WhoIsLookupOptions::from_string("пример.рф").unwrap()).unwrap();
+
+    assert_eq!("xn--e1afmkfd.xn--p1ai\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_host_resolver() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.TEST\r\n");
+
+    let mut who =
+        WhoIs::from_string(r#"{"test": "whois.nic.test", "_": {"ip": "whois.arin.net"}}"#).unwrap();
+
+    let (sender, requests) = mpsc::channel();
+    let sender = std::sync::Mutex::new(sender);
+
+    who.set_host_resolver(Box::new(move |host, port| {
+        sender.lock().unwrap().send((host.to_string(), port)).unwrap();
+
+        if host == "whois.nic.test" {
+            Ok(vec![server.addr])
+        } else {
+            Ok(Vec::new())
+        }
+    }));
+
+    let result = who.lookup(WhoIsLookupOptions::from_string("example.test").unwrap()).unwrap();
+
+    assert_eq!("Domain Name: EXAMPLE.TEST\r\n", result);
+    assert_eq!("example.test\r\n", server.queries.recv().unwrap());
+    assert_eq!((String::from("whois.nic.test"), 43), requests.recv().unwrap());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_host_resolver_async() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.TEST\r\n");
+
+    let mut who =
+        WhoIs::from_string(r#"{"test": "whois.nic.test", "_": {"ip": "whois.arin.net"}}"#).unwrap();
+
+    who.set_host_resolver(Box::new(move |_, _| Ok(vec![server.addr])));
+
+    let result =
+        who.lookup_async(WhoIsLookupOptions::from_string("example.test").unwrap()).await.unwrap();
+
+    assert_eq!("Domain Name: EXAMPLE.TEST\r\n", result);
+    assert_eq!("example.test\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_referral_with_port() {
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\r\n");
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(
+        MockServer::respond(
+            "NetRange: 192.0.2.0 - 192.0.2.255\r\nReferralServer: \
+             rwhois://rwhois.example.net:4321/\r\n",
+        )
+        .server(),
+    );
+
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    who.set_host_resolver(Box::new(move |host, port| {
+        if host == "rwhois.example.net" && port == 4321 {
+            Ok(vec![server.addr])
+        } else {
+            Ok(Vec::new())
+        }
+    }));
+
+    assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\r\n", who.lookup(options).unwrap());
+    assert_eq!("192.0.2.1\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_rwhois_referral() {
+    const ARIN: &str =
+        "NetRange: 192.0.2.0 - 192.0.2.255\r\nReferralServer: rwhois://rwhois.example.net\r\n";
+
+    let server = MockServer::respond("network:IP-Network:192.0.2.0/24\r\n");
+
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    who.set_host_resolver(Box::new(move |host, port| {
+        if host == "rwhois.example.net" && port == 4321 {
+            Ok(vec![server.addr])
+        } else {
+            Ok(Vec::new())
+        }
+    }));
+
+    // the default RWhois port is used instead of 43
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(MockServer::respond(ARIN).server());
+
+    assert_eq!("network:IP-Network:192.0.2.0/24\r\n", who.lookup(options).unwrap());
+    assert_eq!("192.0.2.1\r\n", server.queries.recv().unwrap());
+
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = events.clone();
+
+    who.set_observer(Box::new(move |event| recorded.lock().unwrap().push(event.clone())));
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(MockServer::respond(ARIN).server());
+    options.rwhois_referrals = RWhoisPolicy::Skip;
+
+    assert_eq!(ARIN, who.lookup(options).unwrap());
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [WhoIsEvent::SkippedReferral { referral, .. }] if referral == "rwhois.example.net"
+    ));
+}
+
+#[test]
+fn test_allowed_referral_hosts() {
+    const REGISTRY: &str =
+        "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: whois.registrar.test\r\n";
+    const REGISTRAR: &str = "Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n";
+
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let server = MockServer::respond(REGISTRAR);
+
+    who.set_host_resolver(Box::new(move |host, _| {
+        if host == "whois.registrar.test" {
+            Ok(vec![server.addr])
+        } else {
+            Ok(Vec::new())
+        }
+    }));
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(MockServer::respond(REGISTRY).server());
+    options.allowed_referral_hosts = Some(HashSet::from([String::from("whois.other.test")]));
+
+    assert_eq!(REGISTRY, who.lookup(options.clone()).unwrap());
+
+    options.server = Some(MockServer::respond(REGISTRY).server());
+    options.allowed_referral_hosts = Some(HashSet::from([String::from("whois.registrar.test")]));
+
+    assert_eq!(REGISTRAR, who.lookup(options).unwrap());
+}
+
+#[test]
+fn test_who_is_host() {
+    let server = WhoIsServerValue::from_string("whois.arin.net").unwrap();
+
+    assert_eq!(None, server.host.port());
+    assert_eq!("whois.arin.net:43", server.host.to_addr_string(43));
+
+    let server = WhoIsServerValue::from_string("[2001:db8::1]:4321").unwrap();
+
+    assert!(matches!(server.host.host(), validators::models::Host::IPv6(_)));
+    assert_eq!(Some(4321), server.host.port());
+    assert_eq!("[2001:db8::1]:4321", server.host.to_addr_string(43));
+}
+
+#[test]
+fn test_from_host_with() {
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n");
+
+    let who = WhoIs::from_host_with(server.addr.to_string(), "n + $addr\r\n").unwrap();
+
+    who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap();
+
+    assert_eq!("n + 192.0.2.1\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_insert_server_copy_on_write() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.TEST\r\n");
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut cloned = who.clone();
+
+    assert!(cloned
+        .insert_server("test", WhoIsServerValue::from_string(server.addr.to_string()).unwrap())
+        .is_none());
+
+    assert!(matches!(
+        who.lookup(WhoIsLookupOptions::from_string("example.test").unwrap()),
+        Err(WhoIsError::MapError(_))
+    ));
+
+    cloned.lookup(WhoIsLookupOptions::from_string("example.test").unwrap()).unwrap();
+
+    assert_eq!("example.test\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_strict_query_placeholder() {
+    const SERVERS: &str = r#"{"org": {"host": "whois.pir.org", "query": "domain\r\n"}, "_": {"ip": "whois.arin.net"}}"#;
+
+    assert!(WhoIs::from_string(SERVERS).is_ok());
+    match WhoIs::from_string_strict(SERVERS) {
+        Err(WhoIsError::InvalidServer {
+            key,
+            source,
+        }) => {
+            assert_eq!("org", key);
+            assert!(matches!(*source, WhoIsError::MapError(_)));
+        },
+        other => panic!("{:?}", other),
+    }
+
+    assert!(WhoIs::from_string_strict(
+        r#"{"org": {"host": "whois.pir.org", "query": "domain $addr\r\n"}, "_": {"ip": "whois.arin.net"}}"#
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_lookup_to_writer() {
+    const REGISTRAR: &str = "Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n";
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let server = MockServer::respond(REGISTRAR);
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n",
+            server.addr
+        ))
+        .server(),
+    );
+
+    let mut out = Vec::new();
+
+    who.lookup_to_writer(options.clone(), &mut out).unwrap();
+
+    assert_eq!(REGISTRAR.as_bytes(), out.as_slice());
+
+    options.server = Some(MockServer::respond(REGISTRAR).server());
+    options.follow = 0;
+
+    let mut out = Vec::new();
+
+    who.lookup_to_writer(options, &mut out).unwrap();
+
+    assert_eq!(REGISTRAR.as_bytes(), out.as_slice());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_to_async_writer() {
+    const REGISTRAR: &str = "Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n";
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let server = MockServer::respond(REGISTRAR);
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n",
+            server.addr
+        ))
+        .server(),
+    );
+
+    let mut out = Vec::new();
+
+    who.lookup_to_async_writer(options, &mut out).await.unwrap();
+
+    assert_eq!(REGISTRAR.as_bytes(), out.as_slice());
+}
+
+#[test]
+fn test_lookup_to_writer_post_processing() {
+    const THROTTLE: &str = "WHOIS LIMIT EXCEEDED - SEE WWW.PIR.ORG/WHOIS FOR DETAILS\r\n";
+    const REGISTRAR: &str = "% Comment\r\nDomain Name: EXAMPLE.COM\r\n";
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap().no_follow();
+    options.server = Some(MockServer::respond(THROTTLE).server());
+    options.detect_rate_limit = true;
+
+    let mut out = Vec::new();
+
+    assert!(matches!(
+        who.lookup_to_writer(options.clone(), &mut out),
+        Err(WhoIsError::RateLimited { .. })
+    ));
+    assert!(out.is_empty());
+
+    // the body is processed like in `lookup`, also when a referral fails
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    for response in
+        [String::from(REGISTRAR), format!("{}ReferralServer: whois://{}\r\n", REGISTRAR, refused)]
+    {
+        options.server = Some(MockServer::respond(response.as_str()).server());
+        options.follow = 1;
+        options.strip_percent_comments = true;
+        options.normalize_line_endings = true;
+
+        let expected = who.lookup(options.clone()).unwrap();
+
+        options.server = Some(MockServer::respond(response.as_str()).server());
+
+        let mut out = Vec::new();
+
+        who.lookup_to_writer(options.clone(), &mut out).unwrap();
+
+        assert!(expected.starts_with("Domain Name: EXAMPLE.COM\n"));
+        assert_eq!(expected.as_bytes(), out.as_slice());
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_to_async_writer_post_processing() {
+    const THROTTLE: &str = "% Query rate limit exceeded. Try again later.\n";
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap().no_follow();
+    options.server = Some(MockServer::respond(THROTTLE).server());
+    options.detect_rate_limit = true;
+
+    let mut out = Vec::new();
+
+    assert!(matches!(
+        who.lookup_to_async_writer(options.clone(), &mut out).await,
+        Err(WhoIsError::RateLimited { .. })
+    ));
+    assert!(out.is_empty());
+
+    options.server =
+        Some(MockServer::respond("% Comment\r\nDomain Name: EXAMPLE.COM\r\n").server());
+    options.detect_rate_limit = false;
+    options.trim_boilerplate = true;
+
+    who.lookup_to_async_writer(options, &mut out).await.unwrap();
+
+    assert_eq!(b"Domain Name: EXAMPLE.COM\r\n".as_slice(), out.as_slice());
+}
+
+#[test]
+fn test_max_connections() {
+    let third = MockServer::respond("Third\r\n");
+    let second =
+        MockServer::respond(format!("Second\r\nReferralServer: whois://{}\r\n", third.addr));
+    let first =
+        MockServer::respond(format!("First\r\nReferralServer: whois://{}\r\n", second.addr))
+            .server();
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(first);
+    options.follow = u16::MAX;
+    options.max_connections = 2;
+
+    assert!(who.lookup(options).unwrap().starts_with("Second\r\n"));
+    assert!(third.queries.try_recv().is_err());
+}
+
+#[test]
+fn test_lookup_batch_same_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        loop {
+            let mut query = String::new();
+
+            if reader.read_line(&mut query).unwrap() == 0 {
+                break;
+            }
+
+            let response = format!("#\n# ARIN WHOIS data\n#\n\nNetRange: {}\n", query.trim_end());
+
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let server = WhoIsServerValue::from_string(addr.to_string()).unwrap();
+
+    let targets: Vec<Target> = ["192.0.2.1", "198.51.100.1", "203.0.113.1"]
+        .iter()
+        .map(|target| WhoIsLookupOptions::from_string(*target).unwrap().target)
+        .collect();
+
+    let results = who.lookup_batch_same_server(&server, &targets);
+
+    assert_eq!(3, results.len());
+    assert_eq!("#\n# ARIN WHOIS data\n#\n\nNetRange: 192.0.2.1\n", results[0].as_ref().unwrap());
+    assert_eq!("#\n# ARIN WHOIS data\n#\n\nNetRange: 198.51.100.1\n", results[1].as_ref().unwrap());
+    assert_eq!("#\n# ARIN WHOIS data\n#\n\nNetRange: 203.0.113.1\n", results[2].as_ref().unwrap());
+}
+
+#[test]
+fn test_server_timeout() {
+    let addr = MockServer::spawn(vec![Script::Stall]).addr;
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"test": {{"host": "{}", "timeout_ms": 100}}, "_": {{"ip": "whois.arin.net"}}}}"#,
+        addr
+    ))
+    .unwrap();
+
+    let options = WhoIsLookupOptions::from_string("example.test").unwrap();
+
+    assert_eq!(Some(Duration::from_secs(60)), options.timeout);
+
+    let start = Instant::now();
+
+    match who.lookup(options) {
+        Err(WhoIsError::Timeout {
+            addr: error_addr,
+        }) => assert_eq!(addr.to_string(), error_addr),
+        result => panic!("{:?}", result),
+    }
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    assert!(WhoIs::from_string(
+        r#"{"test": {"host": "whois.nic.test", "timeout_ms": "100"}, "_": {"ip": "whois.arin.net"}}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_tcp_nodelay() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+
+    assert!(options.tcp_nodelay);
+
+    for tcp_nodelay in [true, false] {
+        options.server = Some(MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n").server());
+        options.tcp_nodelay = tcp_nodelay;
+
+        assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\n", who.lookup(options.clone()).unwrap());
+    }
+}
+
+#[test]
+fn test_lookup_detailed() {
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n");
+
+    let who = WhoIs::from_host(server.addr.to_string()).unwrap();
+
+    let result =
+        who.lookup_detailed(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap();
+
+    assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\n", result.body);
+    assert_eq!(server.addr.to_string(), result.server);
+    assert_eq!(server.addr, result.socket_addr);
+    assert_eq!(vec![server.addr.to_string()], result.chain);
+    assert!(!result.followed);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_detailed_async() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let registrar = MockServer::respond("Domain Name: EXAMPLE.COM\r\n");
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(WhoIsServerValue::from_string(registry.addr.to_string()).unwrap());
+
+    let result = who.lookup_detailed_async(options).await.unwrap();
+
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", result.body);
+    assert_eq!(registrar.addr.to_string(), result.server);
+    assert_eq!(vec![registry.addr.to_string(), registrar.addr.to_string()], result.chain);
+    assert!(result.followed);
+}
+
+#[test]
+fn test_follow_controls() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let third = MockServer::respond("Third\r\n");
+    let second =
+        MockServer::respond(format!("Second\r\nReferralServer: whois://{}\r\n", third.addr));
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(
+        MockServer::respond(format!("First\r\nReferralServer: whois://{}\r\n", second.addr))
+            .server(),
+    );
+    options.follow_thin_only = true;
+
+    let result = who.lookup_detailed(options).unwrap();
+
+    assert!(result.body.starts_with("Second\r\n"));
+    assert!(result.followed);
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap().no_follow();
+    options.server = Some(
+        MockServer::respond(format!("First\r\nReferralServer: whois://{}\r\n", third.addr))
+            .server(),
+    );
+
+    assert_eq!(0, options.follow);
+
+    let result = who.lookup_detailed(options).unwrap();
+
+    assert!(result.body.starts_with("First\r\n"));
+    assert!(!result.followed);
+}
+
+#[test]
+fn test_without_ip_server() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.TEST\r\n");
+
+    let who = WhoIs::from_string(format!(r#"{{"test": "{}"}}"#, server.addr)).unwrap();
+
+    assert_eq!(
+        "Domain Name: EXAMPLE.TEST\r\n",
+        who.lookup(WhoIsLookupOptions::from_string("example.test").unwrap()).unwrap()
+    );
+
+    match who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()) {
+        Err(WhoIsError::MapError(message)) => assert!(message.contains("IP lookups")),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_default_domain_server() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.UNKNOWN\r\n");
+
+    let mut who = WhoIs::from_string(format!(
+        r#"{{"org": "whois.pir.org", "": "{}", "_": {{"ip": "whois.arin.net"}}}}"#,
+        server.addr
+    ))
+    .unwrap();
+
+    assert!(who.default_domain_server().is_some());
+
+    who.lookup(WhoIsLookupOptions::from_string("example.unknown").unwrap()).unwrap();
+
+    assert_eq!("example.unknown\r\n", server.queries.recv().unwrap());
+
+    who.set_default_domain_server(None);
+
+    assert!(matches!(
+        who.lookup(WhoIsLookupOptions::from_string("example.unknown").unwrap()),
+        Err(WhoIsError::MapError(_))
+    ));
+}
+
+#[test]
+fn test_referral_validation() {
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let (sender, events) = mpsc::channel();
+    let sender = std::sync::Mutex::new(sender);
+
+    who.set_observer(Box::new(move |event| {
+        sender.lock().unwrap().send(event.clone()).unwrap();
+    }));
+
+    let mock = MockServer::respond("Registrant Name: Example\r\n");
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: \r\nReferralServer: \
+             whois://{}\r\n",
+            mock.addr
+        ))
+        .server(),
+    );
+
+    assert_eq!("Registrant Name: Example\r\n", who.lookup(options).unwrap());
+    assert_eq!("example.com\r\n", mock.queries.recv().unwrap());
+    assert!(events.try_recv().is_err());
+
+    let first =
+        MockServer::respond("Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: not a host!\r\n");
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(first.server());
+
+    assert_eq!(
+        "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: not a host!\r\n",
+        who.lookup(options).unwrap()
+    );
+    assert_eq!(
+        WhoIsEvent::InvalidReferral {
+            server:   first.addr.to_string(),
+            referral: String::from("not a host!"),
+        },
+        events.recv().unwrap()
+    );
+}
+
+#[test]
+fn test_display() {
+    let options = WhoIsLookupOptions::from_string("2001:db8::1").unwrap();
+
+    assert_eq!("2001:db8::1", options.target.to_string());
+    assert_eq!(
+        "192.0.2.1",
+        WhoIsLookupOptions::from_string("192.0.2.1").unwrap().target.to_string()
+    );
+    assert_eq!(
+        "xn--mnchen-3ya.de",
+        WhoIsLookupOptions::from_string("münchen.de").unwrap().target.to_string()
+    );
+
+    let mock = MockServer::respond("inet6num: 2001:db8::/32\n");
+
+    let mut options = options;
+    options.server = Some(mock.server());
+
+    WhoIs::from_host("whois.arin.net").unwrap().lookup(options).unwrap();
+
+    assert_eq!("2001:db8::1\r\n", mock.queries.recv().unwrap());
+
+    assert_eq!(
+        "whois.arin.net",
+        WhoIsServerValue::from_string("whois.arin.net").unwrap().host.to_string()
+    );
+    assert_eq!(
+        "whois.arin.net:4321",
+        WhoIsServerValue::from_string("whois.arin.net:4321").unwrap().host.to_string()
+    );
+    assert_eq!(
+        "2001:db8::1",
+        WhoIsServerValue::from_string("2001:db8::1").unwrap().host.to_string()
+    );
+    assert_eq!(
+        "[2001:db8::1]:43",
+        WhoIsServerValue::from_string("[2001:db8::1]:43").unwrap().host.to_string()
+    );
+}
+
+#[test]
+fn test_connect_error() {
+    // bind and drop a listener to get a local port which refuses connections
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let who = WhoIs::from_host(addr.to_string()).unwrap();
+
+    let options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+
+    let error = who.lookup(options).unwrap_err();
+
+    assert!(
+        matches!(&error, WhoIsError::ConnectError { addr: error_addr, .. } if *error_addr == addr.to_string())
+    );
+    assert!(error.to_string().starts_with(&format!("{}: ", addr)));
+    assert!(std::error::Error::source(&error).is_some());
+}
+
+#[cfg(feature = "system-whois")]
+#[test]
+fn test_system_fallback() {
+    let who = WhoIs::from_string(r#"{"org": "whois.pir.org"}"#).unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.unknowntld").unwrap();
+
+    assert!(!options.system_fallback);
+    assert!(matches!(who.lookup(options.clone()), Err(WhoIsError::MapError(_))));
+
+    options.system_fallback = true;
+
+    // the whois command may be missing or unable to reach the network, but the list is no longer the reason of the failure
+    assert!(!matches!(who.lookup(options), Err(WhoIsError::MapError(_))));
+}
+
+#[test]
+fn test_with_server_host() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.IO\r\n");
+
+    let who = WhoIs::from_string(r#"{"org": "whois.pir.org"}"#).unwrap();
+
+    let options = WhoIsLookupOptions::from_string("example.io")
+        .unwrap()
+        .with_server_host(server.addr.to_string())
+        .unwrap();
+
+    assert_eq!(server.addr.to_string(), options.server.as_ref().unwrap().host.to_string());
+    assert_eq!("Domain Name: EXAMPLE.IO\r\n", who.lookup(options).unwrap());
+    assert_eq!("example.io\r\n", server.queries.recv().unwrap());
+
+    assert!(WhoIsLookupOptions::from_string("example.io")
+        .unwrap()
+        .with_server_host("exa mple")
+        .is_err());
+}
+
+#[test]
+fn test_compressed_response() {
+    let server = MockServer::spawn(vec![
+        Script::Respond(include_bytes!("data/gzip.bin").to_vec()),
+        Script::Respond(include_bytes!("data/zlib.bin").to_vec()),
+        Script::respond("x^ looks like a zlib header\r\n"),
+    ]);
+
+    let who = WhoIs::from_host(server.addr.to_string()).unwrap();
+
+    let lookup = || who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap());
+
+    #[cfg(feature = "compression")]
+    {
+        assert_eq!("Domain Name: EXAMPLE.ORG\r\n", lookup().unwrap());
+        assert_eq!(include_str!("data/verisign.txt"), lookup().unwrap());
+    }
+
+    #[cfg(not(feature = "compression"))]
+    for _ in 0..2 {
+        let error = lookup().unwrap_err();
+
+        assert!(error.to_string().contains("compressed"), "{}", error);
+    }
+
+    // plain text which happens to start with a zlib header is kept as it is
+    assert_eq!("x^ looks like a zlib header\r\n", lookup().unwrap());
+}
+
+/// Decompress a reference corpus, which `data/inflate_corpus.py` generates with Python's `zlib` at every level and strategy in both the zlib and gzip formats, and check that truncated or corrupted copies of it never panic and never decompress to anything else.
+#[cfg(feature = "compression")]
+#[test]
+fn test_inflate_corpus() {
+    let payloads = [include_str!("data/verisign.txt"), include_str!("data/inflate_large.txt"), ""];
+
+    let mut entries = Vec::new();
+    let mut corpus: &[u8] = include_bytes!("data/inflate_corpus.bin");
+
+    while !corpus.is_empty() {
+        let length = u32::from_le_bytes([corpus[1], corpus[2], corpus[3], corpus[4]]) as usize;
+
+        entries.push((corpus[0], &corpus[5..5 + length]));
+        corpus = &corpus[5 + length..];
+    }
+
+    assert!(entries.len() > 100);
+
+    let response = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let mut who = WhoIs::from_host("whois.example.test").unwrap();
+
+    let served = response.clone();
+
+    who.set_transport_connector(Box::new(move |_| {
+        Ok(Box::new(Pipe {
+            query:    Default::default(),
+            response: std::io::Cursor::new(served.lock().unwrap().clone()),
+            accepted: None,
+        }))
+    }));
+
+    let lookup = |data: &[u8]| {
+        *response.lock().unwrap() = data.to_vec();
+
+        let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+        options.follow = 0;
+
+        who.lookup(options)
+    };
+
+    // a deterministic xorshift generator, so that a failure can be reproduced
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+
+    let mut next = |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+
+        (state % bound as u64) as usize
+    };
+
+    for &(id, data) in entries.iter() {
+        let Some(&payload) = payloads.get(usize::from(id)) else {
+            // a preset dictionary is not supported, so the data is kept and it is not UTF-8
+            assert!(lookup(data).is_err());
+
+            continue;
+        };
+
+        assert_eq!(payload, lookup(data).unwrap());
+
+        let check = |mutated: &[u8], lookup_result: Result<String, WhoIsError>| {
+            if let Ok(body) = lookup_result {
+                // undecodable data is kept as it is, and a complete first gzip member is enough
+                assert!(body.as_bytes() == mutated || body == payload, "{:?}", mutated);
+            }
+        };
+
+        let cuts = (0..data.len().min(24))
+            .chain((0..32).map(|i| i * data.len() / 32))
+            .chain(data.len().saturating_sub(16)..data.len());
+
+        for cut in cuts {
+            check(&data[..cut], lookup(&data[..cut]));
+        }
+
+        if data.len() <= 8192 {
+            for _ in 0..64 {
+                let mut mutated = data.to_vec();
+
+                mutated[next(data.len())] ^= 1 << next(8);
+
+                check(&mutated, lookup(&mutated));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_server_timeout_async() {
+    let addr = MockServer::spawn(vec![Script::Stall]).addr;
+
+    let who = WhoIs::from_host(addr.to_string()).unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.timeout = Some(Duration::from_millis(100));
+
+    assert!(matches!(
+        who.lookup_async(options).await,
+        Err(WhoIsError::Timeout { addr: timed_out }) if timed_out == addr.to_string()
+    ));
+}
+
+#[test]
+fn test_follow_ceiling() {
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+
+    assert!(options.try_set_follow(MAX_FOLLOW).is_ok());
+    assert_eq!(MAX_FOLLOW, options.follow);
+    assert!(options.try_set_follow(MAX_FOLLOW + 1).is_err());
+    assert_eq!(MAX_FOLLOW, options.follow);
+
+    // a referral chain which is longer than the ceiling
+    let last = MockServer::respond("Last\r\n");
+    let mut next = last.addr;
+
+    for i in (0..=MAX_FOLLOW).rev() {
+        next =
+            MockServer::respond(format!("Hop {}\r\nReferralServer: whois://{}\r\n", i, next)).addr;
+    }
+
+    let who = WhoIs::from_host(next.to_string()).unwrap();
+
+    options.follow = u16::MAX;
+    options.max_connections = u16::MAX;
+
+    assert!(who.lookup(options).unwrap().starts_with(&format!("Hop {}\r\n", MAX_FOLLOW)));
+    assert!(last.queries.try_recv().is_err());
+}
+
+#[cfg(feature = "jsonc")]
+#[test]
+fn test_jsonc() {
+    use std::sync::{Arc, Mutex};
+
+    let mut who = WhoIs::from_jsonc_path("tests/data/commented_servers.jsonc").unwrap();
+
+    // record the resolved hosts instead of connecting
+    let hosts = Arc::new(Mutex::new(Vec::new()));
+    let recorded_hosts = hosts.clone();
+
+    who.set_host_resolver(Box::new(move |host, _| {
+        recorded_hosts.lock().unwrap().push(host.to_string());
+
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "recorded"))
+    }));
+
+    for domain in ["example.org", "example.io", "example.com", "192.0.2.1"] {
+        assert!(who.lookup(WhoIsLookupOptions::from_string(domain).unwrap()).is_err());
+    }
+
+    assert_eq!(
+        vec!["whois.pir.org", "whois.nic.io", "whois.verisign-grs.com", "whois.arin.net"],
+        *hosts.lock().unwrap()
+    );
+
+    assert!(WhoIs::from_string(include_str!("data/commented_servers.jsonc")).is_err());
+    assert!(WhoIs::from_jsonc_string(r#"{"org": "whois.pir.org",, }"#).is_err());
+}
+
+#[test]
+fn test_parse_key_values() {
+    let arin = parse_key_values(include_str!("data/arin.txt"));
+
+    assert_eq!(("NetRange".to_string(), "8.8.8.0 - 8.8.8.255".to_string()), arin[0]);
+    assert_eq!(
+        Some("https://rdap.arin.net/registry/ip/8.8.8.0"),
+        arin.iter().find(|(key, _)| key == "Ref").map(|(_, value)| value.as_str())
+    );
+    assert_eq!(3, arin.iter().filter(|(key, _)| key == "Comment").count());
+    assert!(arin.iter().all(|(key, _)| !key.starts_with('#')));
+
+    let verisign = parse_key_values(include_str!("data/verisign.txt"));
+
+    assert_eq!(("Domain Name".to_string(), "EXAMPLE.COM".to_string()), verisign[0]);
+    assert_eq!(
+        vec!["A.IANA-SERVERS.NET", "B.IANA-SERVERS.NET"],
+        verisign
+            .iter()
+            .filter(|(key, _)| key == "Name Server")
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert!(verisign.iter().all(|(_, value)| !value.ends_with('\r')));
+}
+
+#[test]
+fn test_parse_ip_record() {
+    let arin = parse_ip_record(include_str!("data/arin.txt"));
+
+    assert_eq!(Some("8.8.8.0 - 8.8.8.255"), arin.range.as_deref());
+    assert_eq!(Some("8.8.8.0/24"), arin.cidr.as_deref());
+    assert_eq!(Some("Google LLC"), arin.org_name.as_deref());
+    assert_eq!(Some("US"), arin.country.as_deref());
+    assert_eq!(Some("network-abuse@google.com"), arin.abuse_email.as_deref());
+    assert_eq!(include_str!("data/arin.txt"), arin.body);
+
+    let ripe = parse_ip_record(include_str!("data/ripe_organisation.txt"));
+
+    assert_eq!(Some("193.0.0.0 - 193.0.7.255"), ripe.range.as_deref());
+    assert_eq!(Some("193.0.0.0/21"), ripe.cidr.as_deref());
+    assert_eq!(
+        Some("Reseaux IP Europeens Network Coordination Centre (RIPE NCC)"),
+        ripe.org_name.as_deref()
+    );
+    assert_eq!(Some("NL"), ripe.country.as_deref());
+    assert_eq!(Some("abuse@ripe.net"), ripe.abuse_email.as_deref());
+
+    let apnic = parse_ip_record(include_str!("data/apnic.txt"));
+
+    assert_eq!(Some("1.1.1.0/24"), apnic.cidr.as_deref());
+    assert_eq!(None, apnic.org_name);
+    assert_eq!(Some("AU"), apnic.country.as_deref());
+    assert_eq!(Some("helpdesk@apnic.net"), apnic.abuse_email.as_deref());
+
+    let record = parse_ip_record("inetnum: 10.0.0.0 - 10.0.0.2\ninet6num: 2001:db8::/32\n");
+
+    assert_eq!(None, record.cidr);
+    assert_eq!(Some("2001:db8::/32"), parse_ip_record("inet6num: 2001:db8::/32\n").cidr.as_deref());
+}
+
+#[test]
+fn test_max_total_duration() {
+    let options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap().unbounded();
+
+    assert_eq!(None, options.timeout);
+    assert_eq!(None, options.max_total_duration);
+
+    // each read succeeds quickly, so only the total duration can stop the lookup
+    let addr = MockServer::spawn(vec![Script::Trickle(b"% still here\r\n".to_vec())]).addr;
+
+    let who = WhoIs::from_host(addr.to_string()).unwrap();
+
+    let mut options = options;
+    options.max_total_duration = Some(Duration::from_millis(300));
+
+    let start = Instant::now();
+
+    assert!(matches!(who.lookup(options), Err(WhoIsError::Timeout { .. })));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_max_total_duration_async() {
+    let addr = MockServer::spawn(vec![Script::Trickle(b"% still here\r\n".to_vec())]).addr;
+
+    let who = WhoIs::from_host(addr.to_string()).unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap().unbounded();
+    options.max_total_duration = Some(Duration::from_millis(300));
+
+    let start = Instant::now();
+
+    assert!(matches!(
+        who.lookup_async(options).await,
+        Err(WhoIsError::Timeout { addr: timed_out }) if timed_out == addr.to_string()
+    ));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+/// Accept one connection as a SOCKS5 proxy, then answer the tunnelled query. The requested `host:port` is sent to the receiver.
+fn spawn_socks5_proxy(response: &'static str) -> (SocketAddr, Receiver<String>) {
+    use std::io::Read;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut greeting = [0; 3];
+        stream.read_exact(&mut greeting).unwrap();
+        assert_eq!([5, 1, 0], greeting);
+        stream.write_all(&[5, 0]).unwrap();
+
+        let mut header = [0; 5];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!([5, 1, 0, 3], header[..4]);
+
+        let mut host = vec![0; usize::from(header[4])];
+        stream.read_exact(&mut host).unwrap();
+
+        let mut port = [0; 2];
+        stream.read_exact(&mut port).unwrap();
+
+        stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 43]).unwrap();
+
+        let mut reader = BufReader::new(stream);
+
+        let mut query = String::new();
+        reader.read_line(&mut query).unwrap();
+
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+
+        let _ = sender.send(format!(
+            "{}:{}",
+            String::from_utf8(host).unwrap(),
+            u16::from_be_bytes(port)
+        ));
+    });
+
+    (addr, receiver)
+}
+
+/// Accept one connection as an HTTP proxy, then answer the tunnelled query. The `CONNECT` request line is sent to the receiver.
+fn spawn_http_proxy(response: &'static str) -> (SocketAddr, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        reader.get_mut().write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").unwrap();
+
+        let mut query = String::new();
+        reader.read_line(&mut query).unwrap();
+
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+
+        let _ = sender.send(request_line);
+    });
+
+    (addr, receiver)
+}
+
+#[test]
+fn test_env_proxy() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(WhoIsServerValue::from_string("whois.example.test:4343").unwrap());
+    options.timeout = Some(Duration::from_secs(5));
+
+    assert!(!options.use_env_proxy);
+
+    options.use_env_proxy = true;
+
+    // all cases share the process environment, so they run in this single test
+    let _env = lock_env();
+
+    std::env::remove_var("all_proxy");
+    std::env::remove_var("no_proxy");
+    std::env::remove_var("NO_PROXY");
+
+    let (proxy, receiver) = spawn_socks5_proxy("Through SOCKS5\r\n");
+    std::env::set_var("ALL_PROXY", format!("socks5h://{}", proxy));
+
+    assert_eq!("Through SOCKS5\r\n", who.lookup(options.clone()).unwrap());
+    assert_eq!("whois.example.test:4343", receiver.recv().unwrap());
+
+    let (proxy, receiver) = spawn_http_proxy("Through HTTP\r\n");
+    std::env::set_var("ALL_PROXY", format!("http://{}/", proxy));
+
+    assert_eq!("Through HTTP\r\n", who.lookup(options.clone()).unwrap());
+    assert_eq!("CONNECT whois.example.test:4343 HTTP/1.1\r\n", receiver.recv().unwrap());
+
+    #[cfg(feature = "tokio")]
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        let (proxy, receiver) = spawn_socks5_proxy("Through SOCKS5\r\n");
+        std::env::set_var("ALL_PROXY", format!("socks5://{}", proxy));
+
+        assert_eq!(
+            "Through SOCKS5\r\n",
+            runtime.block_on(who.lookup_async(options.clone())).unwrap()
+        );
+        assert_eq!("whois.example.test:4343", receiver.recv().unwrap());
+
+        // a stalled proxy handshake is bounded by the deadline of the query
+        let proxy = MockServer::spawn(vec![Script::Stall]).addr;
+        std::env::set_var("ALL_PROXY", format!("socks5://{}", proxy));
+
+        let mut stalled_options = options.clone();
+        stalled_options.max_total_duration = Some(Duration::from_millis(300));
+
+        let start = Instant::now();
+
+        assert!(matches!(
+            runtime.block_on(who.lookup_async(stalled_options)),
+            Err(WhoIsError::Timeout { .. })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    // a bypassed host is connected directly
+    let server = MockServer::respond("Direct\r\n").server();
+    std::env::set_var("NO_PROXY", "localhost, 127.0.0.1");
+
+    let mut direct_options = options.clone();
+    direct_options.server = Some(server);
+
+    assert_eq!("Direct\r\n", who.lookup(direct_options).unwrap());
+
+    // the proxy setting is ignored unless it is enabled
+    std::env::set_var("ALL_PROXY", "ftp://127.0.0.1:1");
+
+    assert!(who.lookup(options.clone()).is_err());
+
+    options.use_env_proxy = false;
+    options.server = Some(MockServer::respond("Not proxied\r\n").server());
+
+    assert_eq!("Not proxied\r\n", who.lookup(options).unwrap());
+
+    std::env::remove_var("ALL_PROXY");
+    std::env::remove_var("NO_PROXY");
+}
+
+#[test]
+fn test_large_response() {
+    // about 8 MiB, like the response for a large IP block
+    let mut response = String::from("NetRange: 10.0.0.0 - 10.255.255.255\n");
+
+    while response.len() < 8 * 1024 * 1024 {
+        response.push_str("Comment: Lorem ipsum dolor sit amet, consectetur adipiscing elit.\n");
+    }
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("10.0.0.1").unwrap();
+    options.server = Some(MockServer::respond(response.clone()).server());
+    options.detect_rate_limit = true;
+    options.normalize_line_endings = true;
+
+    let start = Instant::now();
+
+    let (body, class, pattern) = who.lookup_classified(options).unwrap();
+
+    assert_eq!(response, body);
+    assert_eq!(ResponseClass::Found, class);
+    assert_eq!(None, pattern);
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+fn test_registry_only() {
+    const REGISTRY: &str = "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n";
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let registrar = MockServer::respond("Registrant Name: Example\r\n");
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server =
+        Some(MockServer::respond(REGISTRY.replace("{}", &registrar.addr.to_string())).server());
+
+    assert!(!options.registry_only);
+
+    options.registry_only = true;
+
+    let result = who.lookup_detailed(options.clone()).unwrap();
+
+    assert!(result.body.starts_with("Domain Name: EXAMPLE.COM\r\n"));
+    assert!(!result.followed);
+    assert!(registrar.queries.try_recv().is_err());
+
+    options.server =
+        Some(MockServer::respond(REGISTRY.replace("{}", &registrar.addr.to_string())).server());
+    options.registry_only = false;
+
+    let result = who.lookup_detailed(options).unwrap();
+
+    assert_eq!("Registrant Name: Example\r\n", result.body);
+    assert!(result.followed);
+}
+
+#[test]
+fn test_query_without_newline() {
+    use std::io::Read;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut query = vec![0; "192.0.2.1".len()];
+        stream.read_exact(&mut query).unwrap();
+
+        // nothing may follow the address
+        stream.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let trailing = stream.read(&mut [0; 16]).map(|n| n > 0).unwrap_or(false);
+
+        stream.write_all(b"NetRange: 192.0.2.0 - 192.0.2.255\n").unwrap();
+
+        let _ = sender.send((String::from_utf8(query).unwrap(), trailing));
+    });
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"_": {{"ip": {{"host": "{}", "query": "$addr"}}}}}}"#,
+        addr
+    ))
+    .unwrap();
+
+    let body = who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap();
+
+    assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\n", body);
+    assert_eq!(("192.0.2.1".to_string(), false), receiver.recv().unwrap());
+}
+
+#[test]
+fn test_contains_tld() {
+    let mut who = WhoIs::from_path("tests/data/servers.json").unwrap();
+
+    assert_eq!(1, who.len());
+    assert!(!who.is_empty());
+
+    assert!(who.contains_tld("org"));
+    assert!(who.contains_tld(".org"));
+    assert!(who.contains_tld("www.magiclen.ORG"));
+    // the default domain server doesn't count
+    assert!(!who.contains_tld("io"));
+
+    who.insert_server("рф", WhoIsServerValue::from_string("whois.tcinet.ru").unwrap());
+    who.insert_server("co.uk", WhoIsServerValue::from_string("whois.nic.uk").unwrap());
+
+    assert_eq!(3, who.len());
+    assert!(who.contains_tld("xn--p1ai"));
+    assert!(who.contains_tld("пример.рф"));
+    assert!(who.contains_tld("example.co.uk"));
+    assert!(!who.contains_tld("uk"));
+
+    assert!(WhoIs::from_host("whois.arin.net").unwrap().is_empty());
+}
+
+#[test]
+fn test_server_value_builder() {
+    let mock = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n");
+
+    let server = WhoIsServerValue::builder(mock.addr.to_string())
+        .unwrap()
+        .query("n + $addr\r\n")
+        .punycode(false)
+        .timeout(Duration::from_secs(5))
+        .build();
+
+    assert_eq!(Some("n + $addr\r\n"), server.query.as_deref());
+    assert!(!server.punycode);
+    assert_eq!(Some(Duration::from_secs(5)), server.timeout);
+
+    let who = WhoIs::from_ip_server(server);
+
+    who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap();
+
+    assert_eq!("n + 192.0.2.1\r\n", mock.queries.recv().unwrap());
+
+    let server = WhoIsServerValue::builder("whois.pir.org").unwrap().build();
+
+    assert_eq!(None, server.query);
+    assert!(server.punycode);
+
+    assert!(WhoIsServerValue::builder("whois pir org").is_err());
+}
+
+#[test]
+fn test_object_servers() {
+    let mut who = WhoIs::from_string(
+        r#"{"_": {"ip": "whois.arin.net", "asn": {"host": "whois.radb.net", "query": "-i origin $addr\r\n"}, "handle": null}}"#,
+    )
+    .unwrap();
+
+    assert_eq!("whois.arin.net", who.object_server("ip").unwrap().host.to_string());
+    assert_eq!(Some("-i origin $addr\r\n"), who.object_server("asn").unwrap().query.as_deref());
+    assert!(who.object_server("handle").is_none());
+
+    // IP lookups use the `ip` server
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n");
+
+    who.insert_object_server("ip", WhoIsServerValue::from_string(server.addr.to_string()).unwrap());
+
+    who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap();
+
+    assert_eq!("192.0.2.1\r\n", server.queries.recv().unwrap());
+
+    // a list without an `ip` server is allowed, and IP lookups require `options.server`
+    assert!(WhoIs::from_string(r#"{"_": {}}"#).unwrap().object_server("ip").is_none());
+    assert!(WhoIs::from_string(r#"{"_": {"ip": null}}"#).unwrap().object_server("ip").is_none());
+}
+
+#[test]
+fn test_object_servers_without_ip() {
+    let server = MockServer::respond("aut-num: AS64496\r\n");
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"com": "whois.verisign-grs.com", "_": {{"asn": {{"host": "{}", "query": "-i origin $addr\r\n"}}}}}}"#,
+        server.addr
+    ))
+    .unwrap();
+
+    assert!(who.object_server("ip").is_none());
+
+    let mut options = WhoIsLookupOptions::from_raw("AS64496");
+    options.server = who.object_server("asn").cloned();
+
+    assert_eq!("aut-num: AS64496\r\n", who.lookup(options).unwrap());
+    assert_eq!("-i origin AS64496\r\n", server.queries.recv().unwrap());
+
+    match who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()) {
+        Err(WhoIsError::MapError(message)) => assert!(message.contains("options.server")),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_build_query() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let arin = WhoIsServerValue::builder("whois.arin.net").unwrap().query("n + $addr\r\n").build();
+
+    assert_eq!(
+        b"n + 192.0.2.1\r\n",
+        who.build_query(&WhoIsLookupOptions::from_string("192.0.2.1").unwrap().target, &arin)
+            .as_bytes()
+    );
+    assert_eq!(
+        "2001:db8::1\r\n",
+        who.build_query(
+            &WhoIsLookupOptions::from_string("2001:db8::1").unwrap().target,
+            &WhoIsServerValue::from_string("whois.arin.net").unwrap()
+        )
+    );
+    assert_eq!(
+        "xn--mnchen-3ya.de\r\n",
+        who.build_query(
+            &WhoIsLookupOptions::from_string("münchen.de").unwrap().target,
+            &WhoIsServerValue::from_string("whois.denic.de").unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_referral_failure() {
+    use std::sync::{Arc, Mutex};
+
+    // bind and drop a listener to get a local port which refuses connections
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let registry = format!("Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n", refused);
+
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded_events = events.clone();
+
+    who.set_observer(Box::new(move |event| recorded_events.lock().unwrap().push(event.clone())));
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(MockServer::respond(registry.clone()).server());
+
+    assert!(!options.fail_on_referral_error);
+
+    let result = who.lookup_detailed(options.clone()).unwrap();
+
+    assert_eq!(registry, result.body);
+    assert!(!result.followed);
+
+    match events.lock().unwrap().as_slice() {
+        [WhoIsEvent::ReferralFailed {
+            referral, ..
+        }] => assert_eq!(refused.to_string(), *referral),
+        events => panic!("{:?}", events),
+    }
+
+    options.server = Some(MockServer::respond(registry.clone()).server());
+
+    let mut out = Vec::new();
+    who.lookup_to_writer(options.clone(), &mut out).unwrap();
+
+    assert_eq!(registry.as_bytes(), out.as_slice());
+
+    options.server = Some(MockServer::respond(registry).server());
+    options.fail_on_referral_error = true;
+
+    assert!(matches!(who.lookup(options), Err(WhoIsError::ConnectError { .. })));
+}
+
+#[test]
+fn test_raw_target() {
+    let server = MockServer::respond("NetHandle: NET-8-8-8-0-1\n");
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_raw(" NET-8-8-8-0-1 ");
+
+    assert!(matches!(who.lookup(options.clone()), Err(WhoIsError::MapError(_))));
+
+    options.server = Some(
+        WhoIsServerValue::builder(server.addr.to_string()).unwrap().query("n ! $addr\r\n").build(),
+    );
+
+    assert_eq!("NetHandle: NET-8-8-8-0-1\n", who.lookup(options).unwrap());
+    assert_eq!("n ! NET-8-8-8-0-1\r\n", server.queries.recv().unwrap());
+
+    // the raw target takes precedence over a parsed one
+    let server = MockServer::respond("organisation: ORG-RIEN1-RIPE\n");
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.raw_target = Some(String::from("ORG-RIEN1-RIPE"));
+    options.server = Some(WhoIsServerValue::from_string(server.addr.to_string()).unwrap());
+
+    who.lookup(options).unwrap();
+
+    assert_eq!("ORG-RIEN1-RIPE\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_read_capacity() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+
+    assert_eq!(4096, options.read_capacity);
+
+    for read_capacity in [0, 1 << 20] {
+        options.read_capacity = read_capacity;
+        options.server = Some(MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n").server());
+
+        assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\n", who.lookup(options.clone()).unwrap());
+    }
+}
+
+#[test]
+fn test_bom() {
+    let who = WhoIs::from_path("tests/data/bom_servers.json").unwrap();
+
+    assert!(who.contains_tld("org"));
+    assert!(WhoIs::from_string(include_str!("data/bom_servers.json")).is_ok());
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(MockServer::respond(include_str!("data/bom_response.txt")).server());
+
+    assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\r\n", who.lookup(options).unwrap());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_bom_async() {
+    let who = WhoIs::from_path_async("tests/data/bom_servers.json").await.unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(MockServer::respond(include_str!("data/bom_response.txt")).server());
+
+    assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\r\n", who.lookup_async(options).await.unwrap());
+}
+
+#[test]
+fn test_charset() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut reader = BufReader::new(stream);
+
+        let mut query = Vec::new();
+        reader.read_until(b'\n', &mut query).unwrap();
+
+        reader.get_mut().write_all(b"person: J\xFCrgen M\xFCller\r\n").unwrap();
+
+        query
+    });
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_raw("M\u{FC}ller");
+    options.server = Some(
+        WhoIsServerValue::from_value(&serde_json::json!({
+            "host": addr.to_string(),
+            "charset": "ISO-8859-1"
+        }))
+        .unwrap(),
+    );
+
+    let result = who.lookup(options).unwrap();
+
+    assert_eq!(b"M\xFCller\r\n".to_vec(), handle.join().unwrap());
+    assert_eq!("person: J\u{FC}rgen M\u{FC}ller\r\n", result);
+
+    assert!(WhoIsServerValue::from_value(&serde_json::json!({
+        "host": "whois.example.com",
+        "charset": "EBCDIC"
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_response_charset() {
+    let server =
+        MockServer::spawn(vec![Script::Respond(include_bytes!("data/windows1251.txt").to_vec())]);
+
+    let who = WhoIs::without_ip_server();
+
+    let mut options = WhoIsLookupOptions::from_string("xn--e1afmkfd.xn--p1ai").unwrap();
+    options.server = Some(
+        WhoIsServerValue::from_value(&serde_json::json!({
+            "host": server.addr.to_string(),
+            "response_charset": "windows-1251"
+        }))
+        .unwrap(),
+    );
+
+    let result = who.lookup(options).unwrap();
+
+    assert_eq!(
+        "% Информация о домене\r\ndomain:        ПРИМЕР.РФ\r\nperson:        Иван \
+         Петров\r\nstate:         REGISTERED, DELEGATED\r\n",
+        result
+    );
+
+    // the query is still sent in UTF-8
+    assert_eq!(["xn--e1afmkfd.xn--p1ai\r\n"], server.queries().as_slice());
+
+    let mut utf16 = vec![0xFF, 0xFE];
+    utf16.extend("domain: пример.рф\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+
+    let server = MockServer::spawn(vec![Script::Respond(utf16)]);
+
+    let mut options = WhoIsLookupOptions::from_string("xn--e1afmkfd.xn--p1ai").unwrap();
+    options.server = Some(
+        WhoIsServerValue::builder(server.addr.to_string())
+            .unwrap()
+            .response_charset(Charset::from_label("UTF-16LE").unwrap())
+            .build(),
+    );
+
+    assert_eq!("domain: пример.рф\r\n", who.lookup(options).unwrap());
+
+    assert!(WhoIsServerValue::from_value(&serde_json::json!({
+        "host": "whois.example.com",
+        "response_charset": "koi8-r"
+    }))
+    .is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_sync_async_parity() {
+    fn spawn_chain() -> WhoIsServerValue {
+        let registrar = MockServer::respond("Domain Name: EXAMPLE.COM\nRegistrant Name: Example\n");
+
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\nRegistrar WHOIS Server: {}\n",
+            registrar.addr
+        ))
+        .server()
+    }
+
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    for follow in [0, 1] {
+        let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+        options.follow = follow;
+
+        let mut sync_options = options.clone();
+        sync_options.server = Some(spawn_chain());
+
+        let mut async_options = options;
+        async_options.server = Some(spawn_chain());
+
+        let sync_result = who.lookup_detailed(sync_options).unwrap();
+        let async_result = who.lookup_detailed_async(async_options).await.unwrap();
+
+        // the registry responses differ only in the registrar.addr address
+        let strip_referral = |body: &str| {
+            body.lines()
+                .filter(|line| !line.starts_with("Registrar WHOIS Server:"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        assert_eq!(strip_referral(&sync_result.body), strip_referral(&async_result.body));
+        assert_eq!(sync_result.followed, async_result.followed);
+        assert_eq!(sync_result.chain.len(), async_result.chain.len());
+    }
+}
+
+#[test]
+fn test_total_timeout() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let registrar = MockServer::spawn(vec![Script::Stall]).addr;
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n",
+            registrar
+        ))
+        .server(),
+    );
+    options.total_timeout = Some(Duration::from_millis(300));
+
+    let start = Instant::now();
+
+    match who.lookup(options) {
+        Err(WhoIsError::Timeout {
+            addr,
+        }) => assert_eq!(registrar.to_string(), addr),
+        other => panic!("{:?}", other),
+    }
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_total_timeout_async() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let registrar = MockServer::spawn(vec![Script::Stall]).addr;
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n",
+            registrar
+        ))
+        .server(),
+    );
+    options.total_timeout = Some(Duration::from_millis(300));
+
+    match who.lookup_async(options).await {
+        Err(WhoIsError::Timeout {
+            addr,
+        }) => assert_eq!(registrar.to_string(), addr),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_ip_ranges() {
+    let ripe = MockServer::respond("inetnum: 192.0.2.0 - 192.0.2.255\r\n");
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"_": {{"ip": "whois.arin.net", "ip_ranges": {{"192.0.0.0/16": "whois.lacnic.net", "192.0.2.0/24": "{}", "2001:200::/23": "whois.apnic.net"}}}}}}"#,
+        ripe.addr
+    ))
+    .unwrap();
+
+    let result = who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap();
+
+    assert_eq!("inetnum: 192.0.2.0 - 192.0.2.255\r\n", result);
+    assert_eq!("192.0.2.1\r\n", ripe.queries.recv().unwrap());
+
+    let host = |ip: &str| who.get_server_by_ip(ip.parse().unwrap()).unwrap().host.to_string();
+
+    assert_eq!("whois.lacnic.net", host("192.0.3.1"));
+    assert_eq!("whois.arin.net", host("198.51.100.1"));
+    assert_eq!("whois.apnic.net", host("2001:200::1"));
+    assert_eq!("whois.arin.net", host("2001:db8::1"));
+
+    assert!(WhoIs::from_string(
+        r#"{"_": {"ip": "whois.arin.net", "ip_ranges": {"192.0.2.0/33": "whois.ripe.net"}}}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_pool_reuse() {
+    let server = MockServer::spawn(vec![Script::Persistent(
+        b"Domain Name: EXAMPLE.COM\r\n%END\r\n".to_vec(),
+    )]);
+
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+    who.insert_server("com", server.server());
+
+    let pool = WhoIsPool::new(who, WhoIsPoolOptions {
+        response_end: Some(String::from("%END\r\n")),
+        ..WhoIsPoolOptions::default()
+    });
+
+    for _ in 0..3 {
+        let result = pool.lookup(WhoIsLookupOptions::from_string("example.com").unwrap()).unwrap();
+
+        assert_eq!("Domain Name: EXAMPLE.COM\r\n", result);
+    }
+
+    assert_eq!(1, pool.opened_connections());
+    assert_eq!(2, pool.reused_connections());
+    assert_eq!(1, server.accepted());
+    assert_eq!(3, server.queries().len());
+
+    // the idle connections are closed, so a new one is opened
+    pool.clear();
+
+    pool.lookup(WhoIsLookupOptions::from_string("example.com").unwrap()).unwrap();
+
+    assert_eq!(2, pool.opened_connections());
+}
+
+#[test]
+fn test_pool_without_response_end() {
+    let first = MockServer::respond("Domain Name: EXAMPLE.COM\r\n");
+
+    let pool =
+        WhoIsPool::new(WhoIs::from_host("whois.arin.net").unwrap(), WhoIsPoolOptions::default());
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(WhoIsServerValue::from_string(first.addr.to_string()).unwrap());
+
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", pool.lookup(options).unwrap());
+    assert_eq!(1, pool.opened_connections());
+    assert_eq!(0, pool.reused_connections());
+}
+
+#[test]
+fn test_pool_response_terminator() {
+    let server = MockServer::spawn(vec![Script::Partial(
+        b"Domain Name: EXAMPLE.COM\r\n%END\r\nleftover\r\n".to_vec(),
+    )]);
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server(
+        "com",
+        WhoIsServerValue::builder(server.addr.to_string())
+            .unwrap()
+            .response_terminator("%END")
+            .build(),
+    );
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.timeout = Some(Duration::from_secs(10));
+
+    let unpooled = who.lookup(options.clone()).unwrap();
+
+    let pool = WhoIsPool::new(who, WhoIsPoolOptions::default());
+
+    let started = Instant::now();
+
+    // the connection stays open, so only the terminator can end the response
+    for _ in 0..2 {
+        assert_eq!(unpooled, pool.lookup(options.clone()).unwrap());
+    }
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", unpooled);
+    assert_eq!(2, pool.opened_connections());
+    assert_eq!(0, pool.reused_connections());
+}
+
+#[test]
+fn test_pool_max_total_duration() {
+    // each read succeeds quickly, so only the total duration can stop the lookup
+    let server = MockServer::spawn(vec![Script::Trickle(b"% still here\r\n".to_vec())]);
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server(
+        "org",
+        WhoIsServerValue::builder(server.addr.to_string())
+            .unwrap()
+            .response_terminator("%END")
+            .build(),
+    );
+
+    let pool = WhoIsPool::new(who, WhoIsPoolOptions {
+        response_end: Some(String::from("%END\r\n")),
+        ..WhoIsPoolOptions::default()
+    });
+
+    for target in ["example.com", "example.org"] {
+        let mut options = WhoIsLookupOptions::from_string(target).unwrap().unbounded();
+        options.server = (target == "example.com").then(|| server.server());
+        options.max_total_duration = Some(Duration::from_millis(300));
+
+        let start = Instant::now();
+
+        assert!(matches!(pool.lookup(options), Err(WhoIsError::Timeout { .. })));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
+
+/// Spawn a DNS server which answers every query with NXDOMAIN.
+fn spawn_nxdomain_dns_server() -> SocketAddr {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 512];
+
+        while let Ok((n, peer)) = socket.recv_from(&mut buffer) {
+            let request = &buffer[..n];
+
+            // the ID, the flags (QR, RD, RA, NXDOMAIN), the counts, and the echoed question and additional records
+            let mut response = request[..2].to_vec();
+            response.extend_from_slice(&[0x81, 0x83]);
+            response.extend_from_slice(&request[4..6]);
+            response.extend_from_slice(&[0, 0, 0, 0]);
+            response.extend_from_slice(&request[10..]);
+
+            socket.send_to(&response, peer).unwrap();
+        }
+    });
+
+    addr
+}
+
+#[test]
+fn test_srv_not_found() {
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let dns_server = spawn_nxdomain_dns_server();
+
+    assert!(!who.can_find_server_for_tld("example.test", dns_server.to_string()).unwrap());
+    assert!(!who.contains_tld("test"));
+
+    // nothing listens on this port
+    let unreachable = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    assert!(matches!(
+        who.can_find_server_for_tld("example.test", unreachable.to_string()),
+        Err(WhoIsError::DnsError(_))
+    ));
+
+    assert!(matches!(
+        who.can_find_server_for_tld("example.test", "dns.example"),
+        Err(WhoIsError::MapError(_))
+    ));
+}
+
+#[test]
+fn test_query_flags() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\n");
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.server = Some(
+        WhoIsServerValue::builder(server.addr.to_string()).unwrap().query("n + $addr\r\n").build(),
+    );
+    options.query_flags = Some(String::from("-B"));
+
+    who.lookup(options).unwrap();
+
+    assert_eq!("-B n + 192.0.2.1\r\n", server.queries.recv().unwrap());
+}
+
+/// An in-memory transport which records the query and replies with a canned response.
+struct Pipe {
+    query:    std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+    response: std::io::Cursor<Vec<u8>>,
+    /// The number of bytes of the query which are accepted before the pipe breaks, like a server which closes its side early but still responds. **None** means no limit.
+    accepted: Option<usize>,
+}
+
+impl std::io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.response.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = match &mut self.accepted {
+            Some(0) => return Err(std::io::ErrorKind::BrokenPipe.into()),
+            Some(accepted) => {
+                let n = buf.len().min(*accepted);
+
+                *accepted -= n;
+
+                n
+            },
+            None => buf.len(),
+        };
+
+        self.query.lock().unwrap().extend_from_slice(&buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_transport_connector() {
+    let queries = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = queries.clone();
+
+    let mut who =
+        WhoIs::from_string(r#"{"com": "whois.verisign-grs.com", "_": {"ip": "whois.arin.net"}}"#)
+            .unwrap();
+
+    who.set_transport_connector(Box::new(move |server| {
+        let response = match server.host.to_string().as_str() {
+            "whois.verisign-grs.com" => {
+                "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: whois.registrar.test\r\n"
+            },
+            "whois.registrar.test" => "Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n",
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "unknown server")),
+        };
+
+        let query = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        recorded.lock().unwrap().push((server.host.to_string(), query.clone()));
+
+        Ok(Box::new(Pipe {
+            query,
+            response: std::io::Cursor::new(response.as_bytes().to_vec()),
+            accepted: None,
+        }))
+    }));
+
+    let result =
+        who.lookup_detailed(WhoIsLookupOptions::from_string("example.com").unwrap()).unwrap();
+
+    assert_eq!("Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n", result.body);
+    assert_eq!(vec!["whois.verisign-grs.com:43", "whois.registrar.test:43"], result.chain);
+
+    let queries = queries.lock().unwrap();
+
+    assert_eq!(2, queries.len());
+
+    for (_, query) in queries.iter() {
+        assert_eq!(b"example.com\r\n".as_slice(), query.lock().unwrap().as_slice());
+    }
+}
+
+#[test]
+fn test_from_value() {
+    let who = WhoIs::from_value(serde_json::json!({
+        "org": "whois.pir.org",
+        "": "whois.ripe.net",
+        "_": {
+            "ip": {
+                "host": "whois.arin.net",
+                "query": "n + $addr\r\n"
+            }
+        }
+    }))
+    .unwrap();
+
+    assert!(who.contains_tld("org"));
+    assert_eq!("whois.ripe.net", who.default_domain_server().unwrap().host.to_string());
+    assert_eq!(Some("n + $addr\r\n"), who.object_server("ip").unwrap().query.as_deref());
+
+    assert!(WhoIs::from_value(serde_json::json!(["whois.pir.org"])).is_err());
+}
+
+#[test]
+fn test_lookup_all() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let registrar = MockServer::respond("Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n");
+    let registry_body =
+        format!("Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n", registrar.addr);
+    let registry = MockServer::respond(registry_body.clone());
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(WhoIsServerValue::from_string(registry.addr.to_string()).unwrap());
+
+    let results = who.lookup_all(options).unwrap();
+
+    assert_eq!(
+        vec![
+            (registry.addr.to_string(), registry_body),
+            (
+                registrar.addr.to_string(),
+                String::from("Domain Name: EXAMPLE.COM\r\nRegistrant Name: Example\r\n")
+            ),
+        ],
+        results
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_all_async() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let registrar = MockServer::respond("Domain Name: EXAMPLE.COM\r\n");
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(
+        MockServer::respond(format!(
+            "Domain Name: EXAMPLE.COM\r\nRegistrar WHOIS Server: {}\r\n",
+            registrar.addr
+        ))
+        .server(),
+    );
+
+    let results = who.lookup_all_async(options).await.unwrap();
+
+    assert_eq!(2, results.len());
+    assert_eq!(registrar.addr.to_string(), results[1].0);
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", results[1].1);
+}
+
+#[test]
+fn test_target_whitespace_and_control_characters() {
+    assert_eq!(
+        Target::parse_str("example.com").unwrap(),
+        Target::parse_str(" example.com\t").unwrap()
+    );
+    assert_eq!(
+        Target::parse_str("192.0.2.1").unwrap(),
+        Target::parse_string("192.0.2.1 ").unwrap()
+    );
+
+    assert!(Target::parse_str("example.com\r\nhelp").is_err());
+    assert!(Target::parse_string("example.com\r\n-i origin AS64496").is_err());
+    assert!(WhoIsLookupOptions::from_string("example.com\nexample.net").is_err());
+}
+
+#[test]
+fn test_query_injection() {
+    let who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    let server = MockServer::respond("NetName: EXAMPLE\r\n");
+
+    let mut options = WhoIsLookupOptions::from_raw("NET-192-0-2-0-1\r\n-i origin AS64496");
+    options.server = Some(WhoIsServerValue::from_string(server.addr.to_string()).unwrap());
+
+    match who.lookup(options) {
+        Err(WhoIsError::InvalidTarget {
+            target,
+        }) => assert_eq!("NET-192-0-2-0-1\r\n-i origin AS64496", target),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_health_check() {
+    let reachable = TcpListener::bind("127.0.0.1:0").unwrap();
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let reachable_addr = reachable.local_addr().unwrap();
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"com": "{0}", "net": "{0}", "org": "{1}", "_": {{"ip": "{0}"}}}}"#,
+        reachable_addr, refused
+    ))
+    .unwrap();
+
+    let results = who.health_check(Duration::from_secs(5));
+
+    assert_eq!(2, results.len());
+
+    for (addr, result) in results {
+        if addr == reachable_addr.to_string() {
+            assert!(result.is_ok());
+        } else {
+            assert_eq!(refused.to_string(), addr);
+            assert!(matches!(result, Err(WhoIsError::ConnectError { .. })));
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_health_check_async() {
+    let reachable = TcpListener::bind("127.0.0.1:0").unwrap();
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let reachable_addr = reachable.local_addr().unwrap();
+
+    let who = WhoIs::from_string(format!(
+        r#"{{"com": "{0}", "org": "{1}", "_": {{"ip": "{0}"}}}}"#,
+        reachable_addr, refused
+    ))
+    .unwrap();
+
+    let results = who.health_check_async(Duration::from_secs(5), 1).await;
+
+    assert_eq!(2, results.len());
+    assert_eq!(
+        1,
+        results
+            .iter()
+            .filter(|(addr, result)| *addr == reachable_addr.to_string() && result.is_ok())
+            .count()
+    );
+    assert_eq!(
+        1,
+        results
+            .iter()
+            .filter(|(addr, result)| *addr == refused.to_string() && result.is_err())
+            .count()
+    );
+}
+
+#[test]
+fn test_target_kind() {
+    let kind = |s: &str| Target::parse_str(s).ok().map(|target| target.kind());
+
+    assert_eq!(Some(TargetKind::IPv4), kind("1.2.3.4"));
+    assert_eq!(Some(TargetKind::Domain), kind("1.2.3.4.example.com"));
+    assert_eq!(Some(TargetKind::Domain), kind("4.example"));
+    assert_eq!(Some(TargetKind::IPv6), kind("2001:db8::1"));
+    assert_eq!(Some(TargetKind::IPv6), kind("[2001:db8::1]"));
+    assert_eq!(Some(TargetKind::IPv6), kind("::ffff:1.2.3.4"));
+    assert_eq!(Some(TargetKind::Domain), kind("example.com"));
+
+    // malformed IPv4 addresses are neither IPs nor domains
+    assert_eq!(None, kind("999.1.1.1"));
+    assert_eq!(None, kind("1.2.3"));
+    assert_eq!(None, kind("1.2.3.4."));
+}
+
+#[test]
+fn test_target_validate_string() {
+    use validators::prelude::*;
+
+    // the trait of `validators` applies the same rules as the inherent methods
+    assert_eq!(
+        Target::parse_str("example.com").unwrap(),
+        <Target as ValidateString>::parse_str(" example.com ").unwrap()
+    );
+    assert!(<Target as ValidateString>::parse_string("example.com\r\nhelp").is_err());
+    assert!(Target::validate_str("999.1.1.1").is_err());
+    assert!(Target::validate_str("fe80::1%eth0").is_ok());
+
+    assert_eq!(
+        "[2001:db8::1]",
+        Target::parse_str("2001:db8::1").unwrap().to_uri_authority_string()
+    );
+}
+
+#[test]
+fn test_default_constants() {
+    let options = WhoIsLookupOptions::from_string("example.com").unwrap();
+
+    assert_eq!(DEFAULT_FOLLOW, options.follow);
+    assert_eq!(Some(Duration::from_millis(DEFAULT_TIMEOUT)), options.timeout);
+    assert_eq!(
+        format!("whois.arin.net:{}", DEFAULT_WHOIS_HOST_PORT),
+        WhoIsServerValue::from_string("whois.arin.net")
+            .unwrap()
+            .host
+            .to_addr_string(DEFAULT_WHOIS_HOST_PORT)
+    );
+}
+
+#[test]
+fn test_lookup_target_reuses_options() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().take(3) {
+            let mut reader = BufReader::new(stream.unwrap());
+
+            let mut query = String::new();
+            reader.read_line(&mut query).unwrap();
+
+            write!(reader.get_mut(), "Queried: {}", query.trim_end()).unwrap();
+        }
+    });
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("unused.com").unwrap();
+    options.server = Some(WhoIsServerValue::from_string(addr.to_string()).unwrap());
+
+    for target in ["magiclen.org", "example.com", "8.8.8.8"] {
+        let target = Target::parse_str(target).unwrap();
+
+        let body = who.lookup_target(&target, &options).unwrap();
+
+        assert_eq!(format!("Queried: {}", target), body);
+    }
+
+    assert_eq!("unused.com", options.target.to_string());
+}
+
+#[test]
+fn test_response_terminator() {
+    let addr = MockServer::spawn(vec![Script::Partial(
+        b"Domain Name: EXAMPLE.COM\r\n%END\r\nleftover\r\n".to_vec(),
+    )])
+    .addr;
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.timeout = Some(Duration::from_secs(10));
+    options.server = Some(
+        WhoIsServerValue::builder(addr.to_string()).unwrap().response_terminator("%END").build(),
+    );
+
+    let started = std::time::Instant::now();
+
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", who.lookup(options).unwrap());
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let server = WhoIsServerValue::from_value(&serde_json::json!({
+        "host": addr.to_string(),
+        "response_terminator": "%END"
+    }))
+    .unwrap();
+
+    assert_eq!(Some("%END"), server.response_terminator.as_deref());
+
+    assert!(WhoIsServerValue::from_value(&serde_json::json!({
+        "host": addr.to_string(),
+        "response_terminator": ""
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_response_terminator_pattern() {
+    let server = MockServer::spawn(vec![Script::Partial(
+        b"Domain Name: EXAMPLE.COM\r\n>>> Last update of WHOIS database: 2024-01-01 <<<\r\n"
+            .to_vec(),
+    )]);
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.timeout = Some(Duration::from_secs(10));
+    options.server = Some(
+        WhoIsServerValue::from_value(&serde_json::json!({
+            "host": server.addr.to_string(),
+            "response_terminator_pattern": "^>>> Last update"
+        }))
+        .unwrap(),
+    );
+
+    let started = Instant::now();
+
+    // the connection stays open, so only the pattern can end the response
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", who.lookup(options).unwrap());
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    assert!(WhoIsServerValue::from_value(&serde_json::json!({
+        "host": "whois.example.com",
+        "response_terminator_pattern": "(unclosed"
+    }))
+    .is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_response_terminator_async() {
+    let addr =
+        MockServer::spawn(vec![Script::Partial(b"Domain Name: EXAMPLE.COM\n%END\n".to_vec())]).addr;
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.timeout = Some(Duration::from_secs(10));
+    options.server = Some(
+        WhoIsServerValue::builder(addr.to_string()).unwrap().response_terminator("%END").build(),
+    );
+
+    let started = std::time::Instant::now();
+
+    assert_eq!("Domain Name: EXAMPLE.COM\n", who.lookup_async(options).await.unwrap());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_to_async_writer_terminator() {
+    let server = MockServer::spawn(vec![Script::Partial(
+        b"Domain Name: EXAMPLE.COM\r\n%END\r\nleftover\r\n".to_vec(),
+    )]);
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.follow = 0;
+    options.timeout = Some(Duration::from_secs(10));
+    options.server = Some(
+        WhoIsServerValue::builder(server.addr.to_string())
+            .unwrap()
+            .response_terminator("%END")
+            .build(),
+    );
+
+    let started = Instant::now();
+
+    let mut out = Vec::new();
+
+    // the connection stays open, so only the terminator can end the response
+    who.lookup_to_async_writer(options, &mut out).await.unwrap();
+
+    assert_eq!(b"Domain Name: EXAMPLE.COM\r\n", out.as_slice());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_to_async_writer_timeout() {
+    let server = MockServer::spawn(vec![Script::Partial(b"Domain Name: EXAMP".to_vec())]);
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.follow = 0;
+    options.timeout = Some(Duration::from_millis(200));
+    options.server = Some(server.server());
+
+    let mut out = Vec::new();
+
+    let error = who.lookup_to_async_writer(options, &mut out).await.unwrap_err();
+
+    assert!(
+        matches!(&error, WhoIsError::Timeout { addr } if *addr == server.addr.to_string()),
+        "{error:?}"
+    );
+    assert_eq!(b"Domain Name: EXAMP", out.as_slice());
+}
+
+#[test]
+fn test_null_tld() {
+    let default_server = MockServer::respond("Default server").server();
+
+    let who = WhoIs::from_value(serde_json::json!({
+        "": default_server.host.to_addr_string(43),
+        "xx": null,
+        "ok.xx": "whois.example.net"
+    }))
+    .unwrap();
+
+    assert!(!who.contains_tld("xx"));
+    assert!(who.contains_tld("ok.xx"));
+
+    match who.lookup(WhoIsLookupOptions::from_string("example.xx").unwrap()) {
+        Err(WhoIsError::UnsupportedTld {
+            tld,
+        }) => assert_eq!("xx", tld),
+        other => panic!("{:?}", other),
+    }
+
+    // an absent TLD still falls through to the `""` server
+    assert_eq!(
+        "Default server",
+        who.lookup(WhoIsLookupOptions::from_string("example.yy").unwrap()).unwrap()
+    );
+}
+
+#[test]
+fn test_lookup_str() {
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.set_default_domain_server(Some(MockServer::respond("Domain Name: EXAMPLE.COM").server()));
+
+    assert_eq!("Domain Name: EXAMPLE.COM", who.lookup_str("example.com").unwrap());
+
+    assert!(who.lookup_str("exa\u{7}mple.com").is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_str_async() {
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.set_default_domain_server(Some(MockServer::respond("Domain Name: EXAMPLE.COM").server()));
+
+    assert_eq!("Domain Name: EXAMPLE.COM", who.lookup_str_async("example.com").await.unwrap());
+}
+
+#[test]
+fn test_follow_delay() {
+    let registrar =
+        MockServer::respond("Domain Name: EXAMPLE.COM\nRegistrant Name: Example\n").server();
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.COM\nRegistrar WHOIS Server: {}\n",
+        registrar.host.to_addr_string(43)
+    ))
+    .server();
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(registry);
+    options.follow_delay = Some(Duration::from_millis(300));
+
+    let started = std::time::Instant::now();
+
+    let result = who.lookup_detailed(options).unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(result.followed);
+    assert!(result.body.contains("Registrant Name: Example"));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_follow_delay_async() {
+    let registrar =
+        MockServer::respond("Domain Name: EXAMPLE.COM\nRegistrant Name: Example\n").server();
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.COM\nRegistrar WHOIS Server: {}\n",
+        registrar.host.to_addr_string(43)
+    ))
+    .server();
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(registry);
+    options.follow_delay = Some(Duration::from_millis(300));
+
+    let started = std::time::Instant::now();
+
+    let result = who.lookup_detailed_async(options).await.unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(result.followed);
+}
+
+/// Spawn a DNS server which answers every query with an SRV record pointing to `target`.
+fn spawn_srv_dns_server(target: &'static str) -> SocketAddr {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 512];
+
+        while let Ok((n, peer)) = socket.recv_from(&mut buffer) {
+            let request = &buffer[..n];
+
+            // the question is the name (ending with a zero-length label), the type and the class
+            let name_end = 12 + request[12..].iter().position(|&b| b == 0).unwrap() + 1;
+            let question = &request[12..name_end + 4];
+
+            // the ID, the flags (QR, RD, RA), one question and one answer
+            let mut response = request[..2].to_vec();
+            response.extend_from_slice(&[0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+            response.extend_from_slice(question);
+
+            let mut rdata = vec![0, 0, 0, 0, 0, 43];
+
+            for label in target.split('.') {
+                rdata.push(label.len() as u8);
+                rdata.extend_from_slice(label.as_bytes());
+            }
+
+            rdata.push(0);
+
+            // a pointer to the question name, SRV, IN, the TTL and the data
+            response.extend_from_slice(&[0xC0, 0x0C, 0, 33, 0, 1, 0, 0, 0x0E, 0x10]);
+            response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            response.extend_from_slice(&rdata);
+
+            socket.send_to(&response, peer).unwrap();
+        }
+    });
+
+    addr
+}
+
+#[test]
+fn test_discover_server_for_tld() {
+    let dns_server = spawn_srv_dns_server("whois.nic.test");
+
+    let who = std::sync::Arc::new(WhoIs::from_host("whois.arin.net").unwrap());
+
+    let server =
+        who.discover_server_for_tld("example.test", dns_server.to_string()).unwrap().unwrap();
+
+    assert_eq!("whois.nic.test:43", server.host.to_addr_string(43));
+    assert!(!who.contains_tld("test"));
+
+    let mut who = std::sync::Arc::try_unwrap(who).unwrap();
+
+    assert!(who.can_find_server_for_tld("example.test", dns_server.to_string()).unwrap());
+    assert!(who.contains_tld("test"));
+
+    let nxdomain = spawn_nxdomain_dns_server();
+
+    assert!(who.discover_server_for_tld("example.other", nxdomain.to_string()).unwrap().is_none());
+}
+
+#[test]
+fn test_ipv6_zone_id() {
+    assert_eq!(Target::parse_str("fe80::1").ok(), Target::parse_str("fe80::1%eth0").ok());
+    assert_eq!(Target::parse_str("fe80::1").ok(), Target::parse_str("[fe80::1%25eth0]").ok());
+    assert_eq!(Target::parse_str("fe80::1").ok(), Target::parse_string("fe80::1%3").ok());
+
+    assert!(Target::parse_str("example.com%eth0").is_err());
+
+    let server = MockServer::respond("NetRange: FE80::/10\r\n");
+
+    let who = WhoIs::from_host(server.addr.to_string()).unwrap();
+
+    who.lookup(WhoIsLookupOptions::from_string("fe80::1%eth0").unwrap()).unwrap();
+
+    assert_eq!("fe80::1\r\n", server.queries.recv().unwrap());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serialize_records() {
+    let record = parse_ip_record("inetnum: 193.0.0.0 - 193.0.7.255\ncountry: NL\n");
+
+    assert_eq!(
+        serde_json::json!({
+            "range": "193.0.0.0 - 193.0.7.255",
+            "cidr": "193.0.0.0/21",
+            "org_name": null,
+            "country": "NL",
+            "abuse_email": null,
+            "body": "inetnum: 193.0.0.0 - 193.0.7.255\ncountry: NL\n"
+        }),
+        serde_json::to_value(&record).unwrap()
+    );
+
+    let record = parse_domain_record("domain: afnic.fr\nstatus: ACTIVE\n");
+
+    assert_eq!(
+        serde_json::json!({
+            "domain": "afnic.fr",
+            "registrar": null,
+            "creation_date": null,
+            "expiration_date": null,
+            "updated_date": null,
+            "name_servers": [],
+            "statuses": ["ACTIVE"],
+            "body": "domain: afnic.fr\nstatus: ACTIVE\n"
+        }),
+        serde_json::to_value(&record).unwrap()
+    );
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(MockServer::respond("Domain Name: EXAMPLE.COM\n").server());
+
+    let server = options.server.as_ref().unwrap().host.to_addr_string(43);
+
+    let result = who.lookup_detailed(options).unwrap();
+
+    assert_eq!(
+        serde_json::json!({
+            "body": "Domain Name: EXAMPLE.COM\n",
+            "server": server,
+            "socket_addr": server,
+            "followed": false,
+            "hops": 0,
+            "chain": [server]
+        }),
+        serde_json::to_value(&result).unwrap()
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_lookup_blocking() {
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(MockServer::respond("Domain Name: EXAMPLE.COM\n").server());
+
+    assert_eq!("Domain Name: EXAMPLE.COM\n", who.lookup_blocking(options).unwrap());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_blocking_within_runtime() {
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.server = Some(MockServer::respond("Domain Name: EXAMPLE.COM\n").server());
+
+    assert_eq!("Domain Name: EXAMPLE.COM\n", who.lookup_blocking(options).unwrap());
+}
+
+#[test]
+fn test_set_dns_server() {
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+
+    who.set_dns_server(Some(spawn_srv_dns_server("whois.nic.test")));
+
+    for tld in ["example.test", "example.other"] {
+        let server = who.discover_server(tld).unwrap().unwrap();
+
+        assert_eq!("whois.nic.test:43", server.host.to_addr_string(43));
+    }
+
+    assert!(who.can_find_server("example.test").unwrap());
+    assert!(who.can_find_server("example.other").unwrap());
+    assert!(who.contains_tld("test"));
+    assert!(who.contains_tld("other"));
+
+    // the explicit DNS server takes precedence
+    let nxdomain = spawn_nxdomain_dns_server();
+
+    assert!(who.discover_server_for_tld("example.third", nxdomain.to_string()).unwrap().is_none());
+}
+
+#[test]
+fn test_classified_pattern() {
+    let classifier = ResponseClassifier::default();
+
+    assert_eq!(
+        (ResponseClass::NotFound, Some("No match for")),
+        classifier.classify_with_pattern("no match for \"EXAMPLE-NX.COM\".\r\n")
+    );
+    assert_eq!(
+        (ResponseClass::RateLimited, Some("WHOIS LIMIT EXCEEDED")),
+        classifier.classify_with_pattern("WHOIS LIMIT EXCEEDED")
+    );
+    assert_eq!((ResponseClass::Unknown, None), classifier.classify_with_pattern(""));
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("example-nx.com").unwrap();
+    options.server = Some(MockServer::respond("%ERROR:101: no entries found\r\n").server());
+
+    let (_, class, pattern) = who.lookup_classified(options).unwrap();
+
+    assert_eq!(ResponseClass::NotFound, class);
+    assert_eq!(Some("No entries found"), pattern.as_deref());
+
+    // the body is classified once, before the comments are stripped
+    let mut options = WhoIsLookupOptions::from_string("example-nx.com").unwrap();
+    options.server = Some(
+        MockServer::respond(
+            "% No entries found\r\n\r\nFor more information, see https://example.com/\r\n",
+        )
+        .server(),
+    );
+    options.detect_rate_limit = true;
+    options.strip_percent_comments = true;
+
+    let (body, class, pattern) = who.lookup_classified(options).unwrap();
+
+    assert!(!body.contains("No entries found"));
+    assert_eq!(ResponseClass::NotFound, class);
+    assert_eq!(Some("No entries found"), pattern.as_deref());
+
+    let classifier =
+        ResponseClassifier::new(vec![String::from("Nothing Here")], vec![String::from(
+            "SLOW Down",
+        )]);
+
+    assert_eq!(["Nothing Here"], classifier.not_found_patterns());
+    assert_eq!(["SLOW Down"], classifier.rate_limited_patterns());
+    assert_eq!(
+        (ResponseClass::RateLimited, Some("SLOW Down")),
+        classifier.classify_with_pattern("slow down, nothing here")
+    );
+    assert_eq!(
+        (ResponseClass::NotFound, Some("Nothing Here")),
+        classifier.classify_with_pattern("NOTHING HERE")
+    );
+}
+
+#[test]
+fn test_query_override() {
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\r\n");
+
+    let who = WhoIs::from_host_with(server.addr.to_string(), "n + $addr\r\n").unwrap();
+
+    let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+    options.query_override = Some(String::from("z + $addr\r\n"));
+
+    who.lookup(options).unwrap();
+
+    assert_eq!("z + 192.0.2.1\r\n", server.queries.recv().unwrap());
+}
+
+#[test]
+fn test_options_try_from() -> Result<(), WhoIsError> {
+    let options: WhoIsLookupOptions = "example.org".try_into()?;
+
+    assert_eq!("example.org", options.target.to_string());
+
+    let options = WhoIsLookupOptions::try_from(String::from(" 192.0.2.1 "))?;
+
+    assert_eq!(TargetKind::IPv4, options.target.kind());
+
+    assert!(WhoIsLookupOptions::try_from("exa\nmple.org").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let who = WhoIs::from_path("tests/data/duplicate_keys.json").unwrap();
+
+    assert_eq!(["io"], who.duplicate_keys());
+
+    match WhoIs::from_path_strict("tests/data/duplicate_keys.json") {
+        Err(WhoIsError::DuplicateKey {
+            key,
+        }) => assert_eq!("io", key),
+        other => panic!("{:?}", other),
+    }
+
+    assert!(WhoIs::from_string(include_str!("data/duplicate_keys.json")).is_ok());
+    assert!(WhoIs::from_string_strict(include_str!("data/duplicate_keys.json")).is_err());
+
+    assert!(WhoIs::from_path("tests/data/servers.json").unwrap().duplicate_keys().is_empty());
+
+    // keys of nested objects, strings with quotes and escaped keys are told apart
+    let who = WhoIs::from_string(
+        r#"{"_": {"ip": "whois.arin.net"}, "\u0069o": "whois.nic.io", "io": "whois.example.net", "org": "whois.pir.org", "com": {"host": "whois.verisign-grs.com", "query": "\"org\", $addr\r\n"}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(["io"], who.duplicate_keys());
+}
+
+#[test]
+fn test_from_slice() {
+    let who = WhoIs::from_slice(include_bytes!("data/servers.json")).unwrap();
+
+    assert_eq!(WhoIs::from_path("tests/data/servers.json").unwrap().len(), who.len());
+
+    assert!(WhoIs::from_slice(include_bytes!("data/bom_servers.json")).is_ok());
+    assert!(WhoIs::from_slice(b"[]").is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_many_async_order() {
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let servers = [
+        MockServer::spawn(vec![Script::Delay(Duration::from_millis(400), b"slow".to_vec())])
+            .server(),
+        MockServer::spawn(vec![Script::Delay(Duration::ZERO, b"fast".to_vec())]).server(),
+        WhoIsServerValue::from_string(refused.to_string()).unwrap(),
+        MockServer::spawn(vec![Script::Delay(Duration::ZERO, b"faster".to_vec())]).server(),
+    ];
+
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let options = servers
+        .into_iter()
+        .map(|server| {
+            let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+            options.server = Some(server);
+            options.timeout = Some(Duration::from_secs(5));
+
+            options
+        })
+        .collect();
+
+    let results = who.lookup_many_async(options, 2).await;
+
+    assert_eq!(4, results.len());
+    assert_eq!("slow", results[0].as_ref().unwrap());
+    assert_eq!("fast", results[1].as_ref().unwrap());
+    assert!(results[2].is_err());
+    assert_eq!("faster", results[3].as_ref().unwrap());
+}
+
+#[test]
+fn test_health_check_order() {
+    let listeners: Vec<_> = (0..20).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+
+    for (i, listener) in listeners.iter().enumerate() {
+        who.insert_server(
+            format!("tld{}", i),
+            WhoIsServerValue::from_string(listener.local_addr().unwrap().to_string()).unwrap(),
+        );
+    }
+
+    let results = who.health_check(Duration::from_secs(5));
+
+    let addrs: Vec<_> = results.iter().map(|(addr, _)| addr.clone()).collect();
+
+    let mut sorted = addrs.clone();
+    sorted.sort();
+
+    assert_eq!(20, addrs.len());
+    assert_eq!(sorted, addrs);
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+}
+
+#[test]
+fn test_preserve_case() {
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let mock = MockServer::respond("Domain Name: EXAMPLE.COM\r\n");
+
+    let mut options = WhoIsLookupOptions::from_string(" ExAmple.COM ").unwrap();
+    options.server = Some(mock.server());
+
+    who.lookup(options).unwrap();
+
+    assert_eq!("example.com\r\n", mock.queries.recv().unwrap());
+
+    let mock = MockServer::respond("Domain Name: EXAMPLE.COM\r\n");
+
+    let mut options = WhoIsLookupOptions::from_str("ExAmple.COM").unwrap();
+    options.server = Some(mock.server());
+    options.preserve_case = true;
+
+    who.lookup(options).unwrap();
+
+    assert_eq!("ExAmple.COM\r\n", mock.queries.recv().unwrap());
+
+    // a replaced target keeps its own case
+    let mock = MockServer::respond("Domain Name: EXAMPLE.ORG\r\n");
+
+    let mut options = WhoIsLookupOptions::from_str("ExAmple.COM").unwrap();
+    options.server = Some(mock.server());
+    options.preserve_case = true;
+    options.target = Target::parse_str("Example.ORG").unwrap();
+
+    who.lookup(options).unwrap();
+
+    assert_eq!("Example.ORG\r\n", mock.queries.recv().unwrap());
+
+    // the case does not make targets different
+    assert_eq!(
+        Target::parse_str("ExAmple.COM").unwrap(),
+        Target::parse_str("example.com").unwrap()
+    );
+
+    // the options can still be built with a struct literal
+    let defaults = WhoIsLookupOptions::from_str("example.com").unwrap();
+
+    let options = WhoIsLookupOptions {
+        preserve_case: true,
+        ..defaults
+    };
+
+    assert!(options.preserve_case);
+}
+
+#[test]
+fn test_invalid_server_key() {
+    let error = WhoIs::from_string(r#"{"io": {"host": "bad host"}}"#).unwrap_err();
+
+    assert!(error.to_string().contains("\"io\""), "{}", error);
+    assert!(error.to_string().contains("host string"), "{}", error);
+
+    let error =
+        WhoIs::from_string(r#"{"_": {"ip": "whois.arin.net", "asn": {"host": 1}}}"#).unwrap_err();
+
+    assert!(error.to_string().contains("\"_.asn\""), "{}", error);
+
+    let error = WhoIs::from_string(
+        r#"{"_": {"ip": "whois.arin.net", "ip_ranges": {"193.0.0.0/99": "whois.ripe.net"}}}"#,
+    )
+    .unwrap_err();
+
+    assert!(error.to_string().contains("\"_.ip_ranges.193.0.0.0/99\""), "{}", error);
+
+    let error =
+        WhoIs::from_string(r#"{"": {"host": "whois.ripe.net", "timeout_ms": "1"}}"#).unwrap_err();
+
+    assert!(matches!(&error, WhoIsError::InvalidServer { key, .. } if key.is_empty()));
+    assert!(std::error::Error::source(&error).is_some());
+}
+
+#[test]
+fn test_from_env() {
+    let _env = lock_env();
+
+    std::env::set_var("WHOIS_RUST_TEST_SERVERS", "tests/data/servers.json");
+
+    let who = WhoIs::from_env("WHOIS_RUST_TEST_SERVERS").unwrap();
+
+    assert_eq!(WhoIs::from_path("tests/data/servers.json").unwrap().len(), who.len());
+
+    let error = WhoIs::from_env("WHOIS_RUST_TEST_UNSET").unwrap_err();
+
+    assert!(error.to_string().contains("WHOIS_RUST_TEST_UNSET"), "{}", error);
+
+    std::env::set_var("WHOIS_RUST_TEST_MISSING", "tests/data/missing.json");
+
+    match WhoIs::from_env("WHOIS_RUST_TEST_MISSING") {
+        Err(WhoIsError::IOError(error)) => {
+            assert_eq!(std::io::ErrorKind::NotFound, error.kind());
+            assert!(error.to_string().contains("missing.json"), "{}", error);
+        },
+        other => panic!("{:?}", other),
+    }
+
+    assert_eq!("WHOIS_SERVERS_JSON", DEFAULT_SERVERS_ENV_VAR);
+}
+
+/// A deterministic version of `test`: a thin registry refers to the registrar, and an IP is referred from ARIN to RIPE.
+#[test]
+fn test_mock_lookups() {
+    let registrar = MockServer::respond("Domain Name: MAGICLEN.ORG\r\nRegistrant Country: TW\r\n");
+    let registry = MockServer::respond(format!(
+        "Domain Name: MAGICLEN.ORG\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+    let ripe = MockServer::respond("inetnum: 172.105.0.0 - 172.105.255.255\r\ncountry: NL\r\n");
+    let arin = MockServer::respond(format!(
+        "NetRange: 172.104.0.0 - 172.105.255.255\r\nReferralServer: whois://{}\r\n",
+        ripe.addr
+    ));
+
+    let who = WhoIs::from_value(serde_json::json!({
+        "org": registry.addr.to_string(),
+        "_": {
+            "ip": {
+                "host": arin.addr.to_string(),
+                "query": "n + $addr\r\n"
+            }
+        }
+    }))
+    .unwrap();
+
+    let result = who.lookup(WhoIsLookupOptions::from_string("magiclen.org").unwrap()).unwrap();
+
+    assert!(result.contains("Registrant Country: TW"));
+    assert_eq!(["magiclen.org\r\n"], registry.queries().as_slice());
+    assert_eq!(["magiclen.org\r\n"], registrar.queries().as_slice());
+
+    let result = who.lookup(WhoIsLookupOptions::from_string("172.105.210.153").unwrap()).unwrap();
+
+    assert!(result.contains("country: NL"));
+    assert_eq!(["n + 172.105.210.153\r\n"], arin.queries().as_slice());
+    assert_eq!(["172.105.210.153\r\n"], ripe.queries().as_slice());
+}
+
+/// A deterministic version of `test_srv`: the WHOIS server of a TLD is discovered by its SRV record.
+#[test]
+fn test_mock_srv() {
+    let nic = MockServer::respond("Domain Name: lotteryusa.us\r\n");
+
+    let mut who = WhoIs::from_host("whois.arin.net").unwrap();
+    who.set_dns_server(Some(spawn_srv_dns_server("whois.nic.us")));
+    who.set_host_resolver(Box::new(move |host, _| {
+        Ok(if host == "whois.nic.us" { vec![nic.addr] } else { Vec::new() })
+    }));
+
+    assert!(who.can_find_server(".lotteryusa.us").unwrap());
+
+    let result = who.lookup(WhoIsLookupOptions::from_string("lotteryusa.us").unwrap()).unwrap();
+
+    assert_eq!("Domain Name: lotteryusa.us\r\n", result);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_mock_lookups_async() {
+    let registrar = MockServer::respond("Domain Name: MAGICLEN.ORG\r\nRegistrant Country: TW\r\n");
+    let registry = MockServer::respond(format!(
+        "Domain Name: MAGICLEN.ORG\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server("org", registry.server());
+
+    let result =
+        who.lookup_async(WhoIsLookupOptions::from_string("magiclen.org").unwrap()).await.unwrap();
+
+    assert!(result.contains("Registrant Country: TW"));
+    assert_eq!(1, registrar.queries().len());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_lines_async() {
+    let partial = MockServer::spawn(vec![Script::Partial(
+        b"Domain Name: EXAMPLE.COM\r\nRegistrar: Example\nRegist".to_vec(),
+    )]);
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server("com", partial.server());
+
+    let registrar = MockServer::respond("Domain Name: MAGICLEN.ORG\r\nRegistrant Country: TW");
+    let registry = MockServer::respond(format!(
+        "Domain Name: MAGICLEN.ORG\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+    who.insert_server("org", registry.server());
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.follow = 0;
+    options.timeout = Some(Duration::from_secs(2));
+
+    let start = Instant::now();
+
+    let mut lines = who.lookup_lines_async(options);
+
+    // the complete lines are delivered while the server keeps the connection open
+    assert_eq!("Domain Name: EXAMPLE.COM", lines.next_line().await.unwrap().unwrap());
+    assert_eq!("Registrar: Example", lines.next_line().await.unwrap().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    assert_eq!("Regist", lines.next_line().await.unwrap().unwrap());
+    assert!(matches!(lines.next_line().await, Some(Err(WhoIsError::Timeout { .. }))));
+    assert!(lines.next_line().await.is_none());
+
+    let mut lines =
+        who.lookup_lines_async(WhoIsLookupOptions::from_string("magiclen.org").unwrap());
+
+    let mut received = Vec::new();
+
+    while let Some(line) = lines.next_line().await {
+        received.push(line.unwrap());
+    }
+
+    assert_eq!(["Domain Name: MAGICLEN.ORG", "Registrant Country: TW"], received.as_slice());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_lines_async_decoding() {
+    let windows1251 =
+        MockServer::spawn(vec![Script::Respond(include_bytes!("data/windows1251.txt").to_vec())]);
+    let bom =
+        MockServer::spawn(vec![Script::Respond(include_bytes!("data/bom_response.txt").to_vec())]);
+    let zlib = MockServer::spawn(vec![Script::Respond(include_bytes!("data/zlib.bin").to_vec())]);
+    let terminated = MockServer::spawn(vec![Script::Partial(
+        b"Domain Name: EXAMPLE.COM\r\n%END\r\nleftover\r\n".to_vec(),
+    )]);
+
+    let who = WhoIs::without_ip_server();
+
+    let servers = [
+        WhoIsServerValue::from_value(&serde_json::json!({
+            "host": windows1251.addr.to_string(),
+            "response_charset": "windows-1251"
+        }))
+        .unwrap(),
+        bom.server(),
+        zlib.server(),
+        WhoIsServerValue::builder(terminated.addr.to_string())
+            .unwrap()
+            .response_terminator("%END")
+            .build(),
+    ];
+
+    for server in servers {
+        let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+        options.follow = 0;
+        options.timeout = Some(Duration::from_secs(10));
+        options.server = Some(server);
+
+        let body = who.lookup_async(options.clone()).await;
+
+        let mut lines = who.lookup_lines_async(options);
+
+        let mut received = Vec::new();
+
+        while let Some(line) = lines.next_line().await {
+            received.push(line);
+        }
+
+        match body {
+            Ok(body) => {
+                let received: Vec<String> = received.into_iter().map(Result::unwrap).collect();
+
+                assert_eq!(body.lines().collect::<Vec<_>>(), received);
+            },
+            // only a compressed body without the `compression` feature fails
+            Err(error) => {
+                assert!(error.to_string().contains("compressed"), "{}", error);
+                assert!(matches!(received.as_slice(), [Err(_)]), "{:?}", received);
+            },
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_lookup_lines_async_post_processing() {
+    let who = WhoIs::from_string("{}").unwrap();
+
+    let partial = MockServer::spawn(vec![Script::Partial(
+        b"% Comment\r\nDomain Name: EXAMPLE.COM\r\n%status: active\r\n".to_vec(),
+    )]);
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap().no_follow();
+    options.timeout = Some(Duration::from_secs(10));
+    options.server = Some(partial.server());
+    options.detect_rate_limit = true;
+    options.strip_percent_comments = true;
+    options.normalize_line_endings = true;
+
+    let start = Instant::now();
+
+    let mut lines = who.lookup_lines_async(options.clone());
+
+    // the options which work line by line do not stop the lines from streaming
+    assert_eq!("Domain Name: EXAMPLE.COM", lines.next_line().await.unwrap().unwrap());
+    assert_eq!("%status: active", lines.next_line().await.unwrap().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    drop(lines);
+
+    let throttle = MockServer::respond(
+        "% This is the RIPE Database query service.\n%ERROR:201: access denied for 192.0.2.1\n% \
+         Queries from your IP address have passed the daily limit.\n",
+    );
+
+    options.server = Some(throttle.server());
+
+    let mut lines = who.lookup_lines_async(options.clone());
+
+    match lines.next_line().await {
+        Some(Err(WhoIsError::RateLimited {
+            server,
+            body,
+        })) => {
+            assert_eq!(throttle.addr.to_string(), server);
+            assert!(body.starts_with("%ERROR:201: access denied"), "{}", body);
+            assert!(body.contains("daily limit"), "{}", body);
+        },
+        other => panic!("{:?}", other),
+    }
+
+    assert!(lines.next_line().await.is_none());
+
+    // `trim_boilerplate` needs the whole body
+    let response =
+        "% Comment\r\nDomain Name: EXAMPLE.COM\r\n\r\n>>> Last update of WHOIS database\r\n";
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap().no_follow();
+    options.trim_boilerplate = true;
+    options.detect_rate_limit = true;
+    options.server = Some(MockServer::respond(response).server());
+
+    let body = who.lookup_async(options.clone()).await.unwrap();
+
+    options.server = Some(MockServer::respond(response).server());
+
+    let mut lines = who.lookup_lines_async(options);
+
+    let mut received = Vec::new();
+
+    while let Some(line) = lines.next_line().await {
+        received.push(line.unwrap());
+    }
+
+    assert_eq!(["Domain Name: EXAMPLE.COM"], received.as_slice());
+    assert_eq!(body.lines().collect::<Vec<_>>(), received);
+}
+
+#[test]
+fn test_mock_referral_loop() {
+    let first_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let first_addr = first_listener.local_addr().unwrap();
+
+    let second = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.COM\r\nWhois Server: {}\r\n",
+        first_addr
+    ));
+    let first = MockServer::spawn_on(first_listener, vec![Script::respond(format!(
+        "Domain Name: EXAMPLE.COM\r\nWhois Server: {}\r\n",
+        second.addr
+    ))]);
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server("com", first.server());
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.follow = 5;
+
+    let hops = who.lookup_all(options).unwrap();
+
+    // the loop is bounded by `follow`
+    assert_eq!(6, hops.len());
+    assert_eq!(3, first.queries().len());
+    assert_eq!(3, second.queries().len());
+}
+
+#[test]
+fn test_mock_non_utf8_response() {
+    let server = MockServer::spawn(vec![Script::Respond(b"Registrant: Caf\xe9\r\n".to_vec())]);
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server("fr", server.server());
+
+    let error = who.lookup(WhoIsLookupOptions::from_string("example.fr").unwrap()).unwrap_err();
+
+    assert!(
+        matches!(&error, WhoIsError::ConnectError { source, .. } if source.kind() == std::io::ErrorKind::InvalidData),
+        "{error:?}"
+    );
+
+    who.insert_server(
+        "fr",
+        WhoIsServerValue::builder(server.addr.to_string())
+            .unwrap()
+            .charset(Charset::Iso8859_1)
+            .build(),
+    );
+
+    let result = who.lookup(WhoIsLookupOptions::from_string("example.fr").unwrap()).unwrap();
+
+    assert_eq!("Registrant: Caf\u{e9}\r\n", result);
+}
+
+#[test]
+fn test_mock_stalls() {
+    let stalled = MockServer::spawn(vec![Script::Stall]);
+    let partial = MockServer::spawn(vec![Script::Partial(b"Domain Name: EXAMP".to_vec())]);
+
+    let mut who = WhoIs::from_string("{}").unwrap();
+    who.insert_server("com", stalled.server());
+    who.insert_server("net", partial.server());
+
+    for target in ["example.com", "example.net"] {
+        let mut options = WhoIsLookupOptions::from_string(target).unwrap();
+        options.timeout = Some(Duration::from_millis(200));
+
+        let start = Instant::now();
+
+        let error = who.lookup(options).unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(matches!(error, WhoIsError::Timeout { .. }), "{target}: {error:?}");
+    }
+
+    assert_eq!(["example.com\r\n"], stalled.queries().as_slice());
+    assert_eq!(["example.net\r\n"], partial.queries().as_slice());
+}
+
+#[test]
+fn test_ip_lookup_with_explicit_server() {
+    let server = MockServer::respond("NetRange: 192.0.2.0 - 192.0.2.255\r\n");
+
+    for who in [
+        WhoIs::without_ip_server(),
+        WhoIs::from_value(serde_json::json!({ "org": "whois.pir.org", "_": { "ip": null } }))
+            .unwrap(),
+    ] {
+        let error = who.lookup(WhoIsLookupOptions::from_string("192.0.2.1").unwrap()).unwrap_err();
+
+        assert!(matches!(
+            error,
+            WhoIsError::MapError(
+                "No whois server configured for IP lookups; set one via options.server"
+            )
+        ));
+
+        let mut options = WhoIsLookupOptions::from_string("192.0.2.1").unwrap();
+        options.server = Some(server.server());
+
+        assert_eq!("NetRange: 192.0.2.0 - 192.0.2.255\r\n", who.lookup(options).unwrap());
+    }
+
+    assert_eq!(["192.0.2.1\r\n"; 2], server.queries().as_slice());
+}
+
+#[test]
+fn test_server_hosts() {
+    let who = WhoIs::from_path("tests/data/servers.json").unwrap();
+
+    assert_eq!(
+        HashSet::from([
+            String::from("whois.pir.org:43"),
+            String::from("whois.ripe.net:43"),
+            String::from("whois.arin.net:43"),
+        ]),
+        who.server_hosts()
+    );
+
+    let who = WhoIs::from_string(
+        r#"{"com": "whois.verisign-grs.com", "net": "whois.verisign-grs.com:43", "test": "127.0.0.1:4343", "_": {"ip": "whois.arin.net", "asn": "whois.radb.net"}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(4, who.server_hosts().len());
+    assert!(who.server_hosts().contains("127.0.0.1:4343"));
+
+    assert!(WhoIs::without_ip_server().server_hosts().is_empty());
+}
+
+#[test]
+fn test_lookup_with() {
+    let registrar = MockServer::respond("Domain Name: EXAMPLE.TEST\r\nRegistrant Country: TW\r\n");
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.TEST\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+
+    // the list doesn't know `.test` at all
+    let who = WhoIs::without_ip_server();
+
+    let target = Target::parse_str("example.test").unwrap();
+    let server = registry.server();
+
+    let mut options = WhoIsLookupOptions::from_string("ignored.example").unwrap();
+    options.server = Some(WhoIsServerValue::from_string("127.0.0.1:1").unwrap());
+
+    let result = who.lookup_with(&target, &server, &options).unwrap();
+
+    assert!(result.contains("Registrant Country: TW"));
+    assert_eq!(["example.test\r\n"], registry.queries().as_slice());
+    assert_eq!(["example.test\r\n"], registrar.queries().as_slice());
+
+    options.follow = 0;
+
+    assert!(who
+        .lookup_with(&target, &server, &options)
+        .unwrap()
+        .contains("Registrar WHOIS Server"));
+    assert!(registrar.queries().is_empty());
+}
+
+#[test]
+fn test_reverse_dns_target() {
+    let target = Target::parse_str("1.0.0.127.in-addr.arpa").unwrap();
+
+    assert_eq!(TargetKind::IPv4, target.kind());
+    assert_eq!("127.0.0.1", target.to_string());
+
+    let target = Target::parse_string(
+        "B.A.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.ip6.arpa.",
+    )
+    .unwrap();
+
+    assert_eq!(TargetKind::IPv6, target.kind());
+    assert_eq!("4321:0:1:2:3:4:567:89ab", target.to_string());
+
+    // a network or a malformed name stays a domain
+    for domain in [
+        "0.0.127.in-addr.arpa",
+        "1.0.0.0.127.in-addr.arpa",
+        "01.0.0.127.in-addr.arpa",
+        "1.ip6.arpa",
+    ] {
+        assert_eq!(TargetKind::Domain, Target::parse_str(domain).unwrap().kind(), "{domain}");
+    }
+
+    // the IP is sent to the IP server
+    let server = MockServer::respond("NetRange: 127.0.0.0 - 127.255.255.255\r\n");
+
+    let who = WhoIs::from_ip_server(server.server());
+
+    who.lookup(WhoIsLookupOptions::from_string("1.0.0.127.in-addr.arpa").unwrap()).unwrap();
+    who.lookup(
+        WhoIsLookupOptions::from_string(
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(["127.0.0.1\r\n", "2001:db8::1\r\n"], server.queries().as_slice());
+}
+
+#[test]
+fn test_follow_time_budget() {
+    let registrar = MockServer::spawn(vec![Script::Stall]);
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.TEST\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+
+    let mut who = WhoIs::without_ip_server();
+    who.insert_server("test", registry.server());
+
+    let mut options = WhoIsLookupOptions::from_string("example.test").unwrap();
+    options.fail_on_referral_error = true;
+    options.follow_time_budget = Some(Duration::from_millis(300));
+
+    let start = Instant::now();
+
+    let body = who.lookup(options).unwrap();
+
+    // the stalled registrar is given up on when the budget is exhausted, not after `timeout`
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(body.contains("Registrar WHOIS Server"));
+    assert_eq!(1, registrar.queries().len());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_writer_deadlines() {
+    let registrar = MockServer::spawn(vec![Script::Stall]);
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.TEST\r\nRegistrar WHOIS Server: {}\r\n",
+        registrar.addr
+    ));
+
+    let mut who = WhoIs::without_ip_server();
+    who.insert_server("test", registry.server());
+
+    let start = Instant::now();
+
+    let mut options = WhoIsLookupOptions::from_string("example.test").unwrap();
+    options.total_timeout = Some(Duration::from_millis(300));
+
+    match who.lookup_to_writer(options, Vec::new()) {
+        Err(WhoIsError::Timeout {
+            addr,
+        }) => assert_eq!(registrar.addr.to_string(), addr),
+        other => panic!("{:?}", other),
+    }
+
+    let mut options = WhoIsLookupOptions::from_string("example.test").unwrap();
+    options.fail_on_referral_error = true;
+    options.follow_time_budget = Some(Duration::from_millis(300));
+
+    let mut out = Vec::new();
+
+    who.lookup_to_async_writer(options, &mut out).await.unwrap();
+
+    // the stalled registrar is given up on when the deadlines pass, not after `timeout`
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(String::from_utf8(out).unwrap().contains("Registrar WHOIS Server"));
+    assert_eq!(2, registrar.queries().len());
+}
+
+#[test]
+fn test_server_value_eq_hash() {
+    let servers = HashSet::from([
+        WhoIsServerValue::from_string("whois.verisign-grs.com").unwrap(),
+        WhoIsServerValue::from_string("whois.verisign-grs.com").unwrap(),
+        WhoIsServerValue::from_value(&serde_json::json!({ "host": "whois.verisign-grs.com" }))
+            .unwrap(),
+        WhoIsServerValue::from_string("whois.verisign-grs.com:4343").unwrap(),
+        WhoIsServerValue::builder("whois.verisign-grs.com").unwrap().query("=$addr\r\n").build(),
+        WhoIsServerValue::builder("whois.verisign-grs.com").unwrap().punycode(false).build(),
+    ]);
+
+    assert_eq!(4, servers.len());
+
+    assert_eq!(
+        WhoIsServerValue::from_string("whois.arin.net").unwrap(),
+        WhoIsServerValue::from_string("whois.arin.net").unwrap()
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_watch_path() {
+    let path = std::env::temp_dir().join(format!("whois-rust-watch-{}.json", std::process::id()));
+
+    std::fs::write(&path, r#"{"org": "whois.pir.org"}"#).unwrap();
+
+    let (swap, handle) = WhoIs::watch_path(&path, Duration::from_millis(20)).await.unwrap();
+
+    assert_eq!(1, swap.load().len());
+
+    let wait_for_len = |len: usize| {
+        let swap = swap.clone();
+
+        async move {
+            for _ in 0..250 {
+                if swap.load().len() == len {
+                    return true;
+                }
+
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+
+            false
+        }
+    };
+
+    // a broken list is ignored
+    std::fs::write(&path, r#"{"org": "whois.pir.org", "com": "#).unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(1, swap.load().len());
+
+    std::fs::write(&path, r#"{"org": "whois.pir.org", "com": "whois.verisign-grs.com"}"#).unwrap();
+
+    assert!(wait_for_len(2).await);
+
+    handle.abort();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_tolerate_write_errors() {
+    let mut who = WhoIs::from_string(r#"{"com": "whois.verisign-grs.com"}"#).unwrap();
+
+    who.set_transport_connector(Box::new(|_| {
+        Ok(Box::new(Pipe {
+            query:    Default::default(),
+            response: std::io::Cursor::new(b"Domain Name: EXAMPLE.COM\r\n".to_vec()),
+            accepted: Some(4),
+        }))
+    }));
+
+    match who.lookup(WhoIsLookupOptions::from_string("example.com").unwrap()) {
+        Err(WhoIsError::ConnectError {
+            source, ..
+        }) => assert_eq!(std::io::ErrorKind::BrokenPipe, source.kind()),
+        other => panic!("{:?}", other),
+    }
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.tolerate_write_errors = true;
+
+    assert_eq!("Domain Name: EXAMPLE.COM\r\n", who.lookup(options).unwrap());
+
+    // nothing has been written, so the error is not tolerated
+    who.set_transport_connector(Box::new(|_| {
+        Ok(Box::new(Pipe {
+            query:    Default::default(),
+            response: std::io::Cursor::new(b"Domain Name: EXAMPLE.COM\r\n".to_vec()),
+            accepted: Some(0),
+        }))
+    }));
+
+    let mut options = WhoIsLookupOptions::from_string("example.com").unwrap();
+    options.tolerate_write_errors = true;
+
+    assert!(who.lookup(options).is_err());
+}
+
+#[test]
+fn test_lookup_result_hops() {
+    let registrar = MockServer::respond("Domain Name: EXAMPLE.TEST\r\nRegistrant Country: TW\r\n");
+    let reseller = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.TEST\r\nWhois Server: {}\r\n",
+        registrar.addr
+    ));
+    let registry = MockServer::respond(format!(
+        "Domain Name: EXAMPLE.TEST\r\nRegistrar WHOIS Server: {}\r\n",
+        reseller.addr
+    ));
+
+    let mut who = WhoIs::without_ip_server();
+    who.insert_server("test", registry.server());
+
+    let result =
+        who.lookup_detailed(WhoIsLookupOptions::from_string("example.test").unwrap()).unwrap();
+
+    assert_eq!(2, result.hops);
+    assert_eq!(3, result.chain.len());
+    assert_eq!(registrar.addr.to_string(), result.server);
+
+    let mut options = WhoIsLookupOptions::from_string("example.test").unwrap();
+    options.follow = 1;
+
+    assert_eq!(1, who.lookup_detailed(options).unwrap().hops);
+}
+
+#[test]
+fn test_non_object_server_values() {
+    for (value, kind) in [
+        (serde_json::json!(42), "a number"),
+        (serde_json::json!(["whois.verisign-grs.com"]), "an array"),
+    ] {
+        for (list, expected_key) in [
+            (serde_json::json!({ "com": value }), "com"),
+            (serde_json::json!({ "_": { "asn": value } }), "_.asn"),
+            (
+                serde_json::json!({ "_": { "ip_ranges": { "193.0.0.0/8": value } } }),
+                "_.ip_ranges.193.0.0.0/8",
+            ),
+        ] {
+            match WhoIs::from_value(list) {
+                Err(WhoIsError::InvalidServer {
+                    key,
+                    source,
+                }) => {
+                    assert_eq!(expected_key, key);
+                    assert!(
+                        matches!(*source, WhoIsError::MapError(message) if message.contains(kind))
+                    );
+                },
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    let error = WhoIs::from_string(r#"{"com": 42}"#).unwrap_err();
+
+    assert!(error.to_string().contains("\"com\""), "{}", error);
+}
+
+#[test]
+fn test_parse_domain_record() {
+    let verisign = parse_domain_record(include_str!("data/verisign.txt"));
+
+    assert_eq!(Some("EXAMPLE.COM"), verisign.domain.as_deref());
+    assert_eq!(Some("RESERVED-Internet Assigned Numbers Authority"), verisign.registrar.as_deref());
+    assert_eq!(Some("2024-08-14T07:01:34Z"), verisign.updated_date.as_deref());
+    assert_eq!(["A.IANA-SERVERS.NET", "B.IANA-SERVERS.NET"], verisign.name_servers.as_slice());
+
+    let afnic = parse_domain_record(include_str!("data/afnic.txt"));
+
+    assert_eq!(Some("afnic.fr"), afnic.domain.as_deref());
+    assert_eq!(Some("AFNIC"), afnic.registrar.as_deref());
+    assert_eq!(Some("1995-01-01T00:00:00Z"), afnic.creation_date.as_deref());
+    assert_eq!(["ACTIVE"], afnic.statuses.as_slice());
+    assert!(afnic.name_servers.is_empty());
+}
+
+#[test]
+fn test_lookup_records() {
+    const DOMAIN_BODY: &str = "Domain Name: EXAMPLE.TEST\r\nRegistrar: Example Registrar, \
+                               Inc.\r\nCreation Date: 1995-08-14T04:00:00Z\r\nRegistry Expiry \
+                               Date: 2025-08-13T04:00:00Z\r\nDomain Status: \
+                               clientTransferProhibited\r\nDomain Status: \
+                               clientUpdateProhibited\r\nName Server: NS1.EXAMPLE.TEST\r\n";
+
+    let domain_server = MockServer::respond(DOMAIN_BODY);
+    let ip_server = MockServer::respond(include_str!("data/arin.txt"));
+
+    let mut who = WhoIs::from_ip_server(ip_server.server());
+    who.insert_server("test", domain_server.server());
+
+    let record = who.lookup_domain_record("example.test").unwrap();
+
+    assert_eq!(Some("Example Registrar, Inc."), record.registrar.as_deref());
+    assert_eq!(Some("1995-08-14T04:00:00Z"), record.creation_date.as_deref());
+    assert_eq!(Some("2025-08-13T04:00:00Z"), record.expiration_date.as_deref());
+    assert_eq!(["clientTransferProhibited", "clientUpdateProhibited"], record.statuses.as_slice());
+    assert_eq!(["NS1.EXAMPLE.TEST"], record.name_servers.as_slice());
+    assert_eq!(DOMAIN_BODY, record.body);
+
+    let record = who.lookup_ip_record("8.8.8.8").unwrap();
+
+    assert_eq!(parse_ip_record(include_str!("data/arin.txt")), record);
+    assert_eq!(["8.8.8.8\r\n"], ip_server.queries().as_slice());
+}
+
+#[test]
+fn test_query_placeholder() {
+    let server = MockServer::respond("Domain Name: EXAMPLE.TEST\r\n");
+
+    let mut who = WhoIs::without_ip_server();
+
+    assert_eq!("$addr", who.query_placeholder());
+    assert!(who.set_query_placeholder("").is_err());
+
+    who.set_query_placeholder("{query}").unwrap();
+    who.insert_server(
+        "test",
+        WhoIsServerValue::builder(server.addr.to_string())
+            .unwrap()
+            .query("price $addr {query}\r\n")
+            .build(),
+    );
+
+    who.lookup(WhoIsLookupOptions::from_string("example.test").unwrap()).unwrap();
+
+    // a server without a template still gets the target
+    let mut options = WhoIsLookupOptions::from_string("example.test").unwrap();
+    options.server = Some(server.server());
+
+    who.lookup(options).unwrap();
+
+    assert_eq!(["price $addr example.test\r\n", "example.test\r\n"], server.queries().as_slice());
+
+    assert_eq!(
+        "n + 192.0.2.1\r\n",
+        who.build_query(
+            &Target::parse_str("192.0.2.1").unwrap(),
+            &WhoIsServerValue::builder("whois.arin.net").unwrap().query("n + {query}\r\n").build()
+        )
+    );
+}